@@ -0,0 +1,61 @@
+use axum::http::HeaderMap;
+use log::warn;
+use mongodb::Collection;
+
+use crate::graphql::model::audit_entry::AuditEntry;
+
+/// Trace id of a GraphQL request, passed as request data.
+#[derive(Debug, Clone)]
+pub struct TraceId(pub String);
+
+/// Append-only audit log of invoice mutations and accesses, stored in the `invoice_audit` collection.
+#[derive(Clone)]
+pub struct AuditLog {
+    collection: Collection<AuditEntry>,
+}
+
+impl AuditLog {
+    pub fn new(collection: Collection<AuditEntry>) -> Self {
+        Self { collection }
+    }
+
+    /// Appends an entry to the audit log. Failures are logged, but do not fail the audited action.
+    pub async fn record(&self, entry: AuditEntry) {
+        if let Err(e) = self.collection.insert_one(&entry, None).await {
+            warn!(
+                "Recording audit entry {:?} of invoice {:?} failed: {}",
+                entry.action, entry.invoice_id, e
+            );
+        }
+    }
+
+    /// Appends multiple entries to the audit log. Failures are logged, but do not fail the audited action.
+    pub async fn record_many(&self, entries: Vec<AuditEntry>) {
+        if entries.is_empty() {
+            return;
+        }
+        if let Err(e) = self.collection.insert_many(&entries, None).await {
+            warn!("Recording {} audit entries failed: {}", entries.len(), e);
+        }
+    }
+
+    /// MongoDB collection of audit entries, for queries.
+    pub fn collection(&self) -> &Collection<AuditEntry> {
+        &self.collection
+    }
+}
+
+/// Extracts the trace id of a W3C `traceparent` header, e.g. `00-<trace id>-<span id>-01`.
+pub fn trace_id_from_headers(headers: &HeaderMap) -> Option<String> {
+    let traceparent = headers.get("traceparent")?.to_str().ok()?;
+    trace_id_from_traceparent(traceparent)
+}
+
+/// Extracts the trace id of a W3C `traceparent` value.
+pub fn trace_id_from_traceparent(traceparent: &str) -> Option<String> {
+    traceparent
+        .split('-')
+        .nth(1)
+        .filter(|trace_id| trace_id.len() == 32)
+        .map(str::to_string)
+}
@@ -0,0 +1,77 @@
+use async_graphql::{Context, Error, Result};
+use axum::http::HeaderMap;
+use bson::Uuid;
+use serde::Deserialize;
+
+/// Name of the header in which the gateway passes the authenticated user.
+pub const AUTHORIZED_USER_HEADER: &str = "Authorized-User";
+
+/// Role of an authenticated user.
+#[derive(Debug, Deserialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Buyer,
+    Admin,
+    Employee,
+}
+
+/// Authenticated user as passed by the gateway in the `Authorized-User` header.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuthorizedUser {
+    /// UUID of the user.
+    pub id: Uuid,
+    /// Roles of the user.
+    pub roles: Vec<Role>,
+}
+
+impl AuthorizedUser {
+    /// Parses the authenticated user from the request headers, `None` for anonymous or malformed headers.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = headers.get(AUTHORIZED_USER_HEADER)?.to_str().ok()?;
+        serde_json::from_str(header).ok()
+    }
+
+    /// Whether the user has at least one of the roles.
+    pub fn has_any_role(&self, roles: &[Role]) -> bool {
+        self.roles.iter().any(|role| roles.contains(role))
+    }
+}
+
+/// Returns the authenticated user of a GraphQL request if it has at least one of the roles.
+///
+/// * `ctx` - GraphQL context of the request.
+/// * `roles` - Roles of which the user needs at least one.
+pub fn authorize_roles<'a>(ctx: &Context<'a>, roles: &[Role]) -> Result<&'a AuthorizedUser> {
+    let user = ctx
+        .data_opt::<AuthorizedUser>()
+        .ok_or(Error::new("Authentication is required."))?;
+    match user.has_any_role(roles) {
+        true => Ok(user),
+        false => Err(Error::new(format!(
+            "Permission denied, one of the roles {:?} is required.",
+            roles
+        ))),
+    }
+}
+
+/// Returns the authenticated user of a GraphQL request if it is the user of `user_id` or has at least one of the roles.
+///
+/// * `ctx` - GraphQL context of the request.
+/// * `user_id` - UUID of the user owning the requested data.
+/// * `roles` - Roles which grant access to data of any user.
+pub fn authorize_user_or_roles<'a>(
+    ctx: &Context<'a>,
+    user_id: Uuid,
+    roles: &[Role],
+) -> Result<&'a AuthorizedUser> {
+    let user = ctx
+        .data_opt::<AuthorizedUser>()
+        .ok_or(Error::new("Authentication is required."))?;
+    match user.id == user_id || user.has_any_role(roles) {
+        true => Ok(user),
+        false => Err(Error::new(format!(
+            "Permission denied, only the user itself or one of the roles {:?} may access this data.",
+            roles
+        ))),
+    }
+}
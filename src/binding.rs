@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use async_graphql::{Error, Result};
+use axum::body::Bytes;
+use futures::{stream::BoxStream, stream, StreamExt};
+use reqwest::Response;
+use serde::Serialize;
+
+/// Request to invoke a Dapr output binding.
+#[derive(Debug, Serialize)]
+struct BindingRequest<'a> {
+    operation: &'a str,
+    data: &'a str,
+    metadata: &'a HashMap<String, String>,
+}
+
+/// Invokes a Dapr output binding via the Dapr sidecar and returns the response body.
+///
+/// * `name` - Name of the binding component.
+/// * `operation` - Binding operation, e.g. `create` or `get`.
+/// * `data` - Data passed to the binding.
+/// * `metadata` - Binding specific metadata, e.g. the recipient of an email.
+pub async fn invoke_output_binding(
+    name: &str,
+    operation: &str,
+    data: &str,
+    metadata: &HashMap<String, String>,
+) -> Result<Vec<u8>> {
+    let response = send_binding_request(name, operation, data, metadata).await?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Invokes a Dapr output binding via the Dapr sidecar and streams the response body as it is received.
+///
+/// * `name` - Name of the binding component.
+/// * `operation` - Binding operation, e.g. `get`.
+/// * `data` - Data passed to the binding.
+/// * `metadata` - Binding specific metadata, e.g. the key of a stored file.
+pub async fn stream_output_binding(
+    name: &str,
+    operation: &str,
+    data: &str,
+    metadata: &HashMap<String, String>,
+) -> Result<BoxStream<'static, Result<Bytes>>> {
+    let response = send_binding_request(name, operation, data, metadata).await?;
+    let chunks = stream::unfold(response, |mut response| async move {
+        match response.chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), response)),
+            Ok(None) => None,
+            Err(e) => Some((Err(Error::from(e)), response)),
+        }
+    });
+    Ok(chunks.boxed())
+}
+
+/// Sends a request to a Dapr output binding, failing if the binding does not respond with a success status.
+async fn send_binding_request(
+    name: &str,
+    operation: &str,
+    data: &str,
+    metadata: &HashMap<String, String>,
+) -> Result<Response> {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://localhost:3500/v1.0/bindings/{}", name))
+        .json(&BindingRequest {
+            operation,
+            data,
+            metadata,
+        })
+        .send()
+        .await?;
+    let status = response.status();
+    match status.is_success() {
+        true => Ok(response),
+        false => Err(Error::new(format!(
+            "Binding `{}` failed with status {}: {}",
+            name,
+            status,
+            response.text().await.unwrap_or_default()
+        ))),
+    }
+}
@@ -0,0 +1,192 @@
+use async_graphql::{Error, Result};
+use bson::{DateTime, Uuid};
+use chrono::{NaiveDate, NaiveTime};
+use clap::Subcommand;
+use mongodb::Collection;
+
+use crate::{
+    config::ServiceContext,
+    document::storage::rerender_invoice_documents,
+    event::{
+        http_event_service::publish_event,
+        model::{invoice_created_dto::InvoiceCreatedDTO, invoice_dto::InvoiceDTO},
+    },
+    export::invoices::{query_invoices_issued_between, render_export, ExportFormat},
+    graphql::{
+        model::{
+            audit_entry::{AuditAction, AuditEntry},
+            invoice::Invoice,
+            invoice_chain::verify_invoice_chains,
+        },
+        query::{query_invoices_by_order_id, query_object},
+    },
+    indexes::ensure_indexes,
+    migration::run_pending_migrations,
+};
+
+/// Administrative subcommands which share the configuration of the service.
+#[derive(Subcommand, Debug)]
+pub enum AdminCommand {
+    /// Applies pending schema migrations of stored documents and ensures all indexes.
+    Migrate,
+    /// Creates missing MongoDB indexes.
+    Reindex,
+    /// Renders and stores the documents of an invoice again.
+    Rerender {
+        /// UUID of the invoice.
+        #[arg(long, value_parser = parse_uuid)]
+        invoice: Uuid,
+    },
+    /// Publishes the `invoice/invoice/created` events of all invoices of an order again.
+    Republish {
+        /// UUID of the order.
+        #[arg(long, value_parser = parse_uuid)]
+        order: Uuid,
+    },
+    /// Writes the invoices issued in a date range to stdout.
+    Export {
+        /// First day of the range, e.g. `2025-01-01`.
+        #[arg(long)]
+        from: NaiveDate,
+        /// Day after the range, exclusive.
+        #[arg(long)]
+        to: NaiveDate,
+        /// Format of the export.
+        #[arg(long, value_enum, default_value = "csv")]
+        format: ExportFormat,
+    },
+    /// Walks the hash chains of the invoice numbering sequences and reports any break.
+    VerifyChain {
+        /// Numbering sequence to verify, all sequences if not set.
+        #[arg(long)]
+        sequence: Option<String>,
+    },
+}
+
+/// Parses a UUID command line argument.
+fn parse_uuid(value: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(value).map_err(|e| e.to_string())
+}
+
+/// Executes an administrative subcommand.
+///
+/// * `command` - Subcommand to execute.
+/// * `context` - Configuration and connections shared with the service.
+pub async fn run(command: AdminCommand, context: &ServiceContext) -> Result<()> {
+    let invoice_collection = context.db_client.collection::<Invoice>("invoices");
+    match command {
+        AdminCommand::Migrate => {
+            let applied = run_pending_migrations(&context.db_client).await?;
+            if applied.is_empty() {
+                println!("No migrations are pending.");
+            }
+            for record in applied {
+                println!("Applied migration {} `{}`.", record._id, record.name);
+            }
+            ensure_indexes(&context.db_client).await?;
+        }
+        AdminCommand::Reindex => {
+            for index in ensure_indexes(&context.db_client).await? {
+                println!("Ensured index `{}`.", index);
+            }
+        }
+        AdminCommand::Rerender { invoice } => {
+            let invoice = query_object(&invoice_collection, invoice).await?;
+            rerender_invoice_documents(
+                &invoice_collection,
+                &context.storage_config,
+                &context.audit_log,
+                &invoice,
+            )
+            .await?;
+            println!("Rerendered documents of invoice `{}`.", invoice.invoice_number);
+        }
+        AdminCommand::Republish { order } => {
+            republish_invoices_of_order(&invoice_collection, order).await?;
+        }
+        AdminCommand::Export { from, to, format } => {
+            let invoices =
+                query_invoices_issued_between(&invoice_collection, start_of_day(from), start_of_day(to))
+                    .await?;
+            print!("{}", render_export(&invoices, format));
+        }
+        AdminCommand::VerifyChain { sequence } => {
+            verify_chain(context, &invoice_collection, sequence).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Converts a date to the timestamp of its start in UTC.
+fn start_of_day(date: NaiveDate) -> DateTime {
+    DateTime::from_chrono(date.and_time(NaiveTime::MIN).and_utc())
+}
+
+/// Publishes the `invoice/invoice/created` events of all invoices of an order again.
+///
+/// Invoices issued before the order was recorded on invoices are skipped.
+async fn republish_invoices_of_order(
+    collection: &Collection<Invoice>,
+    order_id: Uuid,
+) -> Result<()> {
+    let invoices = query_invoices_by_order_id(collection, order_id).await?;
+    if invoices.is_empty() {
+        return Err(Error::new(format!(
+            "No invoices of order UUID: `{}` found.",
+            order_id
+        )));
+    }
+    for invoice in invoices {
+        let Some(order) = invoice.order.clone() else {
+            println!(
+                "Skipped invoice `{}`, its order was not recorded.",
+                invoice.invoice_number
+            );
+            continue;
+        };
+        let invoice_number = invoice.invoice_number.clone();
+        let invoice_created_dto = InvoiceCreatedDTO::from((order, InvoiceDTO::from(invoice)));
+        publish_event("invoice/invoice/created", &invoice_created_dto)
+            .await
+            .map_err(|_| Error::new("Publishing invoice created event failed."))?;
+        println!("Republished invoice `{}`.", invoice_number);
+    }
+    Ok(())
+}
+
+/// Verifies the invoice hash chains and prints each break, exits with status `1` if any chain is broken.
+async fn verify_chain(
+    context: &ServiceContext,
+    collection: &Collection<Invoice>,
+    sequence: Option<String>,
+) -> Result<()> {
+    let verifications = verify_invoice_chains(collection, sequence.clone()).await?;
+    let mut valid = true;
+    for verification in verifications {
+        println!(
+            "Sequence `{}`: {} invoices, {}",
+            verification.sequence_id,
+            verification.invoice_count,
+            if verification.valid { "intact" } else { "BROKEN" }
+        );
+        for chain_break in verification.breaks {
+            println!(
+                "  #{} {:?}: {}",
+                chain_break.sequence_number, chain_break.kind, chain_break.message
+            );
+        }
+        valid &= verification.valid;
+    }
+    context
+        .audit_log
+        .record(AuditEntry::new(AuditAction::ChainVerified, None).with_details(format!(
+            "Command line verification of {}: {}.",
+            sequence.map_or("all sequences".to_string(), |s| format!("sequence `{}`", s)),
+            if valid { "intact" } else { "broken" }
+        )))
+        .await;
+    if !valid {
+        std::process::exit(1);
+    }
+    Ok(())
+}
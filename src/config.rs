@@ -0,0 +1,57 @@
+use std::{env, str::FromStr, sync::Arc};
+
+use log::warn;
+use mongodb::Database;
+
+use crate::{
+    audit::AuditLog, dispatch::DispatchConfig, document::storage::StorageConfig,
+    event::bus::InvoiceEventBus,
+    graphql::model::{audit_entry::AuditEntry, payment_terms::PaymentTerms},
+    signature::InvoiceSigner,
+};
+
+/// Parses an optional environment variable, panicking on invalid values.
+pub fn env_var<T: FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().map(|value| match value.parse() {
+        Ok(value) => value,
+        Err(_) => panic!("${} has an invalid value: `{}`.", key, value),
+    })
+}
+
+/// Configuration and connections shared by the service and the administrative subcommands.
+#[derive(Clone)]
+pub struct ServiceContext {
+    /// Database of the invoice service.
+    pub db_client: Database,
+    /// Audit log stored in the `invoice_audit` collection.
+    pub audit_log: AuditLog,
+    /// Configuration of the invoice dispatch.
+    pub dispatch_config: DispatchConfig,
+    /// Configuration of the document storage.
+    pub storage_config: StorageConfig,
+    /// Signer of issued invoices, `None` if no signing key is configured.
+    pub signer: Option<Arc<InvoiceSigner>>,
+    /// Internal bus of invoice changes feeding the GraphQL subscriptions.
+    pub event_bus: InvoiceEventBus,
+    /// Payment terms of invoices to which no payment terms rule applies.
+    pub default_payment_terms: PaymentTerms,
+}
+
+impl ServiceContext {
+    /// Reads the configuration from the environment for a database.
+    pub fn new(db_client: Database) -> Self {
+        let signer = InvoiceSigner::from_env().map(Arc::new);
+        if signer.is_none() {
+            warn!("$INVOICE_SIGNING_KEY_PATH is not set, invoices are issued unsigned.");
+        }
+        Self {
+            audit_log: AuditLog::new(db_client.collection::<AuditEntry>("invoice_audit")),
+            dispatch_config: DispatchConfig::from_env(),
+            storage_config: StorageConfig::from_env(),
+            signer,
+            event_bus: InvoiceEventBus::new(env_var("INVOICE_EVENT_BUS_CAPACITY").unwrap_or(1024)),
+            default_payment_terms: PaymentTerms::from_env(),
+            db_client,
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use async_graphql::{Error, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bson::{doc, DateTime};
+use log::{info, warn};
+use mongodb::Collection;
+
+use crate::{
+    audit::AuditLog,
+    binding::invoke_output_binding,
+    config::env_var,
+    event::model::invoice_resent_dto::InvoiceResentDTO,
+    graphql::model::{
+        audit_entry::{AuditAction, AuditEntry},
+        delivery::{DeliveryAttempt, DeliveryStatus},
+        invoice::{Invoice, PendingEvent},
+    },
+    i18n::Locale,
+    render::{html::render_invoice_html, pdf::render_invoice_pdf},
+};
+
+/// Configuration of the invoice dispatch.
+#[derive(Debug, Clone)]
+pub struct DispatchConfig {
+    /// Name of the Dapr output binding used to send emails.
+    pub binding: String,
+    /// Sender address of the emails, if not configured in the binding.
+    pub sender: Option<String>,
+    /// Maximum number of delivery attempts per invoice.
+    pub max_attempts: u32,
+    /// Delay in seconds before the first retry, doubled with each further attempt.
+    pub retry_delay_seconds: u64,
+    /// Seconds after issuance after which a pending delivery is considered interrupted and retried.
+    pub pending_timeout_seconds: u64,
+}
+
+impl DispatchConfig {
+    /// Reads the configuration from `$INVOICE_DISPATCH_BINDING`, `$INVOICE_DISPATCH_SENDER`, `$INVOICE_DISPATCH_MAX_ATTEMPTS`, `$INVOICE_DISPATCH_RETRY_DELAY_SECONDS`
+    /// and `$INVOICE_DISPATCH_PENDING_TIMEOUT_SECONDS`.
+    pub fn from_env() -> Self {
+        Self {
+            binding: env_var("INVOICE_DISPATCH_BINDING").unwrap_or("invoice-email".to_string()),
+            sender: env_var("INVOICE_DISPATCH_SENDER"),
+            max_attempts: env_var("INVOICE_DISPATCH_MAX_ATTEMPTS").unwrap_or(5),
+            retry_delay_seconds: env_var("INVOICE_DISPATCH_RETRY_DELAY_SECONDS").unwrap_or(60),
+            pending_timeout_seconds: env_var("INVOICE_DISPATCH_PENDING_TIMEOUT_SECONDS")
+                .unwrap_or(600),
+        }
+    }
+}
+
+/// Sends an invoice to the customer through the configured output binding and records the attempt on the invoice.
+///
+/// The email is passed to the binding as MIME message, with the HTML rendering of the invoice as body and the PDF rendering as attachment.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `config` - Configuration of the invoice dispatch.
+/// * `audit_log` - Audit log to record the attempt in.
+/// * `invoice` - Invoice to send.
+/// * `resent` - Whether the invoice is sent again on request, which records an `invoice/invoice/resent` event with the attempt.
+pub async fn dispatch_invoice(
+    collection: &Collection<Invoice>,
+    config: &DispatchConfig,
+    audit_log: &AuditLog,
+    invoice: &Invoice,
+    resent: bool,
+) -> Result<DeliveryStatus> {
+    let result = send_invoice(config, invoice).await;
+    let attempt = DeliveryAttempt {
+        attempted_at: DateTime::now(),
+        success: result.is_ok(),
+        error: result.as_ref().err().map(|e| e.message.clone()),
+    };
+    let status = match result {
+        Ok(()) => {
+            info!("Invoice of UUID: `{}` was sent.", invoice._id);
+            DeliveryStatus::Delivered
+        }
+        Err(e) => {
+            warn!("Sending invoice of UUID: `{}` failed: {}", invoice._id, e.message);
+            DeliveryStatus::Failed
+        }
+    };
+    let mut push = doc! {"delivery.attempts": bson::to_bson(&attempt)?};
+    if resent {
+        let event = PendingEvent::new(
+            "invoice/invoice/resent",
+            &InvoiceResentDTO::from((invoice, status)),
+        )?;
+        push.insert("pending_events", bson::to_bson(&event)?);
+    }
+    collection
+        .update_one(
+            doc! {"_id": invoice._id},
+            doc! {
+                "$set": {"delivery.status": bson::to_bson(&status)?},
+                "$push": push,
+            },
+            None,
+        )
+        .await?;
+    let mut entry = AuditEntry::new(AuditAction::DeliveryAttempted, Some(invoice._id));
+    entry = match attempt.error {
+        Some(error) => entry.with_details(format!("{:?}: {}", status, error)),
+        None => entry.with_details(format!("{:?}", status)),
+    };
+    audit_log.record(entry).await;
+    Ok(status)
+}
+
+/// Renders an invoice as email and hands it over to the output binding.
+async fn send_invoice(config: &DispatchConfig, invoice: &Invoice) -> Result<()> {
+    let recipient = invoice
+        .delivery
+        .recipient
+        .clone()
+        .ok_or(Error::new("Customer has no email address."))?;
+    let locale = Locale::from_tag(&invoice.locale);
+    let subject = locale
+        .message("invoice.email_subject")
+        .replace("{number}", &invoice.invoice_number);
+    let email = InvoiceEmail {
+        sender: config.sender.as_deref(),
+        recipient: &recipient,
+        subject: &subject,
+        html: &render_invoice_html(invoice),
+        attachment_name: &format!("{}.pdf", invoice.invoice_number),
+        attachment: &render_invoice_pdf(invoice),
+        boundary: &format!("invoice-{}", invoice._id),
+    };
+    let mut metadata = HashMap::from([
+        ("emailTo".to_string(), recipient.clone()),
+        ("subject".to_string(), subject.clone()),
+        ("contentType".to_string(), "message/rfc822".to_string()),
+        ("fileName".to_string(), format!("{}.eml", invoice._id)),
+    ]);
+    if let Some(sender) = &config.sender {
+        metadata.insert("emailFrom".to_string(), sender.clone());
+    }
+    invoke_output_binding(&config.binding, "create", &email.to_mime(), &metadata).await?;
+    Ok(())
+}
+
+/// Email of an invoice with its PDF document attached.
+struct InvoiceEmail<'a> {
+    /// Sender address, if not set by the binding.
+    sender: Option<&'a str>,
+    /// Recipient address.
+    recipient: &'a str,
+    /// Subject, in the customer's locale.
+    subject: &'a str,
+    /// HTML body.
+    html: &'a str,
+    /// File name of the attachment.
+    attachment_name: &'a str,
+    /// PDF document to attach.
+    attachment: &'a [u8],
+    /// Boundary separating the parts, must not occur in their base64 encoded content.
+    boundary: &'a str,
+}
+
+impl InvoiceEmail<'_> {
+    /// Formats the email as `multipart/mixed` MIME message with CRLF line endings.
+    ///
+    /// Body and attachment are base64 encoded, the subject as RFC 2047 encoded word, so non-ASCII text is transferred unchanged.
+    fn to_mime(&self) -> String {
+        let mut headers = vec![];
+        if let Some(sender) = self.sender {
+            headers.push(format!("From: {}", sender));
+        }
+        headers.push(format!("To: {}", self.recipient));
+        headers.push(format!(
+            "Subject: =?UTF-8?B?{}?=",
+            STANDARD.encode(self.subject)
+        ));
+        headers.push("MIME-Version: 1.0".to_string());
+        headers.push(format!(
+            "Content-Type: multipart/mixed; boundary=\"{}\"",
+            self.boundary
+        ));
+        format!(
+            "{headers}\r\n\r\n\
+             --{boundary}\r\n\
+             Content-Type: text/html; charset=utf-8\r\n\
+             Content-Transfer-Encoding: base64\r\n\r\n\
+             {html}\r\n\
+             --{boundary}\r\n\
+             Content-Type: application/pdf; name=\"{name}\"\r\n\
+             Content-Disposition: attachment; filename=\"{name}\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\r\n\
+             {pdf}\r\n\
+             --{boundary}--\r\n",
+            headers = headers.join("\r\n"),
+            boundary = self.boundary,
+            html = base64_lines(self.html.as_bytes()),
+            name = self.attachment_name,
+            pdf = base64_lines(self.attachment),
+        )
+    }
+}
+
+/// Encodes data as base64 in lines of at most 76 characters, as required by MIME.
+fn base64_lines(data: &[u8]) -> String {
+    let encoded = STANDARD.encode(data);
+    encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect::<Vec<String>>()
+        .join("\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email<'a>(html: &'a str, attachment: &'a [u8]) -> InvoiceEmail<'a> {
+        InvoiceEmail {
+            sender: Some("billing@example.com"),
+            recipient: "customer@example.com",
+            subject: "Ihre Rechnung RE-1 für März",
+            html,
+            attachment_name: "RE-1.pdf",
+            attachment,
+            boundary: "invoice-boundary",
+        }
+    }
+
+    /// Returns the decoded content of the part of a MIME message with the given content type.
+    fn part(mime: &str, content_type: &str) -> Vec<u8> {
+        let part = mime
+            .split("--invoice-boundary")
+            .find(|part| part.contains(&format!("Content-Type: {}", content_type)))
+            .unwrap();
+        let (_, content) = part.split_once("\r\n\r\n").unwrap();
+        STANDARD.decode(content.replace("\r\n", "")).unwrap()
+    }
+
+    #[test]
+    fn attaches_pdf_to_html_body() {
+        let pdf = vec![0x25, 0x50, 0x44, 0x46, 0xff, 0x00];
+        let mime = email("<p>Größe</p>", &pdf).to_mime();
+        assert!(mime.contains("Content-Type: multipart/mixed; boundary=\"invoice-boundary\"\r\n"));
+        assert!(mime.contains("Content-Disposition: attachment; filename=\"RE-1.pdf\"\r\n"));
+        assert_eq!(part(&mime, "text/html"), "<p>Größe</p>".as_bytes());
+        assert_eq!(part(&mime, "application/pdf"), pdf);
+        assert!(mime.ends_with("--invoice-boundary--\r\n"));
+    }
+
+    #[test]
+    fn encodes_headers_and_wraps_long_lines() {
+        let html = "x".repeat(1000);
+        let mime = email(&html, &[]).to_mime();
+        let (headers, _) = mime.split_once("\r\n\r\n").unwrap();
+        assert!(headers.starts_with("From: billing@example.com\r\nTo: customer@example.com\r\n"));
+        let subject = STANDARD.encode("Ihre Rechnung RE-1 für März");
+        assert!(headers.contains(&format!("Subject: =?UTF-8?B?{}?=", subject)));
+        assert!(mime.split("\r\n").all(|line| line.len() <= 76 || line.starts_with("Subject")));
+        assert!(!mime.replace("\r\n", "").contains('\n'));
+    }
+}
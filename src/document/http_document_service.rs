@@ -0,0 +1,214 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use bson::{doc, Uuid};
+use futures::StreamExt;
+use log::warn;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use super::storage::{find_stored_document, store_document, stream_document, StorageConfig};
+use crate::{
+    audit::{trace_id_from_headers, AuditLog},
+    authorization::{AuthorizedUser, Role},
+    graphql::{
+        model::{
+            audit_entry::{AuditAction, AuditEntry},
+            invoice::Invoice,
+            invoice_chain::invoice_hash,
+            stored_document::DocumentFormat,
+        },
+        query::query_object,
+    },
+    signature::InvoiceSigner,
+};
+
+/// Service state containing database connections and storage configuration.
+#[derive(Clone)]
+pub struct HttpDocumentServiceState {
+    pub invoice_collection: Collection<Invoice>,
+    pub storage_config: StorageConfig,
+    pub signer: Option<Arc<InvoiceSigner>>,
+    pub audit_log: AuditLog,
+}
+
+/// Hash and signature of an invoice document as received by a partner.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureVerificationRequest {
+    /// Invoice hash from the document.
+    pub hash: String,
+    /// Base64 encoded signature from the document.
+    pub signature: String,
+    /// Key id from the document.
+    pub key_id: String,
+}
+
+/// Result of the verification of an invoice signature.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureVerificationResponse {
+    /// Whether the signature is valid and belongs to an unaltered issued invoice.
+    pub valid: bool,
+    /// Description of the result.
+    pub message: String,
+    /// UUID of the signed invoice.
+    pub invoice_id: Option<Uuid>,
+    /// Number of the signed invoice.
+    pub invoice_number: Option<String>,
+    /// Timestamp when the signed invoice was issued.
+    pub issued_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl SignatureVerificationResponse {
+    fn invalid(message: &str) -> Json<Self> {
+        Json(Self {
+            valid: false,
+            message: message.to_string(),
+            invoice_id: None,
+            invoice_number: None,
+            issued_at: None,
+        })
+    }
+}
+
+/// Public HTTP endpoint to verify the signature of an invoice document received by a partner.
+///
+/// * `state` - Service state containing database connections and the invoice signer.
+/// * `request` - Hash, signature and key id from the document.
+pub async fn verify_invoice_signature(
+    State(state): State<HttpDocumentServiceState>,
+    Json(request): Json<SignatureVerificationRequest>,
+) -> Result<Json<SignatureVerificationResponse>, StatusCode> {
+    let signer = state.signer.ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    if !signer.knows_key(&request.key_id) {
+        return Ok(SignatureVerificationResponse::invalid("Unknown signing key."));
+    }
+    if !signer.verify(&request.key_id, &request.hash, &request.signature) {
+        return Ok(SignatureVerificationResponse::invalid(
+            "Signature does not match the hash.",
+        ));
+    }
+    let invoice = state
+        .invoice_collection
+        .find_one(doc! {"hash": &request.hash}, None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some(invoice) = invoice else {
+        return Ok(SignatureVerificationResponse::invalid(
+            "No invoice with this hash was issued.",
+        ));
+    };
+    if invoice_hash(&invoice) != request.hash {
+        return Ok(SignatureVerificationResponse::invalid(
+            "Invoice was altered after issuance.",
+        ));
+    }
+    Ok(Json(SignatureVerificationResponse {
+        valid: true,
+        message: "Signature is valid.".to_string(),
+        invoice_id: Some(invoice._id),
+        invoice_number: Some(invoice.invoice_number),
+        issued_at: Some(invoice.issued_at.to_chrono()),
+    }))
+}
+
+/// Public HTTP endpoint to retrieve the PEM encoded public key of a current or previous signing key id.
+///
+/// * `state` - Service state containing the invoice signer.
+/// * `key_id` - Key id from an invoice document.
+pub async fn get_signing_public_key(
+    State(state): State<HttpDocumentServiceState>,
+    Path(key_id): Path<String>,
+) -> Result<Response, StatusCode> {
+    match state.signer.and_then(|signer| signer.verifying_key_pem(&key_id)) {
+        Some(pem) => Ok(([(header::CONTENT_TYPE, "application/x-pem-file")], pem).into_response()),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// HTTP endpoint to download a generated invoice document, e.g. `/invoices/{id}/documents/pdf`.
+///
+/// Streams the stored document from the document storage, or renders and stores it first if it was not stored yet.
+/// Only the invoiced user, admins and employees may download documents of an invoice.
+///
+/// * `state` - Service state containing database connections and storage configuration.
+/// * `path` - Invoice UUID and document format extension.
+/// * `headers` - Request headers containing the authenticated user and trace context.
+pub async fn download_invoice_document(
+    State(state): State<HttpDocumentServiceState>,
+    Path((id, extension)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let authorized_user = AuthorizedUser::from_headers(&headers).ok_or(StatusCode::UNAUTHORIZED)?;
+    let id = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let format = DocumentFormat::from_extension(&extension).ok_or(StatusCode::NOT_FOUND)?;
+    let invoice = query_object(&state.invoice_collection, id)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    if authorized_user.id != invoice.user_id
+        && !authorized_user.has_any_role(&[Role::Admin, Role::Employee])
+    {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let (body, content_length) = match find_stored_document(&invoice, format) {
+        Some(document) => stream_document(&state.storage_config, document)
+            .await
+            .map(|chunks| {
+                let chunks = chunks.map(move |chunk| {
+                    chunk.map_err(|e| {
+                        warn!(
+                            "Streaming {:?} document of invoice of UUID: `{}` failed: {}",
+                            format, id, e.message
+                        );
+                        std::io::Error::other(e.message)
+                    })
+                });
+                (Body::from_stream(chunks), document.size)
+            }),
+        None => store_document(
+            &state.invoice_collection,
+            &state.storage_config,
+            &state.audit_log,
+            &invoice,
+            format,
+        )
+        .await
+        .map(|(document, content)| (Body::from(content), document.size)),
+    }
+    .map_err(|e| {
+        warn!(
+            "Loading {:?} document of invoice of UUID: `{}` failed: {}",
+            format, id, e.message
+        );
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    state
+        .audit_log
+        .record(
+            AuditEntry::new(AuditAction::DocumentDownloaded, Some(invoice._id))
+                .by_user(Some(&authorized_user))
+                .with_trace_id(trace_id_from_headers(&headers))
+                .with_details(format!("{:?}", format)),
+        )
+        .await;
+    let file_name = format!("{}.{}", invoice.invoice_number, format.extension());
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+            (header::CONTENT_LENGTH, content_length.to_string()),
+        ],
+        body,
+    )
+        .into_response())
+}
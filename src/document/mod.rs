@@ -0,0 +1,2 @@
+pub mod http_document_service;
+pub mod storage;
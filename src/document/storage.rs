@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use async_graphql::{Error, Result};
+use axum::body::Bytes;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bson::{doc, DateTime};
+use futures::{stream, stream::BoxStream, StreamExt};
+use mongodb::Collection;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    audit::AuditLog,
+    binding::{invoke_output_binding, stream_output_binding},
+    config::env_var,
+    graphql::model::{
+        audit_entry::{AuditAction, AuditEntry},
+        invoice::Invoice,
+        stored_document::{DocumentFormat, StoredDocument},
+    },
+};
+
+/// Configuration of the document storage.
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    /// Name of the Dapr blob storage output binding.
+    pub binding: String,
+}
+
+impl StorageConfig {
+    /// Reads the configuration from `$DOCUMENT_STORAGE_BINDING`.
+    pub fn from_env() -> Self {
+        Self {
+            binding: env_var("DOCUMENT_STORAGE_BINDING")
+                .unwrap_or("invoice-documents".to_string()),
+        }
+    }
+}
+
+/// Renders and stores all documents of an invoice which are not yet stored.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `config` - Configuration of the document storage.
+/// * `audit_log` - Audit log to record rendered documents in.
+/// * `invoice` - Invoice to store the documents of.
+pub async fn store_invoice_documents(
+    collection: &Collection<Invoice>,
+    config: &StorageConfig,
+    audit_log: &AuditLog,
+    invoice: &Invoice,
+) -> Result<()> {
+    for format in DocumentFormat::ALL {
+        if find_stored_document(invoice, format).is_none() {
+            store_document(collection, config, audit_log, invoice, format).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Renders all documents of an invoice again and replaces the stored documents.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `config` - Configuration of the document storage.
+/// * `audit_log` - Audit log to record rendered documents in.
+/// * `invoice` - Invoice to render the documents of.
+pub async fn rerender_invoice_documents(
+    collection: &Collection<Invoice>,
+    config: &StorageConfig,
+    audit_log: &AuditLog,
+    invoice: &Invoice,
+) -> Result<()> {
+    collection
+        .update_one(
+            doc! {"_id": invoice._id},
+            doc! {"$set": {"documents": []}},
+            None,
+        )
+        .await?;
+    for format in DocumentFormat::ALL {
+        store_document(collection, config, audit_log, invoice, format).await?;
+    }
+    Ok(())
+}
+
+/// Renders a document of an invoice, stores it and records it on the invoice.
+///
+/// Returns the stored document together with its content.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `config` - Configuration of the document storage.
+/// * `audit_log` - Audit log to record the rendered document in.
+/// * `invoice` - Invoice to render.
+/// * `format` - Format of the document.
+pub async fn store_document(
+    collection: &Collection<Invoice>,
+    config: &StorageConfig,
+    audit_log: &AuditLog,
+    invoice: &Invoice,
+    format: DocumentFormat,
+) -> Result<(StoredDocument, Vec<u8>)> {
+    let content = format.render(invoice);
+    let document = StoredDocument {
+        format,
+        storage_key: format!(
+            "invoices/{}/{}.{}",
+            invoice._id,
+            invoice.invoice_number,
+            format.extension()
+        ),
+        content_hash: content_hash(&content),
+        size: content.len() as u64,
+        stored_at: DateTime::now(),
+    };
+    let metadata = HashMap::from([("fileName".to_string(), document.storage_key.clone())]);
+    invoke_output_binding(
+        &config.binding,
+        "create",
+        &STANDARD.encode(&content),
+        &metadata,
+    )
+    .await?;
+    collection
+        .update_one(
+            doc! {"_id": invoice._id, "documents.format": {"$ne": bson::to_bson(&format)?}},
+            doc! {"$push": {"documents": bson::to_bson(&document)?}},
+            None,
+        )
+        .await?;
+    audit_log
+        .record(
+            AuditEntry::new(AuditAction::DocumentRendered, Some(invoice._id)).with_details(
+                format!("{:?} stored as `{}`.", format, document.storage_key),
+            ),
+        )
+        .await;
+    Ok((document, content))
+}
+
+/// Streams a stored document from the document storage, decoding it as it is received.
+///
+/// The content is verified against the recorded size and hash while it is streamed.
+/// If it does not match, the stream ends with an error instead of its last chunk, so an altered document is never received completely.
+///
+/// * `config` - Configuration of the document storage.
+/// * `document` - Stored document to stream.
+pub async fn stream_document(
+    config: &StorageConfig,
+    document: &StoredDocument,
+) -> Result<BoxStream<'static, Result<Bytes>>> {
+    let metadata = HashMap::from([("fileName".to_string(), document.storage_key.clone())]);
+    let encoded = stream_output_binding(&config.binding, "get", "", &metadata).await?;
+    let decoder = DocumentDecoder::new(document.clone());
+    let chunks = stream::unfold(
+        (encoded, Some(decoder)),
+        |(mut encoded, decoder)| async move {
+            let mut decoder = decoder?;
+            let chunk = match encoded.next().await {
+                Some(Ok(chunk)) => decoder.decode(&chunk),
+                Some(Err(e)) => Err(e),
+                None => decoder.finish(),
+            };
+            match chunk {
+                Ok(chunk) if chunk.is_empty() && decoder.finished => None,
+                Ok(chunk) => Some((Ok(Bytes::from(chunk)), (encoded, Some(decoder)))),
+                Err(e) => Some((Err(e), (encoded, None))),
+            }
+        },
+    );
+    Ok(chunks.boxed())
+}
+
+/// Decodes a base64 encoded stored document chunk by chunk and verifies it against its recorded size and hash.
+struct DocumentDecoder {
+    /// Stored document which is decoded.
+    document: StoredDocument,
+    /// Received base64 characters which do not form a complete group of four yet.
+    pending: Vec<u8>,
+    /// Hash of the content decoded so far.
+    hasher: Sha256,
+    /// Size of the content decoded so far.
+    size: u64,
+    /// Whether the whole content was received.
+    finished: bool,
+}
+
+impl DocumentDecoder {
+    fn new(document: StoredDocument) -> Self {
+        Self {
+            document,
+            pending: vec![],
+            hasher: Sha256::new(),
+            size: 0,
+            finished: false,
+        }
+    }
+
+    /// Decodes the complete base64 groups received so far, keeping an incomplete group for the next chunk.
+    fn decode(&mut self, chunk: &[u8]) -> Result<Vec<u8>> {
+        self.pending
+            .extend(chunk.iter().filter(|byte| !byte.is_ascii_whitespace()));
+        let complete = self.pending.len() - self.pending.len() % 4;
+        let content = STANDARD
+            .decode(&self.pending[..complete])
+            .map_err(|e| Error::new(format!("Stored document is not base64 encoded: {}", e)))?;
+        self.pending.drain(..complete);
+        self.hasher.update(&content);
+        self.size += content.len() as u64;
+        Ok(content)
+    }
+
+    /// Completes decoding, failing if the content does not match the recorded size and hash.
+    fn finish(&mut self) -> Result<Vec<u8>> {
+        self.finished = true;
+        let hash = hex_digest(self.hasher.clone());
+        match self.pending.is_empty()
+            && self.size == self.document.size
+            && hash == self.document.content_hash
+        {
+            true => Ok(vec![]),
+            false => Err(Error::new(format!(
+                "Stored document `{}` does not match its recorded content hash.",
+                self.document.storage_key
+            ))),
+        }
+    }
+}
+
+/// Deletes a stored document from the document storage.
+///
+/// * `config` - Configuration of the document storage.
+/// * `document` - Stored document to delete.
+pub async fn delete_document(config: &StorageConfig, document: &StoredDocument) -> Result<()> {
+    let metadata = HashMap::from([("fileName".to_string(), document.storage_key.clone())]);
+    invoke_output_binding(&config.binding, "delete", "", &metadata).await?;
+    Ok(())
+}
+
+/// Returns the stored document of an invoice in a format, if any.
+pub fn find_stored_document(invoice: &Invoice, format: DocumentFormat) -> Option<&StoredDocument> {
+    invoice
+        .documents
+        .iter()
+        .find(|document| document.format == format)
+}
+
+/// Computes the hex encoded SHA-256 hash of a document content.
+pub fn content_hash(content: &[u8]) -> String {
+    hex_digest(Sha256::new_with_prefix(content))
+}
+
+/// Hex encodes the SHA-256 hash of the data passed to a hasher.
+fn hex_digest(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored_document(content: &[u8]) -> StoredDocument {
+        StoredDocument {
+            format: DocumentFormat::Pdf,
+            storage_key: "invoices/1/RE-1.pdf".to_string(),
+            content_hash: content_hash(content),
+            size: content.len() as u64,
+            stored_at: DateTime::now(),
+        }
+    }
+
+    /// Decodes the encoded content split into chunks of the given size.
+    fn decode_in_chunks(document: StoredDocument, encoded: &str, size: usize) -> Result<Vec<u8>> {
+        let mut decoder = DocumentDecoder::new(document);
+        let mut content = vec![];
+        for chunk in encoded.as_bytes().chunks(size) {
+            content.extend(decoder.decode(chunk)?);
+        }
+        content.extend(decoder.finish()?);
+        Ok(content)
+    }
+
+    #[test]
+    fn decodes_chunks_split_within_base64_groups() {
+        let content: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let encoded = format!("{}\n", STANDARD.encode(&content));
+        for size in [1, 3, 5, 64, 4096] {
+            let decoded = decode_in_chunks(stored_document(&content), &encoded, size).unwrap();
+            assert_eq!(decoded, content);
+        }
+    }
+
+    #[test]
+    fn rejects_altered_content_at_the_end() {
+        let content = b"%PDF-1.4 invoice".to_vec();
+        let mut altered = content.clone();
+        altered[0] = b'#';
+        let encoded = STANDARD.encode(&altered);
+        let mut decoder = DocumentDecoder::new(stored_document(&content));
+        assert_eq!(decoder.decode(encoded.as_bytes()).unwrap(), altered);
+        assert!(decoder.finish().is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_content() {
+        let content = b"%PDF-1.4 invoice".to_vec();
+        let encoded = STANDARD.encode(&content[..8]);
+        assert!(decode_in_chunks(stored_document(&content), &encoded, 4).is_err());
+        let encoded = STANDARD.encode(&content);
+        let truncated = &encoded[..encoded.len() - 2];
+        assert!(decode_in_chunks(stored_document(&content), truncated, 4).is_err());
+    }
+}
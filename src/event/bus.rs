@@ -0,0 +1,52 @@
+use futures::{stream, Stream};
+use log::warn;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::graphql::model::invoice::Invoice;
+
+/// Invoice change published on the internal event bus.
+#[derive(Debug, Clone)]
+pub enum InvoiceBusEvent {
+    /// An invoice, corrective invoice or credit note was issued.
+    Created(Invoice),
+    /// The payment status of an invoice changed, carries the updated invoice.
+    StatusChanged(Invoice),
+}
+
+/// In-process broadcast bus of invoice changes which feeds the GraphQL subscriptions.
+///
+/// Events are only delivered to subscribers connected to this instance, other services are notified via Dapr.
+#[derive(Debug, Clone)]
+pub struct InvoiceEventBus {
+    sender: broadcast::Sender<InvoiceBusEvent>,
+}
+
+impl InvoiceEventBus {
+    /// Creates a bus buffering up to `capacity` events per subscriber.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Publishes an event to all current subscribers, events without subscribers are dropped.
+    pub fn publish(&self, event: InvoiceBusEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Returns a stream of all events published after subscribing.
+    ///
+    /// Subscribers which fall behind by more than the capacity skip the missed events.
+    pub fn subscribe(&self) -> impl Stream<Item = InvoiceBusEvent> + Send + 'static {
+        stream::unfold(self.sender.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Invoice event subscriber lagged behind, skipped {} events.", skipped)
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
@@ -1,16 +1,43 @@
-use async_graphql::Result;
+use std::sync::Arc;
+
+use async_graphql::{Error, Result};
 use axum::{debug_handler, extract::State, http::StatusCode, Json};
-use bson::{doc, Uuid};
-use log::info;
-use mongodb::{options::UpdateOptions, Collection};
+use bson::{doc, DateTime, Document, Uuid};
+use log::{info, warn};
+use mongodb::{
+    options::{ReplaceOptions, UpdateOptions},
+    Collection,
+};
 use serde::{Deserialize, Serialize};
 
-use super::model::{invoice_created_dto::InvoiceCreatedDTO, invoice_dto::InvoiceDTO};
+use super::bus::{InvoiceBusEvent, InvoiceEventBus};
+use super::model::{
+    collective_invoice_created_dto::CollectiveInvoiceCreatedDTO,
+    invoice_created_dto::InvoiceCreatedDTO, invoice_document_issued_dto::InvoiceDocumentIssuedDTO,
+    invoice_dto::InvoiceDTO, subscription_invoice_created_dto::SubscriptionInvoiceCreatedDTO,
+};
+use crate::audit::{trace_id_from_traceparent, AuditLog};
+use crate::config::{env_var, ServiceContext};
+use crate::dispatch::{dispatch_invoice, DispatchConfig};
+use crate::document::storage::{store_invoice_documents, StorageConfig};
+use crate::signature::InvoiceSigner;
 use crate::graphql::model::{
-    foreign_types::{User, UserAddress, VendorAddress},
-    invoice::Invoice,
+    billing::{query_billing_mode, BillingMode},
+    foreign_types::{
+        Discount, ProductCharacteristic, ProductVariant, ProductVariantVersion, ShipmentMethod,
+        User, UserAddress, VendorAddress,
+    },
+    invoice::{Invoice, InvoiceDocumentType, IssuedEvent, PendingEvent},
+    invoice_chain::InvoiceNumberSequence,
+    invoice_line_item::order_shipping_line_items,
+    audit_entry::{AuditAction, AuditEntry},
+    money::Money,
     order::{OrderStatus, RejectionReason},
+    order_invoicing::{mark_order_items_invoiced, record_order_invoicing, OrderInvoicing},
+    payment_terms::{PaymentTerms, PaymentTermsRule},
+    recurring::{BillingInterval, BillingPlan, CustomerSubscription, SubscriptionStatus},
 };
+use crate::job::recurring::change_subscription_plan;
 
 /// Data to send to Dapr in order to describe a subscription.
 #[derive(Serialize)]
@@ -21,16 +48,37 @@ pub struct Pubsub {
     pub route: String,
 }
 
+/// Status of a received event, according to Dapr specs.
+#[derive(Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TopicEventStatus {
+    /// Event was processed.
+    Success,
+    /// Event can never be processed, Dapr drops it instead of redelivering it.
+    Drop,
+}
+
 /// Reponse data to send to Dapr when receiving an event.
 #[derive(Serialize)]
 pub struct TopicEventResponse {
-    pub status: u8,
+    pub status: TopicEventStatus,
 }
 
-/// Default status is `0` -> Ok, according to Dapr specs.
+/// Default status is `SUCCESS`, according to Dapr specs.
 impl Default for TopicEventResponse {
     fn default() -> Self {
-        Self { status: 0 }
+        Self {
+            status: TopicEventStatus::Success,
+        }
+    }
+}
+
+impl TopicEventResponse {
+    /// Response dropping an event which can never be processed, e.g. an order with items in different currencies.
+    pub fn dropped() -> Self {
+        Self {
+            status: TopicEventStatus::Drop,
+        }
     }
 }
 
@@ -39,6 +87,29 @@ impl Default for TopicEventResponse {
 pub struct Event<T> {
     pub topic: String,
     pub data: T,
+    /// W3C trace context of the event.
+    #[serde(default)]
+    pub traceparent: Option<String>,
+    /// Id of the event, the same for each redelivery.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Timestamp when the event occurred.
+    #[serde(default)]
+    pub time: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl<T> Event<T> {
+    /// Trace id of the event, if traced.
+    pub fn trace_id(&self) -> Option<String> {
+        self.traceparent
+            .as_deref()
+            .and_then(trace_id_from_traceparent)
+    }
+
+    /// Timestamp when the event occurred, the current time for events without timestamp.
+    pub fn occurred_at(&self) -> DateTime {
+        self.time.map(DateTime::from_chrono).unwrap_or_else(DateTime::now)
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -58,6 +129,74 @@ pub struct VendorAddressEventData {
     pub country: String,
     /// Name of vendor.
     pub company_name: String,
+    /// UUID of vendor the address belongs to, `None` for the shop's own vendor address.
+    #[serde(default)]
+    pub vendor_id: Option<Uuid>,
+    /// VAT identification number of vendor.
+    #[serde(default)]
+    pub vat_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Relevant part of product variant creation event.
+pub struct ProductVariantEventData {
+    /// Product variant UUID.
+    pub id: Uuid,
+    /// UUID of vendor selling the product variant, `None` if sold by the shop itself.
+    #[serde(default)]
+    pub vendor_id: Option<Uuid>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Relevant part of product variant version creation event.
+pub struct ProductVariantVersionEventData {
+    /// Product variant version UUID.
+    pub id: Uuid,
+    /// UUID of the product variant the version belongs to.
+    pub product_variant_id: Uuid,
+    /// Name of the product variant in this version.
+    pub name: String,
+    /// Stock keeping unit of the product variant.
+    #[serde(default)]
+    pub sku: Option<String>,
+    /// Description of the product variant in this version.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Characteristics of the product variant in this version.
+    #[serde(default)]
+    pub characteristics: Vec<ProductCharacteristic>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Relevant part of discount creation event.
+pub struct DiscountEventData {
+    /// Discount UUID.
+    pub id: Uuid,
+    /// Fraction of the price which is discounted, e.g. `0.1` for 10%.
+    pub discount: f64,
+    /// Description of the discount.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Relevant part of shipment method creation and update events.
+pub struct ShipmentMethodEventData {
+    /// Shipment method UUID.
+    pub id: Uuid,
+    /// Name of the shipment method.
+    pub name: String,
+    /// Fee charged once per order shipping items with the method.
+    pub base_fee: Money,
+    /// Fee charged per shipped item.
+    pub fee_per_item: Money,
+    /// VAT rate included in the fees, `None` if not set.
+    #[serde(default)]
+    pub tax_rate: Option<f64>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -69,6 +208,22 @@ pub struct UserEventData {
     pub first_name: String,
     /// Last name of user.
     pub last_name: String,
+    /// Preferred locale of user as BCP 47 language tag.
+    #[serde(default)]
+    pub preferred_locale: Option<String>,
+    /// Customer group of user.
+    #[serde(default)]
+    pub customer_group: Option<String>,
+    /// Email address of user.
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+/// Relevant part of user deletion event data.
+pub struct UserDeletedEventData {
+    /// User UUID.
+    pub id: Uuid,
 }
 
 #[derive(Deserialize, Debug)]
@@ -133,7 +288,7 @@ pub struct OrderEventData {
     /// UUID of address of invoice.
     pub invoice_address_id: Uuid,
     /// Total compensatable amount of order.
-    pub compensatable_order_amount: u64,
+    pub compensatable_order_amount: Money,
     /// UUID of payment information that the order should be processed with.
     pub payment_information_id: Uuid,
     /// Optional payment authorization information.
@@ -142,6 +297,24 @@ pub struct OrderEventData {
     pub vat_number: Option<String>,
 }
 
+impl OrderEventData {
+    /// Returns the currency of the order, rejecting orders with items in a different currency than the order total.
+    pub fn currency(&self) -> Result<String> {
+        let currency = &self.compensatable_order_amount.currency;
+        match self
+            .order_items
+            .iter()
+            .find(|item| item.compensatable_amount.currency != *currency)
+        {
+            Some(item) => Err(Error::new(format!(
+                "Order item of UUID: `{}` is in currency `{}`, but order is in currency `{}`.",
+                item.id, item.compensatable_amount.currency, currency
+            ))),
+            None => Ok(currency.clone()),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 /// Relevant part of order item event data in order event data.
@@ -161,15 +334,69 @@ pub struct OrderItemEventData {
     /// Specifies the quantity of the order item.
     pub count: u64,
     /// Total cost of product item, which can also be refunded.
-    pub compensatable_amount: u64,
+    pub compensatable_amount: Money,
     /// UUID of shipment method of order item.
     pub shipment_method_id: Uuid,
     /// UUIDs of discounts applied to order item.
     pub discount_ids: Vec<Uuid>,
 }
 
+/// Relevant part of shipment event data.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ShipmentEventData {
+    /// Shipment UUID.
+    pub id: Uuid,
+    /// UUID of the order the shipment belongs to.
+    pub order_id: Uuid,
+    /// UUIDs of the order items which are shipped.
+    pub order_item_ids: Vec<Uuid>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Relevant part of billing plan creation event.
+pub struct BillingPlanEventData {
+    /// Billing plan UUID.
+    pub id: Uuid,
+    /// Name of the plan.
+    pub name: String,
+    /// UUID of vendor selling the plan, `None` if sold by the shop itself.
+    #[serde(default)]
+    pub vendor_id: Option<Uuid>,
+    /// Price of one billing period.
+    pub price: Money,
+    /// Interval in which the plan is billed.
+    pub interval: BillingInterval,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Relevant part of subscription creation and update events.
+pub struct SubscriptionEventData {
+    /// Subscription UUID.
+    pub id: Uuid,
+    /// UUID of the subscribed user.
+    pub user_id: Uuid,
+    /// UUID of the billing plan of the subscription.
+    pub plan_id: Uuid,
+    /// UUID of address of invoices.
+    pub invoice_address_id: Uuid,
+    /// Optional VAT number.
+    #[serde(default)]
+    pub vat_number: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+/// Relevant part of subscription cancellation event.
+pub struct SubscriptionCancelledEventData {
+    /// Subscription UUID.
+    pub id: Uuid,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
+#[allow(clippy::upper_case_acronyms)]
 /// Relevant part of payment authorization event data in order event data.
 pub enum PaymentAuthorizationEventData {
     /// CVC/CVV number of 3-4 digits.
@@ -182,6 +409,55 @@ pub struct HttpEventServiceState {
     pub invoice_collection: Collection<Invoice>,
     pub vendor_address_collection: Collection<VendorAddress>,
     pub user_collection: Collection<User>,
+    pub product_variant_collection: Collection<ProductVariant>,
+    pub product_variant_version_collection: Collection<ProductVariantVersion>,
+    pub invoice_number_sequence_collection: Collection<InvoiceNumberSequence>,
+    pub payment_terms_collection: Collection<PaymentTermsRule>,
+    pub default_payment_terms: PaymentTerms,
+    pub dispatch_config: DispatchConfig,
+    pub storage_config: StorageConfig,
+    pub signer: Option<Arc<InvoiceSigner>>,
+    pub audit_log: AuditLog,
+    pub event_bus: InvoiceEventBus,
+    pub order_invoicing_collection: Collection<OrderInvoicing>,
+    pub billing_plan_collection: Collection<BillingPlan>,
+    pub discount_collection: Collection<Discount>,
+    pub shipment_method_collection: Collection<ShipmentMethod>,
+    pub subscription_collection: Collection<CustomerSubscription>,
+    /// Whether orders are invoiced per shipment instead of once validated.
+    pub invoice_per_shipment: bool,
+}
+
+impl HttpEventServiceState {
+    /// Creates the state of the event handlers from the configuration and connections of the service.
+    pub fn new(context: ServiceContext) -> Self {
+        let db_client = context.db_client;
+        Self {
+            invoice_collection: db_client.collection::<Invoice>("invoices"),
+            vendor_address_collection: db_client.collection::<VendorAddress>("vendor_address"),
+            user_collection: db_client.collection::<User>("user"),
+            product_variant_collection: db_client.collection::<ProductVariant>("product_variants"),
+            product_variant_version_collection: db_client
+                .collection::<ProductVariantVersion>("product_variant_versions"),
+            invoice_number_sequence_collection: db_client
+                .collection::<InvoiceNumberSequence>("invoice_number_sequences"),
+            payment_terms_collection: db_client.collection::<PaymentTermsRule>("payment_terms"),
+            default_payment_terms: context.default_payment_terms,
+            dispatch_config: context.dispatch_config,
+            storage_config: context.storage_config,
+            signer: context.signer,
+            audit_log: context.audit_log,
+            event_bus: context.event_bus,
+            order_invoicing_collection: db_client.collection::<OrderInvoicing>("order_invoicing"),
+            billing_plan_collection: db_client.collection::<BillingPlan>("billing_plans"),
+            discount_collection: db_client.collection::<Discount>("discounts"),
+            shipment_method_collection: db_client
+                .collection::<ShipmentMethod>("shipment_methods"),
+            subscription_collection: db_client
+                .collection::<CustomerSubscription>("customer_subscriptions"),
+            invoice_per_shipment: env_var("INVOICE_PER_SHIPMENT").unwrap_or(false),
+        }
+    }
 }
 
 /// HTTP endpoint to list topic subsciptions.
@@ -199,7 +475,12 @@ pub async fn list_topic_subscriptions() -> Result<Json<Vec<Pubsub>>, StatusCode>
     let pubsub_user = Pubsub {
         pubsubname: "pubsub".to_string(),
         topic: "user/user/created".to_string(),
-        route: "/on-id-creation-event".to_string(),
+        route: "/on-user-creation-event".to_string(),
+    };
+    let pubsub_user_deleted = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "user/user/deleted".to_string(),
+        route: "/on-user-deletion-event".to_string(),
     };
     let pubsub_user_address = Pubsub {
         pubsubname: "pubsub".to_string(),
@@ -211,12 +492,79 @@ pub async fn list_topic_subscriptions() -> Result<Json<Vec<Pubsub>>, StatusCode>
         topic: "address/user-address/archived".to_string(),
         route: "/on-user-address-archived-event".to_string(),
     };
+    let pubsub_order_created = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "order/order/created".to_string(),
+        route: "/on-order-creation-event".to_string(),
+    };
+    let pubsub_shipment_created = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "shipment/shipment/created".to_string(),
+        route: "/on-shipment-creation-event".to_string(),
+    };
+    let pubsub_billing_plan = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "subscription/billing-plan/created".to_string(),
+        route: "/on-billing-plan-creation-event".to_string(),
+    };
+    let pubsub_subscription_created = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "subscription/subscription/created".to_string(),
+        route: "/on-subscription-creation-event".to_string(),
+    };
+    let pubsub_subscription_updated = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "subscription/subscription/updated".to_string(),
+        route: "/on-subscription-update-event".to_string(),
+    };
+    let pubsub_subscription_cancelled = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "subscription/subscription/cancelled".to_string(),
+        route: "/on-subscription-cancellation-event".to_string(),
+    };
+    let pubsub_discount = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "discount/discount/created".to_string(),
+        route: "/on-discount-creation-event".to_string(),
+    };
+    let pubsub_shipment_method_created = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "shipment/shipment-method/created".to_string(),
+        route: "/on-shipment-method-creation-event".to_string(),
+    };
+    let pubsub_shipment_method_updated = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "shipment/shipment-method/updated".to_string(),
+        route: "/on-shipment-method-update-event".to_string(),
+    };
+    let pubsub_product_variant = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "catalog/product-variant/created".to_string(),
+        route: "/on-product-variant-creation-event".to_string(),
+    };
+    let pubsub_product_variant_version = Pubsub {
+        pubsubname: "pubsub".to_string(),
+        topic: "catalog/product-variant-version/created".to_string(),
+        route: "/on-product-variant-version-creation-event".to_string(),
+    };
     Ok(Json(vec![
         pubsub_order,
         pubsub_vendor_address,
         pubsub_user,
+        pubsub_user_deleted,
         pubsub_user_address,
         pubsub_user_address_archived,
+        pubsub_product_variant,
+        pubsub_product_variant_version,
+        pubsub_order_created,
+        pubsub_shipment_created,
+        pubsub_billing_plan,
+        pubsub_subscription_created,
+        pubsub_subscription_updated,
+        pubsub_subscription_cancelled,
+        pubsub_discount,
+        pubsub_shipment_method_created,
+        pubsub_shipment_method_updated,
     ]))
 }
 
@@ -233,13 +581,152 @@ pub async fn on_discount_order_validation_succeeded_event(
 
     match event.topic.as_str() {
         "discount/order/validation-succeeded" => {
-            let invoice = Invoice::new(event.data.order.clone(), &state)
+            let trace_id = event.trace_id();
+            let order = event.data.order;
+            if let Err(e) = order.currency() {
+                warn!("Dropped order of UUID: `{}`: {}", order.id, e.message);
+                return Ok(Json(TopicEventResponse::dropped()));
+            }
+            let billing_mode = query_billing_mode(&state.user_collection, order.user_id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            let shipping_line_items =
+                order_shipping_line_items(&state.shipment_method_collection, &order)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            let order_invoicing = record_order_invoicing(
+                &state.order_invoicing_collection,
+                &order,
+                &shipping_line_items,
+                billing_mode == BillingMode::Monthly,
+            )
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            if !order_invoicing.collective && !state.invoice_per_shipment {
+                let audit_entry = AuditEntry::new(AuditAction::InvoiceCreated, None)
+                    .with_trace_id(trace_id.clone());
+                let invoices = issue_order_invoices(
+                    &state,
+                    &order,
+                    &order.order_items,
+                    None,
+                    InvoiceDocumentType::Invoice,
+                    &audit_entry,
+                )
+                .await?;
+                convert_proformas(&state, &invoices, trace_id).await?;
+            }
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+    Ok(Json(TopicEventResponse::default()))
+}
+
+/// HTTP endpoint to receive order creation events.
+///
+/// Issues non-binding proformas of pending orders of B2B buyers, which are identified by the VAT number of the order.
+///
+/// * `state` - Service state containing database connections.
+/// * `event` - Event handled by endpoint.
+#[debug_handler(state = HttpEventServiceState)]
+pub async fn on_order_created_event(
+    State(state): State<HttpEventServiceState>,
+    Json(event): Json<Event<OrderEventData>>,
+) -> Result<Json<TopicEventResponse>, StatusCode> {
+    info!("{:?}", event);
+
+    match event.topic.as_str() {
+        "order/order/created" => {
+            let trace_id = event.trace_id();
+            let order = event.data;
+            if order.vat_number.is_none() {
+                return Ok(Json(TopicEventResponse::default()));
+            }
+            if let Err(e) = order.currency() {
+                warn!("Dropped order of UUID: `{}`: {}", order.id, e.message);
+                return Ok(Json(TopicEventResponse::dropped()));
+            }
+            let existing_proformas = state
+                .invoice_collection
+                .count_documents(
+                    doc! {
+                        "order_id": order.id,
+                        "document_type": bson::to_bson(&InvoiceDocumentType::Proforma)
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+                    },
+                    None,
+                )
                 .await
                 .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            let invoice_dto = InvoiceDTO::from(invoice.clone());
-            let invoice_created_dto = InvoiceCreatedDTO::from((event.data.order, invoice_dto));
-            insert_invoice_in_mongodb(&state.invoice_collection, invoice).await?;
-            send_invoice_created_event(invoice_created_dto).await?
+            if existing_proformas == 0 {
+                let audit_entry =
+                    AuditEntry::new(AuditAction::InvoiceCreated, None).with_trace_id(trace_id);
+                issue_order_invoices(
+                    &state,
+                    &order,
+                    &order.order_items,
+                    None,
+                    InvoiceDocumentType::Proforma,
+                    &audit_entry,
+                )
+                .await?;
+            }
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+    Ok(Json(TopicEventResponse::default()))
+}
+
+/// HTTP endpoint to receive shipment creation events.
+///
+/// If orders are invoiced per shipment, invoices the shipped order items which are not invoiced yet.
+/// Orders of collective invoices are skipped, their items are invoiced at the end of the billing period.
+/// Shipments of orders which are not validated, e.g. rejected orders, are dropped, as they are never invoiced.
+///
+/// * `state` - Service state containing database connections.
+/// * `event` - Event handled by endpoint.
+#[debug_handler(state = HttpEventServiceState)]
+pub async fn on_shipment_created_event(
+    State(state): State<HttpEventServiceState>,
+    Json(event): Json<Event<ShipmentEventData>>,
+) -> Result<Json<TopicEventResponse>, StatusCode> {
+    info!("{:?}", event);
+
+    match event.topic.as_str() {
+        "shipment/shipment/created" => {
+            if !state.invoice_per_shipment {
+                return Ok(Json(TopicEventResponse::default()));
+            }
+            let trace_id = event.trace_id();
+            let shipment = event.data;
+            let Some(order_invoicing) = state
+                .order_invoicing_collection
+                .find_one(doc! {"_id": shipment.order_id}, None)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            else {
+                warn!(
+                    "Dropped shipment `{}` of order UUID: `{}`, which is not validated.",
+                    shipment.id, shipment.order_id
+                );
+                return Ok(Json(TopicEventResponse::dropped()));
+            };
+            if order_invoicing.collective {
+                return Ok(Json(TopicEventResponse::default()));
+            }
+            let order_items = order_invoicing.uninvoiced_items(&shipment.order_item_ids);
+            let audit_entry =
+                AuditEntry::new(AuditAction::InvoiceCreated, None).with_trace_id(trace_id.clone());
+            let invoices = issue_order_invoices(
+                &state,
+                &order_invoicing.order,
+                &order_items,
+                Some(shipment.id),
+                InvoiceDocumentType::Invoice,
+                &audit_entry,
+            )
+            .await?;
+            convert_proformas(&state, &invoices, trace_id).await?;
         }
         _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
@@ -312,6 +799,287 @@ pub async fn on_user_address_archived_event(
     Ok(Json(TopicEventResponse::default()))
 }
 
+/// HTTP endpoint to receive product variant creation events.
+///
+/// * `state` - Service state containing database connections.
+/// * `event` - Event handled by endpoint.
+#[debug_handler(state = HttpEventServiceState)]
+pub async fn on_product_variant_created_event(
+    State(state): State<HttpEventServiceState>,
+    Json(event): Json<Event<ProductVariantEventData>>,
+) -> Result<Json<TopicEventResponse>, StatusCode> {
+    info!("{:?}", event);
+
+    match event.topic.as_str() {
+        "catalog/product-variant/created" => {
+            let product_variant = ProductVariant::from(event.data);
+            create_or_update_product_variant_in_mongodb(
+                &state.product_variant_collection,
+                product_variant,
+            )
+            .await?
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+    Ok(Json(TopicEventResponse::default()))
+}
+
+/// HTTP endpoint to receive product variant version creation events.
+///
+/// * `state` - Service state containing database connections.
+/// * `event` - Event handled by endpoint.
+#[debug_handler(state = HttpEventServiceState)]
+pub async fn on_product_variant_version_created_event(
+    State(state): State<HttpEventServiceState>,
+    Json(event): Json<Event<ProductVariantVersionEventData>>,
+) -> Result<Json<TopicEventResponse>, StatusCode> {
+    info!("{:?}", event);
+
+    match event.topic.as_str() {
+        "catalog/product-variant-version/created" => {
+            let product_variant_version = ProductVariantVersion::from(event.data);
+            state
+                .product_variant_version_collection
+                .replace_one(
+                    doc! {"_id": product_variant_version._id},
+                    product_variant_version,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+    Ok(Json(TopicEventResponse::default()))
+}
+
+/// HTTP endpoint to receive billing plan creation events.
+///
+/// * `state` - Service state containing database connections.
+/// * `event` - Event handled by endpoint.
+#[debug_handler(state = HttpEventServiceState)]
+pub async fn on_billing_plan_created_event(
+    State(state): State<HttpEventServiceState>,
+    Json(event): Json<Event<BillingPlanEventData>>,
+) -> Result<Json<TopicEventResponse>, StatusCode> {
+    info!("{:?}", event);
+
+    match event.topic.as_str() {
+        "subscription/billing-plan/created" => {
+            let billing_plan = BillingPlan::from(event.data);
+            state
+                .billing_plan_collection
+                .replace_one(
+                    doc! {"_id": billing_plan._id},
+                    billing_plan,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+    Ok(Json(TopicEventResponse::default()))
+}
+
+/// HTTP endpoint to receive discount creation events.
+///
+/// * `state` - Service state containing database connections.
+/// * `event` - Event handled by endpoint.
+#[debug_handler(state = HttpEventServiceState)]
+pub async fn on_discount_created_event(
+    State(state): State<HttpEventServiceState>,
+    Json(event): Json<Event<DiscountEventData>>,
+) -> Result<Json<TopicEventResponse>, StatusCode> {
+    info!("{:?}", event);
+
+    match event.topic.as_str() {
+        "discount/discount/created" => {
+            let discount = Discount::from(event.data);
+            state
+                .discount_collection
+                .replace_one(
+                    doc! {"_id": discount._id},
+                    discount,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+    Ok(Json(TopicEventResponse::default()))
+}
+
+/// HTTP endpoint to receive shipment method creation and update events.
+///
+/// * `state` - Service state containing database connections.
+/// * `event` - Event handled by endpoint.
+#[debug_handler(state = HttpEventServiceState)]
+pub async fn on_shipment_method_event(
+    State(state): State<HttpEventServiceState>,
+    Json(event): Json<Event<ShipmentMethodEventData>>,
+) -> Result<Json<TopicEventResponse>, StatusCode> {
+    info!("{:?}", event);
+
+    match event.topic.as_str() {
+        "shipment/shipment-method/created" | "shipment/shipment-method/updated" => {
+            let shipment_method = ShipmentMethod::from(event.data);
+            state
+                .shipment_method_collection
+                .replace_one(
+                    doc! {"_id": shipment_method._id},
+                    shipment_method,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+    Ok(Json(TopicEventResponse::default()))
+}
+
+/// HTTP endpoint to receive subscription creation events.
+///
+/// The first billing period starts with the subscription and is invoiced by the recurring billing job.
+///
+/// * `state` - Service state containing database connections.
+/// * `event` - Event handled by endpoint.
+#[debug_handler(state = HttpEventServiceState)]
+pub async fn on_subscription_created_event(
+    State(state): State<HttpEventServiceState>,
+    Json(event): Json<Event<SubscriptionEventData>>,
+) -> Result<Json<TopicEventResponse>, StatusCode> {
+    info!("{:?}", event);
+
+    match event.topic.as_str() {
+        "subscription/subscription/created" => {
+            let subscription = CustomerSubscription::from(event.data);
+            let subscription_document = bson::to_document(&subscription)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            state
+                .subscription_collection
+                .update_one(
+                    doc! {"_id": subscription._id},
+                    doc! {"$setOnInsert": subscription_document},
+                    UpdateOptions::builder().upsert(true).build(),
+                )
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+    Ok(Json(TopicEventResponse::default()))
+}
+
+/// HTTP endpoint to receive subscription update events.
+///
+/// Updates the invoice address and VAT number of the subscription.
+/// A plan change within an invoiced billing period is prorated for the remainder of the period.
+///
+/// * `state` - Service state containing database connections.
+/// * `event` - Event handled by endpoint.
+#[debug_handler(state = HttpEventServiceState)]
+pub async fn on_subscription_updated_event(
+    State(state): State<HttpEventServiceState>,
+    Json(event): Json<Event<SubscriptionEventData>>,
+) -> Result<Json<TopicEventResponse>, StatusCode> {
+    info!("{:?}", event);
+
+    match event.topic.as_str() {
+        "subscription/subscription/updated" => {
+            let trace_id = event.trace_id();
+            let changed_at = event.occurred_at();
+            let update = event.data;
+            let Some(subscription) = state
+                .subscription_collection
+                .find_one(doc! {"_id": update.id}, None)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            else {
+                // Retried by Dapr until the subscription creation event is handled.
+                warn!("Subscription of UUID: `{}` is not known yet.", update.id);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            };
+            state
+                .subscription_collection
+                .update_one(
+                    doc! {"_id": update.id},
+                    doc! {"$set": {
+                        "invoice_address_id": update.invoice_address_id,
+                        "vat_number": update.vat_number,
+                    }},
+                    None,
+                )
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            if subscription.plan_id != update.plan_id {
+                // Events without id are keyed by the new plan and the timestamp of the change.
+                let event_id = event.id.clone().unwrap_or_else(|| {
+                    format!("{}-{}", update.plan_id, changed_at.timestamp_millis())
+                });
+                change_subscription_plan(
+                    &state,
+                    subscription,
+                    update.plan_id,
+                    &event_id,
+                    changed_at,
+                    trace_id,
+                )
+                .await
+                    .map_err(|e| {
+                        warn!(
+                            "Changing plan of subscription of UUID: `{}` failed: {}",
+                            update.id, e.message
+                        );
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    })?;
+            }
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+    Ok(Json(TopicEventResponse::default()))
+}
+
+/// HTTP endpoint to receive subscription cancellation events.
+///
+/// Cancelled subscriptions are no longer billed, the current billing period stays invoiced.
+///
+/// * `state` - Service state containing database connections.
+/// * `event` - Event handled by endpoint.
+#[debug_handler(state = HttpEventServiceState)]
+pub async fn on_subscription_cancelled_event(
+    State(state): State<HttpEventServiceState>,
+    Json(event): Json<Event<SubscriptionCancelledEventData>>,
+) -> Result<Json<TopicEventResponse>, StatusCode> {
+    info!("{:?}", event);
+
+    match event.topic.as_str() {
+        "subscription/subscription/cancelled" => {
+            state
+                .subscription_collection
+                .update_one(
+                    doc! {
+                        "_id": event.data.id,
+                        "status": bson::to_bson(&SubscriptionStatus::Active)
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+                    },
+                    doc! {"$set": {
+                        "status": bson::to_bson(&SubscriptionStatus::Cancelled)
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+                        "cancelled_at": DateTime::now(),
+                    }},
+                    None,
+                )
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+    Ok(Json(TopicEventResponse::default()))
+}
+
 /// HTTP endpoint to receive user creation events.
 ///
 /// * `state` - Service state containing database connections.
@@ -330,36 +1098,421 @@ pub async fn on_user_created_event(
     Ok(Json(TopicEventResponse::default()))
 }
 
-/// Sends an `invoice/invoice/created` event the order context with the invoice.
+/// HTTP endpoint to receive user deletion events.
 ///
-/// * `invoice_created_dto` - Invoice created DTO to send in `invoice/invoice/created` event.
-async fn send_invoice_created_event(
-    invoice_created_dto: InvoiceCreatedDTO,
+/// Pseudonymizes the user projection and cancels the user's subscriptions, invoices of the user are kept until their retention period ends.
+///
+/// * `state` - Service state containing database connections.
+/// * `event` - Event handled by endpoint.
+#[debug_handler(state = HttpEventServiceState)]
+pub async fn on_user_deleted_event(
+    State(state): State<HttpEventServiceState>,
+    Json(event): Json<Event<UserDeletedEventData>>,
+) -> Result<Json<TopicEventResponse>, StatusCode> {
+    info!("{:?}", event);
+
+    match event.topic.as_str() {
+        "user/user/deleted" => {
+            let trace_id = event.trace_id();
+            pseudonymize_user_in_mongodb(&state.user_collection, event.data.id).await?;
+            cancel_subscriptions_of_user(&state.subscription_collection, event.data.id).await?;
+            state
+                .audit_log
+                .record(
+                    AuditEntry::new(AuditAction::UserErased, None)
+                        .with_trace_id(trace_id)
+                        .with_details(format!("User of UUID: `{}`.", event.data.id)),
+                )
+                .await;
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+    Ok(Json(TopicEventResponse::default()))
+}
+
+/// Cancels the active subscriptions of a deleted user and removes their VAT number.
+///
+/// * `collection` - MongoDB collection of customer subscriptions.
+/// * `user_id` - UUID of the deleted user.
+async fn cancel_subscriptions_of_user(
+    collection: &Collection<CustomerSubscription>,
+    user_id: Uuid,
 ) -> Result<(), StatusCode> {
+    let active =
+        bson::to_bson(&SubscriptionStatus::Active).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let cancelled = bson::to_bson(&SubscriptionStatus::Cancelled)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    collection
+        .update_many(
+            doc! {"user_id": user_id, "status": active},
+            doc! {"$set": {"status": cancelled, "cancelled_at": DateTime::now()}},
+            None,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    collection
+        .update_many(
+            doc! {"user_id": user_id},
+            doc! {"$set": {"vat_number": null}},
+            None,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(())
+}
+
+/// Issues an invoice or proforma per vendor of order items of an order, stores and publishes them and sends them in the background.
+///
+/// Vendors whose invoice or proforma of the order, or of the shipment, is already issued are skipped and their existing document is returned,
+/// so redelivered events do not issue duplicates. A unique index rejects documents issued concurrently for the same vendor.
+/// Order items are marked as invoiced once their invoice is stored, and again for existing invoices found on redelivered events.
+/// Invoices are published as `invoice/invoice/created`, proformas as `invoice/proforma/created`.
+/// Events which fail to be published stay pending on the document and are published by the publication job.
+///
+/// * `state` - Service state containing database connections.
+/// * `order` - Order to invoice, with currency already validated.
+/// * `order_items` - Order items of the order to invoice, e.g. the items of a shipment.
+/// * `shipment_id` - UUID of the shipment the order items are invoiced for, `None` when invoicing whole orders.
+/// * `document_type` - `InvoiceDocumentType::Invoice` for validated orders, `InvoiceDocumentType::Proforma` for pending orders.
+/// * `audit_entry` - Entry attributing the creation to its actor and trace, recorded once per issued document.
+pub async fn issue_order_invoices(
+    state: &HttpEventServiceState,
+    order: &OrderEventData,
+    order_items: &[OrderItemEventData],
+    shipment_id: Option<Uuid>,
+    document_type: InvoiceDocumentType,
+    audit_entry: &AuditEntry,
+) -> Result<Vec<Invoice>, StatusCode> {
+    let order_items_by_vendor =
+        group_order_items_by_vendor(&state.product_variant_collection, order_items).await?;
+    let mut invoices = vec![];
+    for (vendor_id, order_items) in order_items_by_vendor {
+        if let Some(invoice) = query_order_invoice(
+            &state.invoice_collection,
+            order.id,
+            vendor_id,
+            shipment_id,
+            document_type,
+        )
+        .await?
+        {
+            info!(
+                "{:?} of order UUID: `{}` is already issued for vendor {:?}, skipping it.",
+                document_type, order.id, vendor_id
+            );
+            mark_invoiced_items(state, &invoice).await?;
+            invoices.push(invoice);
+            continue;
+        }
+        let invoice = Invoice::new(
+            order.clone(),
+            vendor_id,
+            order_items,
+            shipment_id,
+            document_type,
+            state,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        mark_invoiced_items(state, &invoice).await?;
+        state
+            .audit_log
+            .record(AuditEntry {
+                _id: Uuid::new(),
+                invoice_id: Some(invoice._id),
+                occurred_at: DateTime::now(),
+                details: Some(format!(
+                    "{:?} `{}` of order `{}`.",
+                    document_type, invoice.invoice_number, order.id
+                )),
+                ..audit_entry.clone()
+            })
+            .await;
+        publish_pending_event(&state.invoice_collection, &invoice).await;
+        state
+            .event_bus
+            .publish(InvoiceBusEvent::Created(invoice.clone()));
+        spawn_invoice_post_processing(
+            &state.invoice_collection,
+            &state.dispatch_config,
+            &state.storage_config,
+            &state.audit_log,
+            invoice.clone(),
+        );
+        invoices.push(invoice);
+    }
+    Ok(invoices)
+}
+
+/// Marks the order items of a stored invoice of an order as invoiced, proformas do not invoice their items.
+///
+/// * `state` - Service state containing database connections.
+/// * `invoice` - Stored invoice or proforma of an order.
+async fn mark_invoiced_items(
+    state: &HttpEventServiceState,
+    invoice: &Invoice,
+) -> Result<(), StatusCode> {
+    let Some(order_id) = invoice.order_id else {
+        return Ok(());
+    };
+    if invoice.document_type != InvoiceDocumentType::Invoice {
+        return Ok(());
+    }
+    let order_item_ids: Vec<Uuid> = invoice
+        .line_items
+        .iter()
+        .filter_map(|item| item.order_item_id)
+        .collect();
+    mark_order_items_invoiced(&state.order_invoicing_collection, order_id, &order_item_ids)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Queries the invoice or proforma of an order issued for a vendor, `None` if it is not issued yet.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `order_id` - UUID of the invoiced order.
+/// * `vendor_id` - UUID of the vendor of the invoiced order items.
+/// * `shipment_id` - UUID of the shipment the order items are invoiced for, `None` when invoicing whole orders.
+/// * `document_type` - `InvoiceDocumentType::Invoice` or `InvoiceDocumentType::Proforma`.
+async fn query_order_invoice(
+    collection: &Collection<Invoice>,
+    order_id: Uuid,
+    vendor_id: Option<Uuid>,
+    shipment_id: Option<Uuid>,
+    document_type: InvoiceDocumentType,
+) -> Result<Option<Invoice>, StatusCode> {
+    let mut filter = doc! {
+        "order_id": order_id,
+        "vendor_id": vendor_id,
+        "document_type": bson::to_bson(&document_type)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    };
+    if document_type == InvoiceDocumentType::Invoice {
+        filter.insert("shipment_id", shipment_id);
+    }
+    collection
+        .find_one(filter, None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Marks the proformas of the orders of issued invoices as converted to the invoice of their vendor.
+///
+/// * `state` - Service state containing database connections.
+/// * `invoices` - Invoices issued for a validated order.
+/// * `trace_id` - Trace id of the event which validated the order.
+async fn convert_proformas(
+    state: &HttpEventServiceState,
+    invoices: &[Invoice],
+    trace_id: Option<String>,
+) -> Result<(), StatusCode> {
+    for invoice in invoices {
+        let result = state
+            .invoice_collection
+            .update_many(
+                unconverted_proformas_filter(invoice)?,
+                doc! {"$set": {"converted_to": invoice._id}},
+                None,
+            )
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if result.modified_count > 0 {
+            state
+                .audit_log
+                .record(
+                    AuditEntry::new(AuditAction::ProformaConverted, Some(invoice._id))
+                        .with_trace_id(trace_id.clone())
+                        .with_details(format!(
+                            "{} proformas converted to invoice `{}`.",
+                            result.modified_count, invoice.invoice_number
+                        )),
+                )
+                .await;
+        }
+    }
+    Ok(())
+}
+
+/// Filter of the proformas converted to an invoice, which are those of its order and vendor not converted yet.
+fn unconverted_proformas_filter(invoice: &Invoice) -> Result<Document, StatusCode> {
+    let proforma = bson::to_bson(&InvoiceDocumentType::Proforma)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(doc! {
+        "order_id": invoice.order_id,
+        "vendor_id": invoice.vendor_id,
+        "document_type": proforma,
+        "converted_to": null,
+    })
+}
+
+/// Stores the documents of an invoice and sends it to the customer in the background.
+///
+/// Documents which fail to be stored are stored on first download, failed deliveries are retried by the dispatch job.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `dispatch_config` - Configuration of the invoice dispatch.
+/// * `storage_config` - Configuration of the document storage.
+/// * `audit_log` - Audit log to record rendered documents and delivery attempts in.
+/// * `invoice` - Newly issued invoice.
+pub fn spawn_invoice_post_processing(
+    collection: &Collection<Invoice>,
+    dispatch_config: &DispatchConfig,
+    storage_config: &StorageConfig,
+    audit_log: &AuditLog,
+    invoice: Invoice,
+) {
+    let collection = collection.clone();
+    let dispatch_config = dispatch_config.clone();
+    let storage_config = storage_config.clone();
+    let audit_log = audit_log.clone();
+    tokio::spawn(async move {
+        if let Err(e) =
+            store_invoice_documents(&collection, &storage_config, &audit_log, &invoice).await
+        {
+            warn!(
+                "Storing documents of invoice of UUID: `{}` failed: {}",
+                invoice._id, e.message
+            );
+        }
+        if let Err(e) =
+            dispatch_invoice(&collection, &dispatch_config, &audit_log, &invoice, false).await
+        {
+            warn!(
+                "Recording delivery of invoice of UUID: `{}` failed: {}",
+                invoice._id, e.message
+            );
+        }
+    });
+}
+
+/// Publishes an event to a topic of the `pubsub` component via the Dapr sidecar.
+///
+/// * `topic` - Topic to publish the event to.
+/// * `data` - Event data, serialized as JSON.
+pub async fn publish_event<T: Serialize>(topic: &str, data: &T) -> Result<(), StatusCode> {
     let client = reqwest::Client::new();
     match client
-        .post("http://localhost:3500/v1.0/publish/invoice/invoice/created")
-        .json(&invoice_created_dto)
+        .post(format!("http://localhost:3500/v1.0/publish/pubsub/{}", topic))
+        .json(data)
         .send()
         .await
     {
-        Ok(_) => Ok(()),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Ok(response) if response.status().is_success() => Ok(()),
+        _ => Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
 
-/// Inserts invoice in MongoDB.
+/// Publishes the pending event of an issued document and clears it, logging a failure instead of failing.
+///
+/// Events which are still pending, e.g. as publishing failed or the service stopped before, are published by the publication job.
+/// Events are published at least once, so consumers must handle duplicates by the UUID of the document.
 ///
-/// * `collection` - MongoDB collection to add newly created invoice to.
-/// * `invoice` - Invoice to insert.
-pub async fn insert_invoice_in_mongodb(
+/// * `collection` - MongoDB collection of invoices.
+/// * `invoice` - Stored document.
+pub async fn publish_pending_event(collection: &Collection<Invoice>, invoice: &Invoice) {
+    if let Err(e) = publish_issued_event(collection, invoice).await {
+        warn!(
+            "Publishing event of invoice of UUID: `{}` failed, it is retried by the publication job: {}",
+            invoice._id, e
+        );
+    }
+}
+
+/// Publishes the pending event of an issued document and clears it.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `invoice` - Stored document.
+pub async fn publish_issued_event(
     collection: &Collection<Invoice>,
-    invoice: Invoice,
+    invoice: &Invoice,
 ) -> Result<(), StatusCode> {
-    match collection.insert_one(invoice, None).await {
-        Ok(_) => Ok(()),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    let Some(event) = invoice.pending_event else {
+        return Ok(());
+    };
+    match event {
+        IssuedEvent::Invoice | IssuedEvent::Proforma => {
+            let order = invoice
+                .order
+                .clone()
+                .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+            let invoice_created_dto =
+                InvoiceCreatedDTO::from((order, InvoiceDTO::from(invoice.clone())));
+            publish_event(event.topic(), &invoice_created_dto).await?
+        }
+        IssuedEvent::CollectiveInvoice => {
+            publish_event(
+                event.topic(),
+                &CollectiveInvoiceCreatedDTO::from(invoice.clone()),
+            )
+            .await?
+        }
+        IssuedEvent::SubscriptionInvoice => {
+            publish_event(
+                event.topic(),
+                &SubscriptionInvoiceCreatedDTO::from(invoice.clone()),
+            )
+            .await?
+        }
+        IssuedEvent::ManualInvoice | IssuedEvent::CorrectiveInvoice | IssuedEvent::CreditNote => {
+            publish_event(
+                event.topic(),
+                &InvoiceDocumentIssuedDTO::from(invoice.clone()),
+            )
+            .await?
+        }
     }
+    collection
+        .update_one(
+            doc! {"_id": invoice._id},
+            doc! {"$unset": {"pending_event": ""}},
+            None,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(())
+}
+
+/// Publishes the recorded change events of an invoice in order and clears them, logging a failure instead of failing.
+///
+/// Publishing stops at the first failure, the remaining events are published in order by the publication job.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `invoice` - Invoice as stored after the change.
+pub async fn publish_pending_change_events(collection: &Collection<Invoice>, invoice: &Invoice) {
+    for event in &invoice.pending_events {
+        if let Err(e) = publish_change_event(collection, invoice._id, event).await {
+            warn!(
+                "Publishing `{}` event of invoice of UUID: `{}` failed, it is retried by the publication job: {}",
+                event.topic, invoice._id, e
+            );
+            return;
+        }
+    }
+}
+
+/// Publishes a recorded change event of an invoice and clears it.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `invoice_id` - UUID of the changed invoice.
+/// * `event` - Recorded event.
+pub async fn publish_change_event(
+    collection: &Collection<Invoice>,
+    invoice_id: Uuid,
+    event: &PendingEvent,
+) -> Result<(), StatusCode> {
+    let data: serde_json::Value =
+        serde_json::from_str(&event.data).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    publish_event(&event.topic, &data).await?;
+    collection
+        .update_one(
+            doc! {"_id": invoice_id},
+            doc! {"$pull": {"pending_events": {"_id": event._id}}},
+            None,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(())
 }
 
 /// Create or update vendor address in MongoDB.
@@ -371,10 +1524,34 @@ pub async fn create_or_update_vendor_address_in_mongodb(
     vendor_address: VendorAddress,
 ) -> Result<(), StatusCode> {
     let update_options = UpdateOptions::builder().upsert(true).build();
+    let vendor_address_document =
+        bson::to_document(&vendor_address).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     match collection
         .update_one(
             doc! {"_id": vendor_address._id },
-            doc! {"$set": {"_id": vendor_address._id}},
+            doc! {"$set": vendor_address_document},
+            update_options,
+        )
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Create or update product variant in MongoDB.
+///
+/// * `collection` - MongoDB collection to create or update product variant in.
+/// * `product_variant` - Product variant to create or update.
+pub async fn create_or_update_product_variant_in_mongodb(
+    collection: &Collection<ProductVariant>,
+    product_variant: ProductVariant,
+) -> Result<(), StatusCode> {
+    let update_options = UpdateOptions::builder().upsert(true).build();
+    match collection
+        .update_one(
+            doc! {"_id": product_variant._id },
+            doc! {"$set": {"vendor_id": product_variant.vendor_id}},
             update_options,
         )
         .await
@@ -384,6 +1561,47 @@ pub async fn create_or_update_vendor_address_in_mongodb(
     }
 }
 
+/// Groups order items by the vendor selling their product variant.
+///
+/// Order items of product variants without a known vendor are grouped under `None`, the shop's own vendor.
+/// Groups are returned in order of first appearance.
+///
+/// * `collection` - MongoDB collection of product variants.
+/// * `order_items` - Order items to group.
+pub async fn group_order_items_by_vendor(
+    collection: &Collection<ProductVariant>,
+    order_items: &[OrderItemEventData],
+) -> Result<Vec<(Option<Uuid>, Vec<OrderItemEventData>)>, StatusCode> {
+    let mut vendor_ids = Vec::new();
+    for item in order_items {
+        let vendor_id = collection
+            .find_one(doc! {"_id": item.product_variant_id }, None)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .and_then(|product_variant| product_variant.vendor_id);
+        vendor_ids.push(vendor_id);
+    }
+    Ok(group_by_vendor(order_items, &vendor_ids))
+}
+
+/// Groups order items by the given vendors, in order of first appearance.
+///
+/// * `order_items` - Order items to group.
+/// * `vendor_ids` - UUID of the vendor of each order item, `None` for the shop's own vendor.
+fn group_by_vendor(
+    order_items: &[OrderItemEventData],
+    vendor_ids: &[Option<Uuid>],
+) -> Vec<(Option<Uuid>, Vec<OrderItemEventData>)> {
+    let mut groups: Vec<(Option<Uuid>, Vec<OrderItemEventData>)> = Vec::new();
+    for (item, vendor_id) in order_items.iter().zip(vendor_ids) {
+        match groups.iter_mut().find(|(id, _)| id == vendor_id) {
+            Some((_, items)) => items.push(item.clone()),
+            None => groups.push((*vendor_id, vec![item.clone()])),
+        }
+    }
+    groups
+}
+
 /// Inserts user address in MongoDB.
 ///
 /// * `collection` - MongoDB collection to add user address to.
@@ -395,7 +1613,7 @@ pub async fn insert_user_address_in_mongodb(
     match collection
         .update_one(
             doc! {"_id": user_address.user_id },
-            doc! {"$push": {"user_addresses": user_address }},
+            doc! {"$push": {"addresses": user_address }},
             None,
         )
         .await
@@ -416,7 +1634,7 @@ pub async fn remove_user_address_in_mongodb(
     match collection
         .update_one(
             doc! {"_id": user_address_event_data.user_id },
-            doc! {"$pull": {"user_addresses._id": user_address_event_data.id }},
+            doc! {"$pull": {"addresses": {"_id": user_address_event_data.id }}},
             None,
         )
         .await
@@ -440,3 +1658,130 @@ async fn add_user_to_mongodb(
         Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
+
+/// Pseudonymizes a user in MongoDB by removing names, addresses and contact data.
+///
+/// The user projection is kept, so that invoices of the user still resolve until they are purged by the retention job.
+///
+/// * `collection` - MongoDB collection of users.
+/// * `id` - UUID of user to pseudonymize.
+pub async fn pseudonymize_user_in_mongodb(
+    collection: &Collection<User>,
+    id: Uuid,
+) -> Result<(), StatusCode> {
+    match collection
+        .update_one(
+            doc! {"_id": id },
+            doc! {"$set": {
+                "first_name": "",
+                "last_name": "",
+                "addresses": [],
+                "preferred_locale": null,
+                "email": null,
+                "erased_at": DateTime::now(),
+            }},
+            None,
+        )
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::graphql::model::invoice::tests::invoice;
+
+    /// Order item of the given amount in EUR, shipped with the given shipment method.
+    pub fn order_item(amount: i64, shipment_method_id: Uuid) -> OrderItemEventData {
+        OrderItemEventData {
+            id: Uuid::new(),
+            created_at: chrono::Utc::now(),
+            product_variant_id: Uuid::new(),
+            product_variant_version_id: Uuid::new(),
+            tax_rate_version_id: Uuid::new(),
+            shopping_cart_item_id: Uuid::new(),
+            count: 1,
+            compensatable_amount: Money {
+                amount,
+                currency: "EUR".to_string(),
+            },
+            shipment_method_id,
+            discount_ids: vec![],
+        }
+    }
+
+    /// Placed order of the given order items with the given total in EUR.
+    pub fn order(order_items: Vec<OrderItemEventData>, total: i64) -> OrderEventData {
+        OrderEventData {
+            id: Uuid::new(),
+            user_id: Uuid::new(),
+            created_at: chrono::Utc::now(),
+            order_status: OrderStatus::Placed,
+            placed_at: chrono::Utc::now(),
+            rejection_reason: None,
+            order_items,
+            shipment_address_id: Uuid::new(),
+            invoice_address_id: Uuid::new(),
+            compensatable_order_amount: Money {
+                amount: total,
+                currency: "EUR".to_string(),
+            },
+            payment_information_id: Uuid::new(),
+            payment_authorization: None,
+            vat_number: None,
+        }
+    }
+
+    fn ids(items: &[OrderItemEventData]) -> Vec<Uuid> {
+        items.iter().map(|item| item.id).collect()
+    }
+
+    #[test]
+    fn groups_order_items_by_vendor_in_order_of_first_appearance() {
+        let shipment_method_id = Uuid::new();
+        let items: Vec<OrderItemEventData> =
+            (0..4).map(|_| order_item(100, shipment_method_id)).collect();
+        let vendor_id = Some(Uuid::new());
+        let groups = group_by_vendor(&items, &[vendor_id, None, vendor_id, None]);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].0, vendor_id);
+        assert_eq!(ids(&groups[0].1), vec![items[0].id, items[2].id]);
+        assert_eq!(groups[1].0, None);
+        assert_eq!(ids(&groups[1].1), vec![items[1].id, items[3].id]);
+    }
+
+    #[test]
+    fn keeps_order_of_single_vendor_in_one_group() {
+        let items = vec![order_item(100, Uuid::new()), order_item(200, Uuid::new())];
+        let groups = group_by_vendor(&items, &[None, None]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(ids(&groups[0].1), ids(&items));
+    }
+
+    #[test]
+    fn rejects_order_with_items_in_other_currency() {
+        let mut item = order_item(100, Uuid::new());
+        item.compensatable_amount.currency = "USD".to_string();
+        assert!(order(vec![item], 100).currency().is_err());
+        let order = order(vec![order_item(100, Uuid::new())], 100);
+        assert_eq!(order.currency().unwrap(), "EUR");
+    }
+
+    #[test]
+    fn converts_unconverted_proformas_of_invoiced_order_and_vendor() {
+        let mut invoice = invoice(10000);
+        invoice.order_id = Some(Uuid::new());
+        invoice.vendor_id = Some(Uuid::new());
+        let filter = unconverted_proformas_filter(&invoice).unwrap();
+        let expected = doc! {
+            "order_id": invoice.order_id,
+            "vendor_id": invoice.vendor_id,
+            "document_type": "Proforma",
+            "converted_to": null,
+        };
+        assert_eq!(filter, expected);
+    }
+}
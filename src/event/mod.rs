@@ -1,2 +1,3 @@
+pub mod bus;
 pub mod http_event_service;
 pub mod model;
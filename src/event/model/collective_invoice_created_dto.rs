@@ -0,0 +1,30 @@
+use bson::Uuid;
+use serde::Serialize;
+
+use super::invoice_dto::InvoiceDTO;
+use crate::graphql::model::{invoice::Invoice, money::Money};
+
+/// DTO which describes the event context when a collective invoice of a billing period is issued.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectiveInvoiceCreatedDTO {
+    pub user_id: Uuid,
+    pub order_ids: Vec<Uuid>,
+    pub billing_period_start: Option<chrono::DateTime<chrono::Utc>>,
+    pub billing_period_end: Option<chrono::DateTime<chrono::Utc>>,
+    pub total: Money,
+    pub invoice: InvoiceDTO,
+}
+
+impl From<Invoice> for CollectiveInvoiceCreatedDTO {
+    fn from(value: Invoice) -> Self {
+        Self {
+            user_id: value.user_id,
+            order_ids: value.order_ids.clone(),
+            billing_period_start: value.billing_period.map(|period| period.start.to_chrono()),
+            billing_period_end: value.billing_period.map(|period| period.end.to_chrono()),
+            total: value.total.clone(),
+            invoice: InvoiceDTO::from(value),
+        }
+    }
+}
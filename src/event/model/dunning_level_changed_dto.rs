@@ -0,0 +1,35 @@
+use bson::Uuid;
+use serde::Serialize;
+
+use crate::graphql::model::{dunning::DunningLevel, invoice::Invoice, money::Money};
+
+/// DTO which describes the event context when an invoice reaches a new dunning level.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DunningLevelChangedDTO {
+    pub invoice_id: Uuid,
+    pub order_id: Option<Uuid>,
+    pub user_id: Uuid,
+    pub invoice_number: String,
+    pub previous_level: Option<DunningLevel>,
+    pub level: DunningLevel,
+    pub due_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub total: Money,
+    pub fee: Option<Money>,
+}
+
+impl From<(&Invoice, DunningLevel, Option<Money>)> for DunningLevelChangedDTO {
+    fn from((invoice, level, fee): (&Invoice, DunningLevel, Option<Money>)) -> Self {
+        Self {
+            invoice_id: invoice._id,
+            order_id: invoice.order_id,
+            user_id: invoice.user_id,
+            invoice_number: invoice.invoice_number.clone(),
+            previous_level: invoice.dunning_level,
+            level,
+            due_at: invoice.due_at.map(|due_at| due_at.to_chrono()),
+            total: invoice.total.clone(),
+            fee,
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use bson::Uuid;
+use serde::Serialize;
+
+use super::invoice_dto::InvoiceDTO;
+use crate::graphql::model::{
+    invoice::{Invoice, InvoiceDocumentType},
+    money::Money,
+};
+
+/// DTO which describes the event context when a manual invoice, corrective invoice or credit note is issued.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceDocumentIssuedDTO {
+    pub document_type: InvoiceDocumentType,
+    pub related_invoice_id: Option<Uuid>,
+    pub user_id: Uuid,
+    pub total: Money,
+    pub reason: Option<String>,
+    pub invoice: InvoiceDTO,
+}
+
+impl From<Invoice> for InvoiceDocumentIssuedDTO {
+    fn from(value: Invoice) -> Self {
+        Self {
+            document_type: value.document_type,
+            related_invoice_id: value.related_invoice_id,
+            user_id: value.user_id,
+            total: value.total.clone(),
+            reason: value.reason.clone(),
+            invoice: InvoiceDTO::from(value),
+        }
+    }
+}
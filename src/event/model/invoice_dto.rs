@@ -7,7 +7,11 @@ use crate::graphql::model::invoice::Invoice;
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InvoiceDTO {
-    pub order_id: Uuid,
+    pub id: Uuid,
+    pub order_id: Option<Uuid>,
+    pub shipment_id: Option<Uuid>,
+    pub vendor_id: Option<Uuid>,
+    pub invoice_number: String,
     pub issued_at: chrono::DateTime<chrono::Utc>,
     pub content: String,
 }
@@ -15,7 +19,11 @@ pub struct InvoiceDTO {
 impl From<Invoice> for InvoiceDTO {
     fn from(value: Invoice) -> Self {
         InvoiceDTO {
+            id: value._id,
             order_id: value.order_id,
+            shipment_id: value.shipment_id,
+            vendor_id: value.vendor_id,
+            invoice_number: value.invoice_number,
             issued_at: value.issued_at.to_chrono(),
             content: value.content,
         }
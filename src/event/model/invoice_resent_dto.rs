@@ -0,0 +1,27 @@
+use bson::Uuid;
+use serde::Serialize;
+
+use crate::graphql::model::{delivery::DeliveryStatus, invoice::Invoice};
+
+/// DTO which describes the event context when an invoice is sent to the customer again.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceResentDTO {
+    pub invoice_id: Uuid,
+    pub order_id: Option<Uuid>,
+    pub invoice_number: String,
+    pub recipient: Option<String>,
+    pub delivery_status: DeliveryStatus,
+}
+
+impl From<(&Invoice, DeliveryStatus)> for InvoiceResentDTO {
+    fn from((invoice, delivery_status): (&Invoice, DeliveryStatus)) -> Self {
+        Self {
+            invoice_id: invoice._id,
+            order_id: invoice.order_id,
+            invoice_number: invoice.invoice_number.clone(),
+            recipient: invoice.delivery.recipient.clone(),
+            delivery_status,
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use bson::Uuid;
+use serde::Serialize;
+
+use crate::graphql::model::invoice::{Invoice, InvoiceStatus};
+
+/// DTO which describes the event context when the status of an invoice changes.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceStatusChangedDTO {
+    pub invoice_id: Uuid,
+    pub order_id: Option<Uuid>,
+    pub user_id: Uuid,
+    pub invoice_number: String,
+    pub previous_status: InvoiceStatus,
+    pub status: InvoiceStatus,
+    pub reason: Option<String>,
+    pub changed_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<(&Invoice, InvoiceStatus, Option<String>)> for InvoiceStatusChangedDTO {
+    fn from((invoice, status, reason): (&Invoice, InvoiceStatus, Option<String>)) -> Self {
+        Self {
+            invoice_id: invoice._id,
+            order_id: invoice.order_id,
+            user_id: invoice.user_id,
+            invoice_number: invoice.invoice_number.clone(),
+            previous_status: invoice.status,
+            status,
+            reason,
+            changed_at: chrono::Utc::now(),
+        }
+    }
+}
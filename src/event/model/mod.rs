@@ -1,2 +1,9 @@
+pub mod collective_invoice_created_dto;
+pub mod dunning_level_changed_dto;
 pub mod invoice_created_dto;
+pub mod invoice_document_issued_dto;
 pub mod invoice_dto;
+pub mod invoice_resent_dto;
+pub mod invoice_status_changed_dto;
+pub mod subscription_invoice_created_dto;
+pub mod vendor_address_set_dto;
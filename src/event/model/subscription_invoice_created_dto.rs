@@ -0,0 +1,30 @@
+use bson::Uuid;
+use serde::Serialize;
+
+use super::invoice_dto::InvoiceDTO;
+use crate::graphql::model::{invoice::Invoice, money::Money};
+
+/// DTO which describes the event context when an invoice of a subscription is issued.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionInvoiceCreatedDTO {
+    pub subscription_id: Option<Uuid>,
+    pub user_id: Uuid,
+    pub billing_period_start: Option<chrono::DateTime<chrono::Utc>>,
+    pub billing_period_end: Option<chrono::DateTime<chrono::Utc>>,
+    pub total: Money,
+    pub invoice: InvoiceDTO,
+}
+
+impl From<Invoice> for SubscriptionInvoiceCreatedDTO {
+    fn from(value: Invoice) -> Self {
+        Self {
+            subscription_id: value.subscription_id,
+            user_id: value.user_id,
+            billing_period_start: value.billing_period.map(|period| period.start.to_chrono()),
+            billing_period_end: value.billing_period.map(|period| period.end.to_chrono()),
+            total: value.total.clone(),
+            invoice: InvoiceDTO::from(value),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use bson::Uuid;
+use serde::Serialize;
+
+use crate::graphql::model::foreign_types::VendorAddress;
+
+/// DTO which describes the event context when back-office sets the vendor address used on invoices.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorAddressSetDTO {
+    pub id: Uuid,
+    pub vendor_id: Option<Uuid>,
+    pub company_name: String,
+    pub street1: String,
+    pub street2: String,
+    pub postal_code: String,
+    pub city: String,
+    pub country: String,
+    pub vat_id: Option<String>,
+}
+
+impl From<&VendorAddress> for VendorAddressSetDTO {
+    fn from(value: &VendorAddress) -> Self {
+        Self {
+            id: value._id,
+            vendor_id: value.vendor_id,
+            company_name: value.company_name.clone(),
+            street1: value.street1.clone(),
+            street2: value.street2.clone(),
+            postal_code: value.postal_code.clone(),
+            city: value.city.clone(),
+            country: value.country.clone(),
+            vat_id: value.vat_id.clone(),
+        }
+    }
+}
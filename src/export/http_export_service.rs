@@ -0,0 +1,89 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bson::Uuid;
+use log::warn;
+use mongodb::Collection;
+
+use super::user_data::export_user_data;
+use crate::{
+    audit::{trace_id_from_headers, AuditLog},
+    authorization::{AuthorizedUser, Role},
+    graphql::model::{
+        audit_entry::{AuditAction, AuditEntry},
+        foreign_types::User,
+        invoice::Invoice,
+        order_invoicing::OrderInvoicing,
+        recurring::CustomerSubscription,
+    },
+};
+
+/// Service state containing database connections.
+#[derive(Clone)]
+pub struct HttpExportServiceState {
+    pub invoice_collection: Collection<Invoice>,
+    pub user_collection: Collection<User>,
+    pub subscription_collection: Collection<CustomerSubscription>,
+    pub order_invoicing_collection: Collection<OrderInvoicing>,
+    pub audit_log: AuditLog,
+}
+
+/// HTTP endpoint to export everything stored about a user as JSON bundle, restricted to admins.
+///
+/// * `state` - Service state containing database connections.
+/// * `path` - UUID of the user.
+/// * `headers` - Request headers containing the authenticated user and trace context.
+pub async fn download_user_data_export(
+    State(state): State<HttpExportServiceState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let authorized_user = AuthorizedUser::from_headers(&headers).ok_or(StatusCode::UNAUTHORIZED)?;
+    if !authorized_user.has_any_role(&[Role::Admin]) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let user_id = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let export = export_user_data(
+        &state.user_collection,
+        &state.invoice_collection,
+        &state.subscription_collection,
+        &state.order_invoicing_collection,
+        state.audit_log.collection(),
+        user_id,
+    )
+    .await
+    .map_err(|e| {
+        warn!(
+            "Exporting data of user of UUID: `{}` failed: {}",
+            user_id, e.message
+        );
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let json = serde_json::to_vec_pretty(&export).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state
+        .audit_log
+        .record(
+            AuditEntry::new(AuditAction::UserDataExported, None)
+                .by_user(Some(&authorized_user))
+                .with_trace_id(trace_id_from_headers(&headers))
+                .with_details(format!(
+                    "User of UUID: `{}`, {} invoices.",
+                    user_id,
+                    export.invoices.len()
+                )),
+        )
+        .await;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"user-{}.json\"", user_id),
+            ),
+        ],
+        json,
+    )
+        .into_response())
+}
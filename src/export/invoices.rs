@@ -0,0 +1,128 @@
+use std::fmt::Write;
+
+use async_graphql::Result;
+use bson::{doc, DateTime};
+use clap::ValueEnum;
+use futures::TryStreamExt;
+use mongodb::{options::FindOptions, Collection};
+use serde::Serialize;
+
+use crate::graphql::model::invoice::{Invoice, InvoiceDocumentType};
+
+/// Format of an invoice export.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Invoice as exported for accounting, one line per invoice.
+#[derive(Debug, Serialize)]
+pub struct InvoiceExportRecord {
+    pub invoice_number: String,
+    pub invoice_id: String,
+    pub order_id: String,
+    pub user_id: String,
+    pub vendor_id: Option<String>,
+    pub issued_at: String,
+    pub due_at: Option<String>,
+    pub status: String,
+    pub customer: String,
+    pub vat_number: Option<String>,
+    pub total: String,
+    pub currency: String,
+}
+
+impl From<&Invoice> for InvoiceExportRecord {
+    fn from(value: &Invoice) -> Self {
+        Self {
+            invoice_number: value.invoice_number.clone(),
+            invoice_id: value._id.to_string(),
+            order_id: value.order_id.map(|id| id.to_string()).unwrap_or_default(),
+            user_id: value.user_id.to_string(),
+            vendor_id: value.vendor_id.map(|id| id.to_string()),
+            issued_at: value.issued_at.to_chrono().to_rfc3339(),
+            due_at: value.due_at.map(|due_at| due_at.to_chrono().to_rfc3339()),
+            status: format!("{:?}", value.status),
+            customer: value.customer.name.clone(),
+            vat_number: value.vat_number.clone(),
+            total: value.total.decimal(),
+            currency: value.total.currency.clone(),
+        }
+    }
+}
+
+/// Queries the invoices issued in a time range, ordered by their issue date.
+///
+/// Proformas are non-binding and therefore not exported.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `from` - Start of the time range, inclusive.
+/// * `to` - End of the time range, exclusive.
+pub async fn query_invoices_issued_between(
+    collection: &Collection<Invoice>,
+    from: DateTime,
+    to: DateTime,
+) -> Result<Vec<Invoice>> {
+    let find_options = FindOptions::builder()
+        .sort(doc! {"issued_at": 1})
+        .build();
+    let invoices = collection
+        .find(
+            doc! {
+                "issued_at": {"$gte": from, "$lt": to},
+                "document_type": {"$ne": bson::to_bson(&InvoiceDocumentType::Proforma)?},
+            },
+            find_options,
+        )
+        .await?
+        .try_collect()
+        .await?;
+    Ok(invoices)
+}
+
+/// Renders invoices in an export format.
+pub fn render_export(invoices: &[Invoice], format: ExportFormat) -> String {
+    let records: Vec<InvoiceExportRecord> =
+        invoices.iter().map(InvoiceExportRecord::from).collect();
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_string_pretty(&records).expect("Export records are always serializable.")
+        }
+        ExportFormat::Csv => render_csv(&records),
+    }
+}
+
+/// Renders export records as CSV with a header line.
+fn render_csv(records: &[InvoiceExportRecord]) -> String {
+    let mut csv = String::from(
+        "invoice_number,invoice_id,order_id,user_id,vendor_id,issued_at,due_at,status,customer,vat_number,total,currency\n",
+    );
+    for record in records {
+        let fields = [
+            record.invoice_number.as_str(),
+            &record.invoice_id,
+            &record.order_id,
+            &record.user_id,
+            record.vendor_id.as_deref().unwrap_or_default(),
+            &record.issued_at,
+            record.due_at.as_deref().unwrap_or_default(),
+            &record.status,
+            &record.customer,
+            record.vat_number.as_deref().unwrap_or_default(),
+            &record.total,
+            &record.currency,
+        ];
+        let line = fields.map(escape_csv).join(",");
+        writeln!(csv, "{}", line).expect("Writing to a string never fails.");
+    }
+    csv
+}
+
+/// Quotes a CSV field if it contains separators, quotes or line breaks.
+fn escape_csv(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
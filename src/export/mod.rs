@@ -0,0 +1,3 @@
+pub mod http_export_service;
+pub mod invoices;
+pub mod user_data;
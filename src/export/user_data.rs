@@ -0,0 +1,105 @@
+use async_graphql::Result;
+use bson::{doc, DateTime, Uuid};
+use futures::TryStreamExt;
+use mongodb::{options::FindOptions, Collection};
+use serde::Serialize;
+
+use crate::graphql::model::{
+    audit_entry::AuditEntry,
+    foreign_types::{User, UserAddress},
+    invoice::Invoice,
+    order_invoicing::OrderInvoicing,
+    recurring::CustomerSubscription,
+};
+
+/// Everything the invoice service stores about a user, exported to answer a data subject access request.
+#[derive(Debug, Serialize)]
+pub struct UserDataExport {
+    /// UUID of the user the data is exported of.
+    pub user_id: Uuid,
+    /// Timestamp of the export.
+    pub exported_at: DateTime,
+    /// Projection of the user, `None` if no projection is stored.
+    pub user: Option<User>,
+    /// Addresses of the user stored in the projection.
+    pub addresses: Vec<UserAddress>,
+    /// Invoices issued to the user, including their customer snapshots.
+    pub invoices: Vec<Invoice>,
+    /// Subscriptions of the user, including their VAT numbers.
+    pub subscriptions: Vec<CustomerSubscription>,
+    /// Invoicing states of the user's orders, including their order snapshots.
+    pub order_invoicing: Vec<OrderInvoicing>,
+    /// Audit entries of actions performed by the user or on invoices of the user.
+    pub audit_entries: Vec<AuditEntry>,
+}
+
+/// Collects everything stored about a user.
+///
+/// * `user_collection` - MongoDB collection of users.
+/// * `invoice_collection` - MongoDB collection of invoices.
+/// * `subscription_collection` - MongoDB collection of customer subscriptions.
+/// * `order_invoicing_collection` - MongoDB collection of order invoicing states.
+/// * `audit_collection` - MongoDB collection of audit entries.
+/// * `user_id` - UUID of the user to export the data of.
+pub async fn export_user_data(
+    user_collection: &Collection<User>,
+    invoice_collection: &Collection<Invoice>,
+    subscription_collection: &Collection<CustomerSubscription>,
+    order_invoicing_collection: &Collection<OrderInvoicing>,
+    audit_collection: &Collection<AuditEntry>,
+    user_id: Uuid,
+) -> Result<UserDataExport> {
+    let user = user_collection
+        .find_one(doc! {"_id": user_id}, None)
+        .await?;
+    let addresses = user
+        .as_ref()
+        .map(|user| user.addresses.clone())
+        .unwrap_or_default();
+    let invoices: Vec<Invoice> = invoice_collection
+        .find(
+            doc! {"user_id": user_id},
+            FindOptions::builder().sort(doc! {"issued_at": 1}).build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    let subscriptions = subscription_collection
+        .find(
+            doc! {"user_id": user_id},
+            FindOptions::builder().sort(doc! {"started_at": 1}).build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    let order_invoicing = order_invoicing_collection
+        .find(
+            doc! {"order.user_id": user_id},
+            FindOptions::builder().sort(doc! {"validated_at": 1}).build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    let invoice_ids: Vec<Uuid> = invoices.iter().map(|invoice| invoice._id).collect();
+    let audit_entries = audit_collection
+        .find(
+            doc! {"$or": [
+                {"actor_id": user_id},
+                {"invoice_id": {"$in": invoice_ids}},
+            ]},
+            FindOptions::builder().sort(doc! {"occurred_at": 1}).build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    Ok(UserDataExport {
+        user_id,
+        exported_at: DateTime::now(),
+        user,
+        addresses,
+        invoices,
+        subscriptions,
+        order_invoicing,
+        audit_entries,
+    })
+}
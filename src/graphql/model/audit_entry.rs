@@ -0,0 +1,173 @@
+use async_graphql::{Enum, InputObject, SimpleObject};
+use bson::{doc, DateTime, Document, Uuid};
+use serde::{Deserialize, Serialize};
+
+use crate::authorization::AuthorizedUser;
+
+/// Action recorded in the audit log.
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditAction {
+    /// An invoice was created.
+    InvoiceCreated,
+    /// The payment status of an invoice changed.
+    StatusChanged,
+    /// An overdue invoice reached a new dunning level.
+    DunningLevelChanged,
+    /// Sending an invoice to the customer was attempted.
+    DeliveryAttempted,
+    /// A document of an invoice was rendered and stored.
+    DocumentRendered,
+    /// A document of an invoice was downloaded.
+    DocumentDownloaded,
+    /// An invoice was read through the GraphQL API.
+    InvoiceRead,
+    /// An administrator verified the invoice hash chains.
+    ChainVerified,
+    /// An administrator read the audit log.
+    AuditLogRead,
+    /// The personal data of a deleted user was pseudonymized.
+    UserErased,
+    /// An invoice was purged after its retention period.
+    InvoicePurged,
+    /// The pseudonymized projection of a deleted user was purged after all of its invoices.
+    UserPurged,
+    /// Order invoicing states and subscriptions were purged after their retention period.
+    RecordsPurged,
+    /// An administrator exported everything stored about a user.
+    UserDataExported,
+    /// An invoice was superseded by a corrective invoice with corrected data.
+    InvoiceReissued,
+    /// An invoice was cancelled.
+    InvoiceCancelled,
+    /// A credit note was issued for an invoice.
+    CreditNoteIssued,
+    /// An invoice was sent to the customer again on request.
+    InvoiceResent,
+    /// The vendor address used on invoices was set by back-office.
+    VendorAddressSet,
+    /// The proformas of an order were converted to an invoice when the order was validated.
+    ProformaConverted,
+    /// The billing mode of a user was set by back-office.
+    BillingModeSet,
+}
+
+/// Kind of actor which performed an audited action.
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActorType {
+    /// Authenticated user, identified by the gateway.
+    User,
+    /// Anonymous request without authenticated user.
+    Anonymous,
+    /// The invoice service itself, e.g. an event handler or background job.
+    System,
+}
+
+/// Append-only entry of the `invoice_audit` collection.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
+pub struct AuditEntry {
+    pub _id: Uuid,
+    /// Audited action.
+    pub action: AuditAction,
+    /// UUID of the affected invoice, if any.
+    pub invoice_id: Option<Uuid>,
+    /// Kind of actor which performed the action.
+    pub actor_type: ActorType,
+    /// UUID of the user which performed the action.
+    pub actor_id: Option<Uuid>,
+    /// Trace id of the request or event which caused the action.
+    pub trace_id: Option<String>,
+    /// Timestamp of the action.
+    pub occurred_at: DateTime,
+    /// Additional details of the action.
+    pub details: Option<String>,
+}
+
+impl AuditEntry {
+    /// Creates an entry of an action performed by the service itself.
+    pub fn new(action: AuditAction, invoice_id: Option<Uuid>) -> Self {
+        Self {
+            _id: Uuid::new(),
+            action,
+            invoice_id,
+            actor_type: ActorType::System,
+            actor_id: None,
+            trace_id: None,
+            occurred_at: DateTime::now(),
+            details: None,
+        }
+    }
+
+    /// Attributes the action to the user of a request, or to an anonymous actor if unauthenticated.
+    pub fn by_user(mut self, user: Option<&AuthorizedUser>) -> Self {
+        match user {
+            Some(user) => {
+                self.actor_type = ActorType::User;
+                self.actor_id = Some(user.id);
+            }
+            None => self.actor_type = ActorType::Anonymous,
+        }
+        self
+    }
+
+    /// Sets the trace id of the request or event which caused the action.
+    pub fn with_trace_id(mut self, trace_id: Option<String>) -> Self {
+        self.trace_id = trace_id;
+        self
+    }
+
+    /// Sets additional details of the action.
+    pub fn with_details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+}
+
+/// Filter for audit log queries. All set criteria must match.
+#[derive(Debug, InputObject, Default, Clone)]
+pub struct AuditFilter {
+    /// Only entries of the invoice with this UUID.
+    pub invoice_id: Option<Uuid>,
+    /// Only entries of actions performed by the user with this UUID.
+    pub actor_id: Option<Uuid>,
+    /// Only entries of this action.
+    pub action: Option<AuditAction>,
+    /// Only entries of this trace.
+    pub trace_id: Option<String>,
+    /// Only entries which occurred at or after this timestamp.
+    pub from: Option<DateTime>,
+    /// Only entries which occurred before this timestamp.
+    pub to: Option<DateTime>,
+}
+
+impl AuditFilter {
+    /// Builds the MongoDB filter document of the filter.
+    pub fn to_document(&self) -> Document {
+        let mut filter = doc! {};
+        if let Some(invoice_id) = self.invoice_id {
+            filter.insert("invoice_id", invoice_id);
+        }
+        if let Some(actor_id) = self.actor_id {
+            filter.insert("actor_id", actor_id);
+        }
+        if let Some(action) = self.action {
+            filter.insert(
+                "action",
+                bson::to_bson(&action).expect("Audit action is always serializable."),
+            );
+        }
+        if let Some(trace_id) = &self.trace_id {
+            filter.insert("trace_id", trace_id);
+        }
+        let mut occurred_at = doc! {};
+        if let Some(from) = self.from {
+            occurred_at.insert("$gte", from);
+        }
+        if let Some(to) = self.to {
+            occurred_at.insert("$lt", to);
+        }
+        if !occurred_at.is_empty() {
+            filter.insert("occurred_at", occurred_at);
+        }
+        filter
+    }
+}
@@ -0,0 +1,62 @@
+use async_graphql::{Enum, Error, Result, SimpleObject};
+use bson::{doc, DateTime, Uuid};
+use chrono::{Datelike, Months, TimeZone, Utc};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use super::foreign_types::User;
+
+/// How the validated orders of a user are invoiced.
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum BillingMode {
+    /// Each validated order is invoiced on its own.
+    #[default]
+    PerOrder,
+    /// Validated orders are accumulated and invoiced in one collective invoice per calendar month.
+    Monthly,
+}
+
+/// Billing period of a collective invoice.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Copy, Clone, PartialEq, Eq)]
+pub struct BillingPeriod {
+    /// Start of the period, inclusive.
+    pub start: DateTime,
+    /// End of the period, exclusive.
+    pub end: DateTime,
+}
+
+impl BillingPeriod {
+    /// Returns the calendar month in UTC which contains a timestamp.
+    pub fn month_of(timestamp: DateTime) -> Self {
+        let timestamp = timestamp.to_chrono();
+        let start = Utc
+            .with_ymd_and_hms(timestamp.year(), timestamp.month(), 1, 0, 0, 0)
+            .single()
+            .expect("First of a month at midnight is always a valid UTC timestamp.");
+        let end = start
+            .checked_add_months(Months::new(1))
+            .expect("Next month of a valid timestamp is in range.");
+        Self {
+            start: DateTime::from_chrono(start),
+            end: DateTime::from_chrono(end),
+        }
+    }
+}
+
+/// Returns the billing mode of a user.
+///
+/// * `collection` - MongoDB collection of users.
+/// * `user_id` - UUID of the user.
+pub async fn query_billing_mode(
+    collection: &Collection<User>,
+    user_id: Uuid,
+) -> Result<BillingMode> {
+    collection
+        .find_one(doc! {"_id": user_id}, None)
+        .await?
+        .map(|user| user.billing_mode)
+        .ok_or(Error::new(format!(
+            "User with UUID: `{}` not found.",
+            user_id
+        )))
+}
@@ -0,0 +1,150 @@
+use async_graphql::{Error, Result};
+use bson::doc;
+use futures::TryStreamExt;
+use log::warn;
+use mongodb::Collection;
+
+use super::{
+    super::query::query_object,
+    invoice::{Invoice, InvoiceDocumentType},
+    money::Money,
+};
+
+/// Maximum number of attempts to reserve a credit while other credit notes of the invoice are issued concurrently.
+const MAX_CREDIT_RESERVATION_ATTEMPTS: usize = 5;
+
+/// Reserves part of the total of an invoice for a credit note and returns the reserved amount.
+///
+/// The credited amount is counted on the invoice and only advanced if it is unchanged since it was read,
+/// so concurrent credit notes cannot exceed the total.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `invoice` - Credited invoice.
+/// * `amount` - Amount to credit, the whole uncredited amount if `None`.
+pub async fn reserve_credit(
+    collection: &Collection<Invoice>,
+    invoice: &Invoice,
+    amount: Option<i64>,
+) -> Result<i64> {
+    for _ in 0..MAX_CREDIT_RESERVATION_ATTEMPTS {
+        let credited = match query_object(collection, invoice._id).await?.credited_amount {
+            Some(credited) => credited,
+            None => initialize_credited_amount(collection, invoice).await?,
+        };
+        let amount = credit_to_reserve(&invoice.total, credited, amount)?;
+        let result = collection
+            .update_one(
+                doc! {"_id": invoice._id, "credited_amount": credited},
+                doc! {"$inc": {"credited_amount": amount}},
+                None,
+            )
+            .await?;
+        if result.modified_count == 1 {
+            return Ok(amount);
+        }
+    }
+    Err(Error::new(format!(
+        "Invoice of UUID: `{}` is credited concurrently, try again.",
+        invoice._id
+    )))
+}
+
+/// Amount to credit of an invoice total of which `credited` is already credited.
+///
+/// * `total` - Total of the credited invoice.
+/// * `credited` - Amount already credited.
+/// * `amount` - Amount to credit, the whole uncredited amount if `None`.
+fn credit_to_reserve(total: &Money, credited: i64, amount: Option<i64>) -> Result<i64> {
+    let remaining = total.amount - credited;
+    let amount = amount.unwrap_or(remaining);
+    if amount <= 0 || amount > remaining {
+        return Err(Error::new(format!(
+            "Credited amount must be positive and at most the uncredited amount of {}.",
+            Money::new(remaining, &total.currency)?
+        )));
+    }
+    Ok(amount)
+}
+
+/// Initializes the credited amount of an invoice by the credit notes issued before it was counted.
+///
+/// Issued invoices store the credited amount as `null` until they are first credited.
+/// Returns the credited amount the invoice is initialized with.
+async fn initialize_credited_amount(
+    collection: &Collection<Invoice>,
+    invoice: &Invoice,
+) -> Result<i64> {
+    let credit_notes: Vec<Invoice> = collection
+        .find(
+            doc! {
+                "related_invoice_id": invoice._id,
+                "document_type": bson::to_bson(&InvoiceDocumentType::CreditNote)?,
+            },
+            None,
+        )
+        .await?
+        .try_collect()
+        .await?;
+    let credited = Money::sum(
+        &invoice.total.currency,
+        credit_notes.iter().map(|credit_note| &credit_note.total),
+    )?;
+    collection
+        .update_one(
+            doc! {"_id": invoice._id, "credited_amount": null},
+            doc! {"$set": {"credited_amount": -credited.amount}},
+            None,
+        )
+        .await?;
+    Ok(-credited.amount)
+}
+
+/// Releases the reservation of a credit note which could not be issued.
+pub async fn release_credit(collection: &Collection<Invoice>, invoice: &Invoice, amount: i64) {
+    if let Err(e) = collection
+        .update_one(
+            doc! {"_id": invoice._id},
+            doc! {"$inc": {"credited_amount": -amount}},
+            None,
+        )
+        .await
+    {
+        warn!(
+            "Releasing credited amount of invoice of UUID: `{}` failed: {}",
+            invoice._id, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql::model::invoice::tests::invoice;
+
+    #[test]
+    fn stores_null_credited_amount_of_uncredited_invoices() {
+        let invoice = bson::to_document(&invoice(10000)).unwrap();
+        assert_eq!(invoice.get("credited_amount"), Some(&bson::Bson::Null));
+    }
+
+    #[test]
+    fn reserves_whole_uncredited_amount_of_freshly_issued_invoice() {
+        let invoice = invoice(10000);
+        assert_eq!(invoice.credited_amount, None);
+        assert_eq!(credit_to_reserve(&invoice.total, 0, None).unwrap(), 10000);
+        assert_eq!(credit_to_reserve(&invoice.total, 2500, None).unwrap(), 7500);
+    }
+
+    #[test]
+    fn reserves_partial_credit_up_to_uncredited_amount() {
+        let invoice = invoice(10000);
+        assert_eq!(
+            credit_to_reserve(&invoice.total, 2500, Some(7500)).unwrap(),
+            7500
+        );
+        assert!(credit_to_reserve(&invoice.total, 2500, Some(7501)).is_err());
+        assert!(credit_to_reserve(&invoice.total, 0, Some(0)).is_err());
+        assert!(credit_to_reserve(&invoice.total, 0, Some(-100)).is_err());
+        assert!(credit_to_reserve(&invoice.total, 10000, None).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use async_graphql::{Enum, SimpleObject};
+use bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// Status of the delivery of an invoice to the customer.
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum DeliveryStatus {
+    /// Invoice is not yet sent.
+    #[default]
+    Pending,
+    /// Invoice was sent successfully.
+    Delivered,
+    /// Sending the invoice failed, it is retried until the maximum number of attempts is reached.
+    Failed,
+}
+
+/// Attempt to send an invoice to the customer.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
+pub struct DeliveryAttempt {
+    /// Timestamp of the attempt.
+    pub attempted_at: DateTime,
+    /// Whether the invoice was handed over to the binding successfully.
+    pub success: bool,
+    /// Error message of a failed attempt.
+    pub error: Option<String>,
+}
+
+/// Delivery of an invoice to the customer by email.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone, Default)]
+pub struct InvoiceDelivery {
+    /// Email address the invoice is sent to.
+    pub recipient: Option<String>,
+    /// Current delivery status.
+    pub status: DeliveryStatus,
+    /// Delivery attempts in chronological order.
+    pub attempts: Vec<DeliveryAttempt>,
+}
@@ -0,0 +1,155 @@
+use async_graphql::{Enum, SimpleObject};
+use bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::config::env_var;
+
+use super::money::Money;
+
+/// Escalation level of the dunning process of an overdue invoice, ordered by severity.
+#[derive(
+    Debug, Enum, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum DunningLevel {
+    /// Friendly payment reminder.
+    Reminder,
+    /// First formal notice.
+    FirstNotice,
+    /// Final notice, which charges a dunning fee.
+    FinalNotice,
+}
+
+impl DunningLevel {
+    /// All dunning levels in order of escalation.
+    pub const ALL: [DunningLevel; 3] = [
+        DunningLevel::Reminder,
+        DunningLevel::FirstNotice,
+        DunningLevel::FinalNotice,
+    ];
+}
+
+/// Record of a dunning level reached by an invoice.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
+pub struct DunningRecord {
+    /// Reached dunning level.
+    pub level: DunningLevel,
+    /// Timestamp when the level was reached.
+    pub escalated_at: DateTime,
+    /// Dunning fee charged with this level.
+    pub fee: Option<Money>,
+}
+
+/// Configuration of the dunning levels.
+#[derive(Debug, Clone)]
+pub struct DunningPolicy {
+    /// Days after the due date when a reminder is sent.
+    pub reminder_days: u32,
+    /// Days after the due date when the first notice is sent.
+    pub first_notice_days: u32,
+    /// Days after the due date when the final notice is sent.
+    pub final_notice_days: u32,
+    /// Fee in minor units of the invoice currency charged with the final notice.
+    pub final_notice_fee: i64,
+}
+
+impl DunningPolicy {
+    /// Reads the policy from `$DUNNING_REMINDER_DAYS`, `$DUNNING_FIRST_NOTICE_DAYS`, `$DUNNING_FINAL_NOTICE_DAYS` and `$DUNNING_FINAL_NOTICE_FEE`.
+    pub fn from_env() -> Self {
+        Self {
+            reminder_days: env_var("DUNNING_REMINDER_DAYS").unwrap_or(7),
+            first_notice_days: env_var("DUNNING_FIRST_NOTICE_DAYS").unwrap_or(14),
+            final_notice_days: env_var("DUNNING_FINAL_NOTICE_DAYS").unwrap_or(28),
+            final_notice_fee: env_var("DUNNING_FINAL_NOTICE_FEE").unwrap_or(500),
+        }
+    }
+
+    /// Number of days after the due date when a level is reached.
+    pub fn days_overdue(&self, level: DunningLevel) -> u32 {
+        match level {
+            DunningLevel::Reminder => self.reminder_days,
+            DunningLevel::FirstNotice => self.first_notice_days,
+            DunningLevel::FinalNotice => self.final_notice_days,
+        }
+    }
+
+    /// Returns the level following the current level of an invoice which is overdue for `days_overdue` days, if reached.
+    ///
+    /// Levels are stepped through in order, so an invoice reaching several levels at once is escalated one level at a time.
+    pub fn next_level(
+        &self,
+        current: Option<DunningLevel>,
+        days_overdue: i64,
+    ) -> Option<DunningLevel> {
+        DunningLevel::ALL
+            .into_iter()
+            .find(|level| current.is_none_or(|current| *level > current))
+            .filter(|level| days_overdue >= self.days_overdue(*level).into())
+    }
+
+    /// Returns the fee charged when a level is reached, in the currency of the invoice total.
+    pub fn fee(&self, level: DunningLevel, total: &Money) -> Option<Money> {
+        match level {
+            DunningLevel::FinalNotice if self.final_notice_fee > 0 => Some(Money {
+                amount: self.final_notice_fee,
+                currency: total.currency.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> DunningPolicy {
+        DunningPolicy {
+            reminder_days: 7,
+            first_notice_days: 14,
+            final_notice_days: 28,
+            final_notice_fee: 500,
+        }
+    }
+
+    #[test]
+    fn reaches_levels_at_their_days_overdue() {
+        let policy = policy();
+        assert_eq!(policy.next_level(None, 6), None);
+        assert_eq!(policy.next_level(None, 7), Some(DunningLevel::Reminder));
+        let reminder = Some(DunningLevel::Reminder);
+        assert_eq!(policy.next_level(reminder, 13), None);
+        assert_eq!(policy.next_level(reminder, 14), Some(DunningLevel::FirstNotice));
+        let first_notice = Some(DunningLevel::FirstNotice);
+        assert_eq!(policy.next_level(first_notice, 27), None);
+        assert_eq!(policy.next_level(first_notice, 28), Some(DunningLevel::FinalNotice));
+        assert_eq!(policy.next_level(Some(DunningLevel::FinalNotice), 365), None);
+    }
+
+    #[test]
+    fn steps_through_levels_one_at_a_time() {
+        let policy = policy();
+        let mut level = None;
+        let mut reached = vec![];
+        while let Some(next) = policy.next_level(level, 100) {
+            reached.push(next);
+            level = Some(next);
+        }
+        assert_eq!(reached, DunningLevel::ALL.to_vec());
+    }
+
+    #[test]
+    fn charges_fee_in_invoice_currency_with_final_notice_only() {
+        let total = Money {
+            amount: 10000,
+            currency: "CHF".to_string(),
+        };
+        assert_eq!(policy().fee(DunningLevel::FirstNotice, &total), None);
+        let fee = policy().fee(DunningLevel::FinalNotice, &total).unwrap();
+        assert_eq!((fee.amount, fee.currency.as_str()), (500, "CHF"));
+        let free = DunningPolicy {
+            final_notice_fee: 0,
+            ..policy()
+        };
+        assert_eq!(free.fee(DunningLevel::FinalNotice, &total), None);
+    }
+}
@@ -1,9 +1,11 @@
 use async_graphql::SimpleObject;
-use bson::{doc, Bson, Uuid};
+use bson::{doc, Bson, DateTime, Uuid};
 use serde::{Deserialize, Serialize};
 
+use super::{billing::BillingMode, money::Money};
 use crate::event::http_event_service::{
-    UserAddressEventData, UserEventData, VendorAddressEventData,
+    DiscountEventData, ProductVariantEventData, ProductVariantVersionEventData,
+    ShipmentMethodEventData, UserAddressEventData, UserEventData, VendorAddressEventData,
 };
 
 /// Foreign type of a user.
@@ -17,6 +19,26 @@ pub struct User {
     pub last_name: String,
     #[graphql(skip)]
     pub addresses: Vec<UserAddress>,
+    /// Preferred locale of the user as BCP 47 language tag.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub preferred_locale: Option<String>,
+    /// Customer group of the user, used to select payment terms.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub customer_group: Option<String>,
+    /// Email address of the user, which invoices are sent to.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub email: Option<String>,
+    /// Timestamp when the personal data of the user was pseudonymized after the user was deleted.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub erased_at: Option<DateTime>,
+    /// How validated orders of the user are invoiced.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub billing_mode: BillingMode,
 }
 
 impl From<UserEventData> for User {
@@ -26,6 +48,11 @@ impl From<UserEventData> for User {
             first_name: value.first_name,
             last_name: value.last_name,
             addresses: vec![],
+            preferred_locale: value.preferred_locale,
+            customer_group: value.customer_group,
+            email: value.email,
+            erased_at: None,
+            billing_mode: BillingMode::default(),
         }
     }
 }
@@ -98,6 +125,14 @@ pub struct VendorAddress {
     pub country: String,
     #[graphql(skip)]
     pub company_name: String,
+    /// UUID of the vendor owning the address, `None` for the shop's own vendor address.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub vendor_id: Option<Uuid>,
+    /// VAT identification number of the vendor.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub vat_id: Option<String>,
 }
 
 impl From<VendorAddressEventData> for VendorAddress {
@@ -110,6 +145,112 @@ impl From<VendorAddressEventData> for VendorAddress {
             postal_code: value.postal_code,
             country: value.country,
             company_name: value.company_name,
+            vendor_id: value.vendor_id,
+            vat_id: value.vat_id,
+        }
+    }
+}
+
+/// Foreign type of a product variant, replicated to map order items to their vendor.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProductVariant {
+    pub _id: Uuid,
+    /// UUID of the vendor selling the product variant, `None` if sold by the shop itself.
+    pub vendor_id: Option<Uuid>,
+}
+
+impl From<ProductVariantEventData> for ProductVariant {
+    fn from(value: ProductVariantEventData) -> Self {
+        Self {
+            _id: value.id,
+            vendor_id: value.vendor_id,
+        }
+    }
+}
+
+/// Foreign type of a product variant version, replicated to describe ordered products on invoices.
+///
+/// Versions are immutable, an order item references the exact version which was ordered.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProductVariantVersion {
+    pub _id: Uuid,
+    /// UUID of the product variant the version belongs to.
+    pub product_variant_id: Uuid,
+    /// Name of the product variant in this version.
+    pub name: String,
+    /// Stock keeping unit of the product variant.
+    pub sku: Option<String>,
+    /// Description of the product variant in this version.
+    pub description: Option<String>,
+    /// Characteristics of the product variant in this version, e.g. its color or size.
+    pub characteristics: Vec<ProductCharacteristic>,
+}
+
+impl From<ProductVariantVersionEventData> for ProductVariantVersion {
+    fn from(value: ProductVariantVersionEventData) -> Self {
+        Self {
+            _id: value.id,
+            product_variant_id: value.product_variant_id,
+            name: value.name,
+            sku: value.sku,
+            description: value.description,
+            characteristics: value.characteristics,
+        }
+    }
+}
+
+/// Characteristic of a product variant version, e.g. `Color: Red`.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
+pub struct ProductCharacteristic {
+    /// Name of the characteristic.
+    pub name: String,
+    /// Value of the characteristic.
+    pub value: String,
+}
+
+/// Foreign type of a discount, replicated to explain the discounted amounts of order items.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Discount {
+    pub _id: Uuid,
+    /// Fraction of the price which is discounted, e.g. `0.1` for 10%.
+    pub discount: f64,
+    /// Description of the discount, printed on invoices.
+    pub description: Option<String>,
+}
+
+impl From<DiscountEventData> for Discount {
+    fn from(value: DiscountEventData) -> Self {
+        Self {
+            _id: value.id,
+            discount: value.discount,
+            description: value.description,
+        }
+    }
+}
+
+/// Foreign type of a shipment method, replicated to charge shipping costs on invoices.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShipmentMethod {
+    pub _id: Uuid,
+    /// Name of the shipment method, printed on invoices.
+    pub name: String,
+    /// Fee charged once per order shipping items with the method.
+    pub base_fee: Money,
+    /// Fee charged per shipped item.
+    pub fee_per_item: Money,
+    /// VAT rate included in the fees, e.g. `0.19` for 19%, `None` if not known.
+    #[serde(default)]
+    pub tax_rate: Option<f64>,
+}
+
+impl From<ShipmentMethodEventData> for ShipmentMethod {
+    fn from(value: ShipmentMethodEventData) -> Self {
+        Self {
+            _id: value.id,
+            name: value.name,
+            base_fee: value.base_fee,
+            fee_per_item: value.fee_per_item,
+            tax_rate: value.tax_rate,
         }
     }
 }
@@ -1,153 +1,782 @@
-use async_graphql::{Error, Result, SimpleObject};
+use async_graphql::{Enum, Error, Result, SimpleObject};
 use bson::{doc, DateTime, Uuid};
 use mongodb::{options::FindOneOptions, Collection};
 use serde::{Deserialize, Serialize};
 
-use crate::event::http_event_service::{HttpEventServiceState, OrderEventData};
+use crate::{
+    config::ServiceContext,
+    event::http_event_service::{HttpEventServiceState, OrderEventData, OrderItemEventData},
+    graphql::mutation_input_structs::CreateManualInvoiceInput,
+    i18n::Locale,
+    signature::{InvoiceSignature, InvoiceSigner},
+};
 
 use super::{
     super::query::query_object,
+    billing::BillingPeriod,
+    delivery::InvoiceDelivery,
+    dunning::{DunningLevel, DunningRecord},
     foreign_types::{User, UserAddress, VendorAddress},
+    invoice_chain::{issue_in_sequence, sequence_id_of_vendor, InvoiceNumberSequence},
+    invoice_line_item::{
+        invoiced_shipping_line_items, order_item_line_items, order_shipping_line_items,
+        InvoiceLineItem,
+    },
+    invoice_party::InvoiceParty,
+    money::Money,
+    payment_terms::{query_payment_terms, PaymentTerms, PaymentTermsRule},
+    recurring::CustomerSubscription,
+    stored_document::StoredDocument,
 };
 
-static INVOICE_TERMS: &str = "This invoice is created according the the companies terms and conditions specified on the website.";
-
-/// Invoice of an order.
+/// Invoice of an order, or a manual invoice created by back-office.
 #[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
 pub struct Invoice {
     pub _id: Uuid,
-    pub order_id: Uuid,
+    /// UUID of the invoiced order, `None` for manual invoices.
+    pub order_id: Option<Uuid>,
+    /// UUID of the shipment whose order items are invoiced, `None` for invoices of whole orders.
+    #[serde(default)]
+    pub shipment_id: Option<Uuid>,
+    /// UUIDs of the orders a collective invoice consolidates, empty for other invoices.
+    #[serde(default)]
+    pub order_ids: Vec<Uuid>,
+    /// Billing period of a collective or subscription invoice, `None` for other invoices.
+    #[serde(default)]
+    pub billing_period: Option<BillingPeriod>,
+    /// UUID of the invoiced subscription, `None` for other invoices.
+    #[serde(default)]
+    pub subscription_id: Option<Uuid>,
+    /// Type of the document, a regular invoice unless issued by back-office.
+    #[serde(default)]
+    pub document_type: InvoiceDocumentType,
+    /// UUID of the invoice a corrective invoice or credit note refers to.
+    #[serde(default)]
+    pub related_invoice_id: Option<Uuid>,
+    /// Number of the invoice a corrective invoice or credit note refers to.
+    #[serde(default)]
+    pub related_invoice_number: Option<String>,
+    /// Reason for a corrective invoice or credit note.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// UUID of the invoiced user.
+    #[serde(default = "nil_uuid")]
+    pub user_id: Uuid,
+    /// UUID of the vendor issuing the invoice, `None` for the shop's own vendor.
+    #[serde(default)]
+    pub vendor_id: Option<Uuid>,
+    /// Invoice number, sequential per vendor.
+    #[serde(default)]
+    pub invoice_number: String,
+    /// Numbering sequence the invoice is issued in.
+    #[serde(default)]
+    pub sequence_id: Option<String>,
+    /// Number of the invoice in its numbering sequence.
+    #[serde(default)]
+    pub sequence_number: Option<i64>,
+    /// Hex encoded SHA-256 hash over the legal content of the invoice and the previous hash.
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// Hash of the preceding invoice in the numbering sequence.
+    #[serde(default)]
+    pub previous_hash: Option<String>,
+    /// Signature over the hash, made at issuance with the configured signing key.
+    #[serde(default)]
+    pub signature: Option<InvoiceSignature>,
     pub issued_at: DateTime,
     pub content: String,
+    /// BCP 47 language tag of the locale the content is rendered in.
+    #[serde(default = "default_locale")]
+    pub locale: String,
     pub user_address: UserAddress,
     pub vendor_address: VendorAddress,
     pub vat_number: Option<String>,
+    /// VAT identification number of the issuing vendor.
+    #[serde(default)]
+    pub vendor_vat_id: Option<String>,
+    /// Snapshot of the customer at the time of issuance.
+    #[serde(default)]
+    pub customer: InvoiceParty,
+    /// Snapshot of the issuing vendor at the time of issuance.
+    #[serde(default)]
+    pub vendor: InvoiceParty,
+    /// Invoiced lines.
+    #[serde(default)]
+    pub line_items: Vec<InvoiceLineItem>,
+    /// Total compensatable amount of the invoice.
+    #[serde(default)]
+    pub total: Money,
+    /// Payment terms of the invoice.
+    #[serde(default)]
+    pub payment_terms: PaymentTerms,
+    /// Timestamp when the invoice is due, `None` for invoices issued before payment terms were supported.
+    #[serde(default)]
+    pub due_at: Option<DateTime>,
+    /// Deadline for the early payment discount.
+    #[serde(default)]
+    pub discount_deadline: Option<DateTime>,
+    /// Discount on the total if paid before the discount deadline.
+    #[serde(default)]
+    pub early_payment_discount: Option<Money>,
+    /// Payment status of the invoice.
+    #[serde(default)]
+    pub status: InvoiceStatus,
+    /// Timestamp when the invoice was marked as paid.
+    #[serde(default)]
+    pub paid_at: Option<DateTime>,
+    /// Timestamp when the invoice was cancelled.
+    #[serde(default)]
+    pub cancelled_at: Option<DateTime>,
+    /// Reason for the cancellation of the invoice.
+    #[serde(default)]
+    pub cancellation_reason: Option<String>,
+    /// UUID of the corrective invoice which superseded this invoice.
+    #[serde(default)]
+    pub superseded_by: Option<Uuid>,
+    /// UUID of the invoice a proforma was converted to when its order was validated.
+    #[serde(default)]
+    pub converted_to: Option<Uuid>,
+    /// Part of the total in minor units which is credited by credit notes, `None` until the invoice is credited.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub credited_amount: Option<i64>,
+    /// Highest dunning level reached by the invoice.
+    #[serde(default)]
+    pub dunning_level: Option<DunningLevel>,
+    /// Dunning levels reached by the invoice, in order of escalation.
+    #[serde(default)]
+    pub dunning_history: Vec<DunningRecord>,
+    /// Delivery of the invoice to the customer.
+    #[serde(default)]
+    pub delivery: InvoiceDelivery,
+    /// Generated documents persisted in object storage.
+    #[serde(default)]
+    pub documents: Vec<StoredDocument>,
+    /// Snapshot of the invoiced order without payment authorization, used to republish invoice events.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub order: Option<OrderEventData>,
+    /// Event announcing the issued document which is not published yet, recorded when the document is stored.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub pending_event: Option<IssuedEvent>,
+    /// Events of changes of the invoice which are not published yet, recorded in the same write as the change.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub pending_events: Vec<PendingEvent>,
+}
+
+/// Event which announces an issued document.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IssuedEvent {
+    /// Invoice of an order, with the invoiced order.
+    Invoice,
+    /// Proforma of a pending order, with the order.
+    Proforma,
+    /// Manual invoice of free-form lines.
+    ManualInvoice,
+    /// Collective invoice of the orders of a billing period.
+    CollectiveInvoice,
+    /// Invoice of a billing period or a plan change of a subscription.
+    SubscriptionInvoice,
+    /// Corrective invoice which supersedes an invoice.
+    CorrectiveInvoice,
+    /// Credit note of an invoice or a plan change of a subscription.
+    CreditNote,
+}
+
+impl IssuedEvent {
+    /// Topic the event is published to.
+    pub fn topic(&self) -> &'static str {
+        match self {
+            IssuedEvent::Invoice => "invoice/invoice/created",
+            IssuedEvent::Proforma => "invoice/proforma/created",
+            IssuedEvent::ManualInvoice => "invoice/manual-invoice/created",
+            IssuedEvent::CollectiveInvoice => "invoice/collective-invoice/created",
+            IssuedEvent::SubscriptionInvoice => "invoice/subscription-invoice/created",
+            IssuedEvent::CorrectiveInvoice => "invoice/invoice/reissued",
+            IssuedEvent::CreditNote => "invoice/credit-note/issued",
+        }
+    }
+}
+
+/// Event of a change of a stored invoice, e.g. of its status, which is not published yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingEvent {
+    /// UUID of the event, which identifies it when it is cleared after publishing.
+    pub _id: Uuid,
+    /// Topic the event is published to.
+    pub topic: String,
+    /// JSON encoded data of the event, stored as text so it is published as it was serialized.
+    pub data: String,
+    /// Timestamp when the event was recorded.
+    pub recorded_at: DateTime,
+}
+
+impl PendingEvent {
+    /// Serializes the data of an event to publish to a topic.
+    pub fn new<T: Serialize>(topic: &str, data: &T) -> Result<Self> {
+        Ok(Self {
+            _id: Uuid::new(),
+            topic: topic.to_string(),
+            data: serde_json::to_string(data)?,
+            recorded_at: DateTime::now(),
+        })
+    }
+}
+
+/// Payment status of an invoice.
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum InvoiceStatus {
+    /// Invoice is issued and awaits payment.
+    #[default]
+    Open,
+    /// Invoice is paid.
+    Paid,
+    /// Invoice is cancelled or superseded by a corrective invoice.
+    Cancelled,
+}
+
+/// Type of an invoice document.
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum InvoiceDocumentType {
+    /// Regular invoice of an order.
+    #[default]
+    Invoice,
+    /// Invoice which replaces an invoice with corrected data.
+    CorrectiveInvoice,
+    /// Credit note which refunds all or part of an invoice.
+    CreditNote,
+    /// Non-binding proforma invoice of a pending order, numbered in its own sequence.
+    Proforma,
+}
+
+impl InvoiceDocumentType {
+    /// Prefix of the formatted number of documents of this type.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            InvoiceDocumentType::Invoice => "INV",
+            InvoiceDocumentType::CorrectiveInvoice => "COR",
+            InvoiceDocumentType::CreditNote => "CN",
+            InvoiceDocumentType::Proforma => "PRO",
+        }
+    }
+
+    /// Numbering sequence documents of this type are issued in for a vendor.
+    ///
+    /// Proformas are numbered separately, as they are not part of the vendor's gapless invoice sequence.
+    pub fn sequence_id(&self, vendor_id: Option<Uuid>) -> String {
+        match self {
+            InvoiceDocumentType::Proforma => {
+                format!("proforma-{}", sequence_id_of_vendor(vendor_id))
+            }
+            _ => sequence_id_of_vendor(vendor_id),
+        }
+    }
+
+    /// Message key of the title of documents of this type.
+    pub fn title_key(&self) -> &'static str {
+        match self {
+            InvoiceDocumentType::Invoice => "invoice.title",
+            InvoiceDocumentType::CorrectiveInvoice => "invoice.title_corrective",
+            InvoiceDocumentType::CreditNote => "invoice.title_credit_note",
+            InvoiceDocumentType::Proforma => "invoice.title_proforma",
+        }
+    }
 }
 
 impl Invoice {
-    /// Creates a new invoice from `OrderEventData` and `HttpEventServiceState` (containing the database connections).
+    /// Creates, issues and stores a new invoice or proforma of a vendor from `OrderEventData` and `HttpEventServiceState` (containing the database connections).
+    ///
+    /// The invoice is stored with its creation event pending, which is cleared once the event is published.
+    ///
+    /// * `order_event_data` - Order to invoice.
+    /// * `vendor_id` - Vendor issuing the invoice, `None` for the shop's own vendor.
+    /// * `order_items` - Order items of the order which are sold by the vendor.
+    /// * `shipment_id` - UUID of the shipment the order items are invoiced for, `None` when invoicing whole orders.
+    /// * `document_type` - `InvoiceDocumentType::Invoice` for validated orders, `InvoiceDocumentType::Proforma` for pending orders.
+    /// * `state` - Service state containing database connections.
     pub async fn new(
         order_event_data: OrderEventData,
+        vendor_id: Option<Uuid>,
+        order_items: Vec<OrderItemEventData>,
+        shipment_id: Option<Uuid>,
+        document_type: InvoiceDocumentType,
         state: &HttpEventServiceState,
     ) -> Result<Self, Error> {
-        let _id = Uuid::new();
-        let (
-            issued_at,
-            issued_at_string,
-            order_item_invoice_overview,
+        let (user_address, vendor_address, user) = invoice_attribute_setup(
+            order_event_data.user_id,
+            order_event_data.invoice_address_id,
+            vendor_id,
+            state,
+        )
+        .await?;
+        let currency = order_event_data.currency()?;
+        let mut line_items = order_item_line_items(
+            &state.discount_collection,
+            &state.product_variant_version_collection,
+            &order_items,
+        )
+        .await?;
+        let item_ids: Vec<Uuid> = order_items.iter().map(|item| item.id).collect();
+        let shipping_line_items = invoiced_shipping_line_items(
+            &shipping_of_order(state, &order_event_data).await?,
+            &order_event_data,
+            &item_ids,
+        );
+        let items_total = compensatable_amount_of_items(&order_event_data, &order_items)?;
+        let shipping_amounts = shipping_line_items.iter().map(|item| &item.amount);
+        let total = Money::sum(&currency, std::iter::once(&items_total).chain(shipping_amounts))?;
+        line_items.extend(shipping_line_items);
+        let payment_terms = query_payment_terms(
+            &state.payment_terms_collection,
+            &state.default_payment_terms,
+            vendor_id,
+            user.customer_group.as_deref(),
+        )
+        .await?;
+        let order = OrderEventData {
+            payment_authorization: None,
+            ..order_event_data.clone()
+        };
+        let mut invoice = Invoice::from(InvoiceDraft {
+            document_type,
+            order_id: Some(order_event_data.id),
+            shipment_id,
+            order_ids: vec![],
+            billing_period: None,
+            subscription_id: None,
+            order: Some(order),
+            vendor_id,
+            vat_number: order_event_data.vat_number,
+            user,
             user_address,
             vendor_address,
+            payment_terms,
+            line_items,
+            total,
+        });
+        invoice.pending_event = Some(match document_type {
+            InvoiceDocumentType::Proforma => IssuedEvent::Proforma,
+            _ => IssuedEvent::Invoice,
+        });
+        invoice
+            .issue(
+                &state.invoice_number_sequence_collection,
+                &state.invoice_collection,
+                state.signer.as_deref(),
+            )
+            .await?;
+        Ok(invoice)
+    }
+
+    /// Creates, issues and stores a collective invoice of a vendor, which consolidates the orders of a user in a billing period.
+    ///
+    /// Customer and invoice address are taken from the latest order. The invoice is stored with its creation event pending.
+    ///
+    /// * `orders` - Orders in order of validation, each with its order items which are sold by the vendor.
+    /// * `vendor_id` - Vendor issuing the invoice, `None` for the shop's own vendor.
+    /// * `billing_period` - Billing period the orders were validated in.
+    /// * `state` - Service state containing database connections.
+    pub async fn new_collective(
+        orders: &[(OrderEventData, Vec<OrderItemEventData>)],
+        vendor_id: Option<Uuid>,
+        billing_period: BillingPeriod,
+        state: &HttpEventServiceState,
+    ) -> Result<Self> {
+        let (latest_order, _) = orders
+            .last()
+            .ok_or(Error::new("A collective invoice needs at least one order."))?;
+        let (user_address, vendor_address, user) = invoice_attribute_setup(
+            latest_order.user_id,
+            latest_order.invoice_address_id,
+            vendor_id,
+            state,
+        )
+        .await?;
+        let currency = latest_order.currency()?;
+        let mut totals = orders
+            .iter()
+            .map(|(order, order_items)| compensatable_amount_of_items(order, order_items))
+            .collect::<Result<Vec<Money>>>()?;
+        let mut line_items = vec![];
+        for (order, order_items) in orders {
+            let mut order_line_items = order_item_line_items(
+                &state.discount_collection,
+                &state.product_variant_version_collection,
+                order_items,
+            )
+            .await?;
+            let item_ids: Vec<Uuid> = order_items.iter().map(|item| item.id).collect();
+            let shipping_line_items = invoiced_shipping_line_items(
+                &shipping_of_order(state, order).await?,
+                order,
+                &item_ids,
+            );
+            totals.extend(shipping_line_items.iter().map(|item| item.amount.clone()));
+            order_line_items.extend(shipping_line_items);
+            line_items.extend(order_line_items.into_iter().map(|line_item| InvoiceLineItem {
+                order_id: Some(order.id),
+                ..line_item
+            }));
+        }
+        let total = Money::sum(&currency, totals.iter())?;
+        let payment_terms = query_payment_terms(
+            &state.payment_terms_collection,
+            &state.default_payment_terms,
+            vendor_id,
+            user.customer_group.as_deref(),
+        )
+        .await?;
+        let mut invoice = Invoice::from(InvoiceDraft {
+            document_type: InvoiceDocumentType::Invoice,
+            order_id: None,
+            shipment_id: None,
+            order_ids: orders.iter().map(|(order, _)| order.id).collect(),
+            billing_period: Some(billing_period),
+            subscription_id: None,
+            order: None,
+            vendor_id,
+            vat_number: latest_order.vat_number.clone(),
             user,
-        ) = invoice_attribute_setup(&order_event_data, state).await?;
-        let vat_number = order_event_data
-            .vat_number
-            .clone()
-            .unwrap_or("-".to_string());
-        let content = format!(
-            r#"
-# Invoice
-
-### Company information:
-{}
-{}, {}
-{}, {}
-
-VAT number: {}
-
-### Customer information:
-ID: {}
-Name: {}, {}
-Address:
-{}
-{}, {}
-{}, {}
-
-### Invoice ID: {}, issued at: {} 
-
-Terms and conditions: {}
-
----
-
-Purchased items overview:
-
-{}
-
----
-
-Total compensatable amount: {}
-"#,
-            vendor_address.company_name,
-            vendor_address.street1,
-            vendor_address.street2,
-            vendor_address.city,
-            vendor_address.country,
-            vat_number,
-            user._id,
-            user.first_name,
-            user.last_name,
-            user_address.company_name,
-            user_address.street1,
-            user_address.street2,
-            user_address.city,
-            user_address.country,
-            _id,
-            issued_at_string,
-            INVOICE_TERMS,
-            order_item_invoice_overview,
-            order_event_data.compensatable_order_amount
-        );
-        let invoice = Invoice {
-            _id,
-            order_id: order_event_data.id,
-            issued_at,
-            content: content,
             user_address,
             vendor_address,
-            vat_number: order_event_data.vat_number,
-        };
+            payment_terms,
+            line_items,
+            total,
+        });
+        invoice.pending_event = Some(IssuedEvent::CollectiveInvoice);
+        invoice
+            .issue(
+                &state.invoice_number_sequence_collection,
+                &state.invoice_collection,
+                state.signer.as_deref(),
+            )
+            .await?;
         Ok(invoice)
     }
+
+    /// Creates, issues and stores an invoice of a subscription, for a billing period or a plan change within it.
+    ///
+    /// The invoice is stored with its creation event pending.
+    ///
+    /// * `invoice_id` - UUID of the invoice.
+    /// * `subscription` - Invoiced subscription.
+    /// * `vendor_id` - Vendor issuing the invoice, `None` for the shop's own vendor.
+    /// * `billing_period` - Billing period the invoice covers.
+    /// * `line_items` - Lines of the invoice, in the currency of the plan.
+    /// * `state` - Service state containing database connections.
+    pub async fn new_subscription(
+        invoice_id: Uuid,
+        subscription: &CustomerSubscription,
+        vendor_id: Option<Uuid>,
+        billing_period: BillingPeriod,
+        line_items: Vec<InvoiceLineItem>,
+        state: &HttpEventServiceState,
+    ) -> Result<Self> {
+        let currency = line_items
+            .first()
+            .map(|item| item.amount.currency.clone())
+            .ok_or(Error::new("A subscription invoice needs at least one line."))?;
+        let total = Money::sum(&currency, line_items.iter().map(|item| &item.amount))?;
+        let (user_address, vendor_address, user) = invoice_attribute_setup(
+            subscription.user_id,
+            subscription.invoice_address_id,
+            vendor_id,
+            state,
+        )
+        .await?;
+        let payment_terms = query_payment_terms(
+            &state.payment_terms_collection,
+            &state.default_payment_terms,
+            vendor_id,
+            user.customer_group.as_deref(),
+        )
+        .await?;
+        let mut invoice = Invoice::from(InvoiceDraft {
+            document_type: InvoiceDocumentType::Invoice,
+            order_id: None,
+            shipment_id: None,
+            order_ids: vec![],
+            billing_period: Some(billing_period),
+            subscription_id: Some(subscription._id),
+            order: None,
+            vendor_id,
+            vat_number: subscription.vat_number.clone(),
+            user,
+            user_address,
+            vendor_address,
+            payment_terms,
+            line_items,
+            total,
+        });
+        invoice._id = invoice_id;
+        invoice.pending_event = Some(IssuedEvent::SubscriptionInvoice);
+        invoice
+            .issue(
+                &state.invoice_number_sequence_collection,
+                &state.invoice_collection,
+                state.signer.as_deref(),
+            )
+            .await?;
+        Ok(invoice)
+    }
+
+    /// Creates, issues and stores a manual invoice of free-form lines, which is not related to an order.
+    ///
+    /// * `input` - Customer, invoice address and lines of the invoice.
+    /// * `context` - Configuration and connections of the service.
+    pub async fn new_manual(
+        input: CreateManualInvoiceInput,
+        context: &ServiceContext,
+    ) -> Result<Self> {
+        let db_client = &context.db_client;
+        let user_collection = db_client.collection::<User>("user");
+        let address_user =
+            query_user_address_user(&user_collection, input.invoice_address_id).await?;
+        if address_user._id != input.user_id {
+            return Err(Error::new(format!(
+                "Address of UUID: `{}` does not belong to user of UUID: `{}`.",
+                input.invoice_address_id, input.user_id
+            )));
+        }
+        let user_address = project_user_to_user_address(address_user)?;
+        let user = query_object(&user_collection, input.user_id).await?;
+        let vendor_address = query_vendor_address(
+            &db_client.collection::<VendorAddress>("vendor_address"),
+            input.vendor_id,
+        )
+        .await?;
+        let line_items = input
+            .line_items
+            .into_iter()
+            .map(|line_item| line_item.into_line_item(&input.currency))
+            .collect::<Result<Vec<InvoiceLineItem>>>()?;
+        let total = Money::sum(&input.currency, line_items.iter().map(|item| &item.amount))?;
+        if line_items.is_empty() || total.amount <= 0 {
+            return Err(Error::new(
+                "A manual invoice needs at least one line and a positive total.",
+            ));
+        }
+        let payment_terms = query_payment_terms(
+            &db_client.collection::<PaymentTermsRule>("payment_terms"),
+            &context.default_payment_terms,
+            input.vendor_id,
+            user.customer_group.as_deref(),
+        )
+        .await?;
+        let mut invoice = Invoice::from(InvoiceDraft {
+            document_type: InvoiceDocumentType::Invoice,
+            order_id: None,
+            shipment_id: None,
+            order_ids: vec![],
+            billing_period: None,
+            subscription_id: None,
+            order: None,
+            vendor_id: input.vendor_id,
+            vat_number: input.vat_number,
+            user,
+            user_address,
+            vendor_address,
+            payment_terms,
+            line_items,
+            total,
+        });
+        invoice.pending_event = Some(IssuedEvent::ManualInvoice);
+        invoice
+            .issue(
+                &db_client.collection::<InvoiceNumberSequence>("invoice_number_sequences"),
+                &db_client.collection::<Invoice>("invoices"),
+                context.signer.as_deref(),
+            )
+            .await?;
+        Ok(invoice)
+    }
+
+    /// Issues the invoice in the numbering sequence of its vendor, rendering its content, signs its hash and stores it.
+    ///
+    /// * `sequence_collection` - MongoDB collection of invoice numbering sequences.
+    /// * `invoice_collection` - MongoDB collection of invoices to store the invoice in.
+    /// * `signer` - Signer of issued invoices, `None` to issue the invoice unsigned.
+    pub async fn issue(
+        &mut self,
+        sequence_collection: &Collection<InvoiceNumberSequence>,
+        invoice_collection: &Collection<Invoice>,
+        signer: Option<&InvoiceSigner>,
+    ) -> Result<()> {
+        let locale = Locale::from_tag(&self.locale);
+        issue_in_sequence(
+            sequence_collection,
+            invoice_collection,
+            &self.document_type.sequence_id(self.vendor_id),
+            self.document_type.prefix(),
+            self,
+            &locale,
+            signer,
+        )
+        .await
+    }
+
+    /// Creates an unissued document of a type which refers to this invoice, with the data of this invoice.
+    ///
+    /// * `document_type` - Type of the new document.
+    /// * `reason` - Reason for the new document.
+    pub fn derive_document(&self, document_type: InvoiceDocumentType, reason: String) -> Invoice {
+        Invoice {
+            _id: Uuid::new(),
+            document_type,
+            related_invoice_id: Some(self._id),
+            related_invoice_number: Some(self.invoice_number.clone()),
+            reason: Some(reason),
+            invoice_number: String::new(),
+            sequence_id: None,
+            sequence_number: None,
+            hash: None,
+            previous_hash: None,
+            signature: None,
+            issued_at: DateTime::now(),
+            content: String::new(),
+            status: InvoiceStatus::Open,
+            paid_at: None,
+            cancelled_at: None,
+            cancellation_reason: None,
+            superseded_by: None,
+            converted_to: None,
+            credited_amount: None,
+            dunning_level: None,
+            dunning_history: vec![],
+            delivery: InvoiceDelivery {
+                recipient: self.delivery.recipient.clone(),
+                ..Default::default()
+            },
+            documents: vec![],
+            pending_event: None,
+            pending_events: vec![],
+            ..self.clone()
+        }
+    }
+}
+
+/// Data of a new, unissued invoice which differs between order based, collective, subscription and manual invoices.
+struct InvoiceDraft {
+    document_type: InvoiceDocumentType,
+    order_id: Option<Uuid>,
+    shipment_id: Option<Uuid>,
+    order_ids: Vec<Uuid>,
+    billing_period: Option<BillingPeriod>,
+    subscription_id: Option<Uuid>,
+    order: Option<OrderEventData>,
+    vendor_id: Option<Uuid>,
+    vat_number: Option<String>,
+    user: User,
+    user_address: UserAddress,
+    vendor_address: VendorAddress,
+    payment_terms: PaymentTerms,
+    line_items: Vec<InvoiceLineItem>,
+    total: Money,
 }
 
-/// Sets up all the attributes from `OrderEventData` and `HttpEventServiceState` (containing the database connections) that are required for invoice creation.
+impl From<InvoiceDraft> for Invoice {
+    fn from(value: InvoiceDraft) -> Self {
+        let issued_at = DateTime::now();
+        let customer =
+            InvoiceParty::customer(&value.user, &value.user_address, value.vat_number.clone());
+        let vendor = InvoiceParty::from(&value.vendor_address);
+        let locale = Locale::resolve(
+            value.user.preferred_locale.as_deref(),
+            &value.user_address.country,
+        );
+        let payable = value.document_type != InvoiceDocumentType::Proforma;
+        Invoice {
+            _id: Uuid::new(),
+            order_id: value.order_id,
+            shipment_id: value.shipment_id,
+            order_ids: value.order_ids,
+            billing_period: value.billing_period,
+            subscription_id: value.subscription_id,
+            document_type: value.document_type,
+            related_invoice_id: None,
+            related_invoice_number: None,
+            reason: None,
+            user_id: value.user._id,
+            vendor_id: value.vendor_id,
+            invoice_number: String::new(),
+            sequence_id: None,
+            sequence_number: None,
+            hash: None,
+            previous_hash: None,
+            signature: None,
+            issued_at,
+            content: String::new(),
+            locale: locale.tag().to_string(),
+            vendor_vat_id: vendor.vat_id.clone(),
+            customer,
+            vendor,
+            user_address: value.user_address,
+            vendor_address: value.vendor_address,
+            vat_number: value.vat_number,
+            due_at: Some(value.payment_terms.due_at(issued_at)).filter(|_| payable),
+            discount_deadline: value
+                .payment_terms
+                .discount_deadline(issued_at)
+                .filter(|_| payable),
+            early_payment_discount: value
+                .payment_terms
+                .discount_amount(&value.total)
+                .filter(|_| payable),
+            payment_terms: value.payment_terms,
+            status: InvoiceStatus::Open,
+            paid_at: None,
+            cancelled_at: None,
+            cancellation_reason: None,
+            superseded_by: None,
+            converted_to: None,
+            credited_amount: None,
+            dunning_level: None,
+            dunning_history: vec![],
+            delivery: InvoiceDelivery {
+                recipient: value.user.email.clone(),
+                ..Default::default()
+            },
+            documents: vec![],
+            order: value.order,
+            pending_event: None,
+            pending_events: vec![],
+            line_items: value.line_items,
+            total: value.total,
+        }
+    }
+}
+
+/// UUID of invoices stored before the invoiced user was recorded.
+fn nil_uuid() -> Uuid {
+    Uuid::from_bytes([0; 16])
+}
+
+/// Locale of invoices stored before localization was supported.
+fn default_locale() -> String {
+    "en".to_string()
+}
+
+/// Sets up all the attributes from `HttpEventServiceState` (containing the database connections) that are required for invoice creation.
+///
+/// * `user_id` - UUID of the invoiced user.
+/// * `invoice_address_id` - UUID of the address the invoice is issued to.
+/// * `vendor_id` - Vendor issuing the invoice, `None` for the shop's own vendor.
+/// * `state` - Service state containing database connections.
 async fn invoice_attribute_setup(
-    order_event_data: &OrderEventData,
+    user_id: Uuid,
+    invoice_address_id: Uuid,
+    vendor_id: Option<Uuid>,
     state: &HttpEventServiceState,
-) -> Result<(DateTime, String, String, UserAddress, VendorAddress, User), Error> {
-    let issued_at = DateTime::now();
-    let issued_at_string = issued_at
-        .to_chrono()
-        .format("%Y-%m-%d %H:%M:%S")
-        .to_string();
-    let order_item_invoice_overview = build_order_item_invoice_content(order_event_data);
+) -> Result<(UserAddress, VendorAddress, User), Error> {
     let user_address_user =
-        query_user_address_user(&state.user_collection, order_event_data.invoice_address_id)
-            .await?;
+        query_user_address_user(&state.user_collection, invoice_address_id).await?;
     let user_address = project_user_to_user_address(user_address_user)?;
-    let vendor_address = query_vendor_address(&state.vendor_address_collection).await?;
-    let user = query_object(&state.user_collection, order_event_data.user_id).await?;
-    Ok((
-        issued_at,
-        issued_at_string,
-        order_item_invoice_overview,
-        user_address,
-        vendor_address,
-        user,
-    ))
-}
-
-/// Builds the part of the invoice content which describes the order items as a markdown table.
-fn build_order_item_invoice_content(value: &OrderEventData) -> String {
-    let mut content = String::new();
-    content.push_str("| Item UUID | Product variant UUID | count | Compensatable amount |\n");
-    content.push_str("| --- | --- | --- | --- |\n");
-    for item in &value.order_items {
-        content.push_str(&format!(
-            "| {} | {} | {} | {} |\n",
-            item.id, item.product_variant_id, item.count, item.compensatable_amount
-        ));
-    }
-    content
+    let vendor_address =
+        query_vendor_address(&state.vendor_address_collection, vendor_id).await?;
+    let user = query_object(&state.user_collection, user_id).await?;
+    Ok((user_address, vendor_address, user))
 }
 
 /// Shared function to query an address from a MongoDB collection of users.
@@ -181,18 +810,157 @@ pub async fn query_user_address_user(
 
 /// Projects result of user address query, which is of type `User`, to the contained user address.
 pub fn project_user_to_user_address(user: User) -> Result<UserAddress> {
-    let message = format!("Projection failed, address could not be extracted from user.");
+    let message = "Projection failed, address could not be extracted from user.";
     user.addresses
-        .iter()
-        .next()
+        .first()
         .cloned()
-        .ok_or(Error::new(message.clone()))
+        .ok_or(Error::new(message))
 }
 
-/// Shared function to query the current vendor address.
-pub async fn query_vendor_address(collection: &Collection<VendorAddress>) -> Result<VendorAddress> {
+/// Computes the compensatable amount which is invoiced for the given order items, excluding shipping.
+fn compensatable_amount_of_items(
+    order_event_data: &OrderEventData,
+    order_items: &[OrderItemEventData],
+) -> Result<Money> {
+    Money::sum(
+        &order_event_data.currency()?,
+        order_items.iter().map(|item| &item.compensatable_amount),
+    )
+}
+
+/// Queries the shipping line items recorded for an order when it was validated.
+///
+/// Orders without a recorded shipping, like proformas of orders not yet validated, derive it from the order.
+///
+/// * `state` - HTTP event service state containing the order invoicing and shipment method collections.
+/// * `order` - Order whose shipping is charged.
+async fn shipping_of_order(
+    state: &HttpEventServiceState,
+    order: &OrderEventData,
+) -> Result<Vec<InvoiceLineItem>> {
+    let order_invoicing = state
+        .order_invoicing_collection
+        .find_one(doc! {"_id": order.id}, None)
+        .await?;
+    match order_invoicing.and_then(|order_invoicing| order_invoicing.shipping_line_items) {
+        Some(shipping_line_items) => Ok(shipping_line_items),
+        None => order_shipping_line_items(&state.shipment_method_collection, order).await,
+    }
+}
+
+/// Shared function to query the current vendor address of a vendor.
+///
+/// * `collection` - MongoDB collection of vendor addresses.
+/// * `vendor_id` - UUID of vendor, `None` for the shop's own vendor address.
+pub async fn query_vendor_address(
+    collection: &Collection<VendorAddress>,
+    vendor_id: Option<Uuid>,
+) -> Result<VendorAddress> {
+    let message = match vendor_id {
+        Some(vendor_id) => format!("Vendor address of vendor UUID: `{}` is not set locally.", vendor_id),
+        None => "Vendor address is not set locally.".to_string(),
+    };
     collection
-        .find_one(None, None)
+        .find_one(doc! {"vendor_id": vendor_id}, None)
         .await?
-        .ok_or(Error::new("Vendor address is not set locally."))
+        .ok_or(Error::new(message))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::event::model::invoice_status_changed_dto::InvoiceStatusChangedDTO;
+
+    /// Issued invoice of the given total in EUR, without line items.
+    pub fn invoice(total: i64) -> Invoice {
+        let address = doc! {
+            "_id": Uuid::new(),
+            "street1": "Street 1",
+            "street2": "",
+            "city": "City",
+            "postal_code": "12345",
+            "country": "DE",
+            "company_name": "",
+        };
+        let mut user_address = address.clone();
+        user_address.insert("user_id", Uuid::new());
+        bson::from_document(doc! {
+            "_id": Uuid::new(),
+            "order_id": null,
+            "user_id": Uuid::new(),
+            "invoice_number": "RE-00000001",
+            "issued_at": DateTime::now(),
+            "content": "",
+            "user_address": user_address,
+            "vendor_address": address,
+            "vat_number": null,
+            "total": {"amount": total, "currency": "EUR"},
+        })
+        .expect("Invoice fixture is deserializable.")
+    }
+
+    /// Draft of a document of the given type with a total of 100 EUR, due in 14 days with 2% discount within 7 days.
+    fn draft(document_type: InvoiceDocumentType) -> InvoiceDraft {
+        let fixture = invoice(10000);
+        let user: User = bson::from_document(doc! {
+            "_id": fixture.user_id,
+            "first_name": "Ada",
+            "last_name": "Lovelace",
+            "addresses": [],
+        })
+        .unwrap();
+        InvoiceDraft {
+            document_type,
+            order_id: Some(Uuid::new()),
+            shipment_id: None,
+            order_ids: vec![],
+            billing_period: None,
+            subscription_id: None,
+            order: None,
+            vendor_id: Some(Uuid::new()),
+            vat_number: Some("DE123456789".to_string()),
+            user,
+            user_address: fixture.user_address,
+            vendor_address: fixture.vendor_address,
+            payment_terms: PaymentTerms {
+                net_days: 14,
+                early_payment_discount_percentage: Some(2.0),
+                early_payment_discount_days: Some(7),
+            },
+            line_items: vec![],
+            total: fixture.total,
+        }
+    }
+
+    #[test]
+    fn issues_proformas_without_payment_terms_in_their_own_sequence() {
+        let proforma = Invoice::from(draft(InvoiceDocumentType::Proforma));
+        assert_eq!(proforma.due_at, None);
+        assert_eq!(proforma.discount_deadline, None);
+        assert!(proforma.early_payment_discount.is_none());
+        assert_eq!(proforma.converted_to, None);
+        let invoice = Invoice::from(draft(InvoiceDocumentType::Invoice));
+        assert!(invoice.due_at.is_some());
+        assert_eq!(invoice.early_payment_discount.unwrap().amount, 200);
+        let vendor_id = proforma.vendor_id;
+        assert_eq!(
+            proforma.document_type.sequence_id(vendor_id),
+            format!("proforma-{}", invoice.document_type.sequence_id(vendor_id))
+        );
+        assert_ne!(proforma.document_type.prefix(), invoice.document_type.prefix());
+    }
+
+    #[test]
+    fn stores_change_events_as_published_json() {
+        let invoice = invoice(10000);
+        let status_changed = InvoiceStatusChangedDTO::from((&invoice, InvoiceStatus::Paid, None));
+        let event = PendingEvent::new("invoice/invoice/status-changed", &status_changed).unwrap();
+        let stored: PendingEvent =
+            bson::from_document(bson::to_document(&event).unwrap()).unwrap();
+        let data: serde_json::Value = serde_json::from_str(&stored.data).unwrap();
+        assert_eq!(stored._id, event._id);
+        assert_eq!(data["invoiceId"], invoice._id.to_string());
+        assert_eq!(data["previousStatus"], "Open");
+        assert_eq!(data["status"], "Paid");
+    }
 }
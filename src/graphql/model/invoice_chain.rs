@@ -0,0 +1,522 @@
+use std::time::Duration;
+
+use async_graphql::{Enum, Error, Result, SimpleObject};
+use bson::{doc, Uuid};
+use futures::TryStreamExt;
+use mongodb::{
+    error::{ErrorKind, WriteFailure},
+    options::{FindOptions, UpdateOptions},
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    i18n::Locale, render::content::render_invoice_content, signature::InvoiceSigner,
+};
+
+use super::{
+    invoice::{Invoice, InvoiceDocumentType},
+    invoice_line_item::{InvoiceLineItem, LineItemProduct},
+    invoice_party::InvoiceParty,
+    money::Money, payment_terms::PaymentTerms,
+};
+
+/// Maximum number of attempts to append an invoice to a contended sequence.
+const MAX_ISSUE_ATTEMPTS: u32 = 20;
+
+/// Numbering sequence of invoices, which also holds the head of the sequence's hash chain.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InvoiceNumberSequence {
+    /// Vendor UUID as string, or `default` for the shop's own vendor.
+    pub _id: String,
+    /// Last invoice number issued in this sequence.
+    pub value: i64,
+    /// Hash of the last invoice issued in this sequence, `None` before the first chained invoice.
+    #[serde(default)]
+    pub last_hash: Option<String>,
+}
+
+/// Returns the numbering sequence of a vendor, `default` for the shop's own vendor.
+pub fn sequence_id_of_vendor(vendor_id: Option<Uuid>) -> String {
+    vendor_id.map_or("default".to_string(), |id| id.to_string())
+}
+
+/// Assigns the next number of a sequence to an invoice, renders its content, links it to the sequence's hash chain, signs it and stores it.
+///
+/// The number is reserved by inserting the invoice, as a unique index rejects a second invoice with the same number in a sequence.
+/// Only after the insert succeeded, number and chain head of the sequence are advanced with a compare-and-set.
+/// If an issuer stops between both steps, the next issuer of the sequence finds the stored invoice and advances the sequence to it,
+/// so the sequence never points past or skips a stored invoice.
+///
+/// * `sequence_collection` - MongoDB collection of invoice numbering sequences.
+/// * `invoice_collection` - MongoDB collection of invoices to store the issued invoice in.
+/// * `sequence_id` - Numbering sequence to issue the invoice in.
+/// * `prefix` - Prefix of the formatted invoice number.
+/// * `invoice` - Invoice to issue.
+/// * `locale` - Locale to render the invoice content in.
+/// * `signer` - Signer of issued invoices, `None` to issue the invoice unsigned.
+pub async fn issue_in_sequence(
+    sequence_collection: &Collection<InvoiceNumberSequence>,
+    invoice_collection: &Collection<Invoice>,
+    sequence_id: &str,
+    prefix: &str,
+    invoice: &mut Invoice,
+    locale: &Locale,
+    signer: Option<&InvoiceSigner>,
+) -> Result<()> {
+    sequence_collection
+        .update_one(
+            doc! {"_id": sequence_id},
+            doc! {"$setOnInsert": {"value": 0_i64}},
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?;
+    for attempt in 0..MAX_ISSUE_ATTEMPTS {
+        let sequence = sequence_collection
+            .find_one(doc! {"_id": sequence_id}, None)
+            .await?
+            .ok_or(Error::new(format!(
+                "Invoice number sequence `{}` not found.",
+                sequence_id
+            )))?;
+        let number = sequence.value + 1;
+        if let Some(stored) = invoice_collection
+            .find_one(
+                doc! {"sequence_id": sequence_id, "sequence_number": number},
+                None,
+            )
+            .await?
+        {
+            advance_sequence(sequence_collection, &sequence, number, stored.hash).await?;
+            continue;
+        }
+        invoice.sequence_id = Some(sequence_id.to_string());
+        invoice.sequence_number = Some(number);
+        invoice.invoice_number = format!("{}-{:08}", prefix, number);
+        invoice.previous_hash = sequence.last_hash.clone();
+        invoice.content = render_invoice_content(invoice, locale);
+        let hash = invoice_hash(invoice);
+        invoice.signature = signer.map(|signer| signer.sign(&hash));
+        invoice.hash = Some(hash.clone());
+        match invoice_collection.insert_one(&*invoice, None).await {
+            Ok(_) => {
+                advance_sequence(sequence_collection, &sequence, number, Some(hash)).await?;
+                return Ok(());
+            }
+            Err(e) if is_duplicate_key_error(&e) => {
+                if !number_is_taken(invoice_collection, invoice).await? {
+                    return Err(e.into());
+                }
+                tokio::time::sleep(Duration::from_millis(10 * u64::from(attempt + 1))).await;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(Error::new(format!(
+        "Invoice number sequence `{}` is contended, invoice could not be issued.",
+        sequence_id
+    )))
+}
+
+/// Advances a sequence to a stored invoice, unless another issuer advanced it already.
+///
+/// * `collection` - MongoDB collection of invoice numbering sequences.
+/// * `sequence` - Sequence as read before the invoice was stored.
+/// * `number` - Number of the stored invoice.
+/// * `hash` - Hash of the stored invoice.
+async fn advance_sequence(
+    collection: &Collection<InvoiceNumberSequence>,
+    sequence: &InvoiceNumberSequence,
+    number: i64,
+    hash: Option<String>,
+) -> Result<()> {
+    collection
+        .update_one(
+            doc! {"_id": &sequence._id, "value": sequence.value},
+            doc! {"$set": {"value": number, "last_hash": hash}},
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Whether an insert failed because a unique index already holds a document with the same key.
+pub fn is_duplicate_key_error(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == 11000
+    )
+}
+
+/// Whether the number assigned to an invoice is taken by another invoice, e.g. one issued concurrently.
+async fn number_is_taken(collection: &Collection<Invoice>, invoice: &Invoice) -> Result<bool> {
+    let other = collection
+        .find_one(
+            doc! {
+                "sequence_id": &invoice.sequence_id,
+                "sequence_number": invoice.sequence_number,
+                "_id": {"$ne": invoice._id},
+            },
+            None,
+        )
+        .await?;
+    Ok(other.is_some())
+}
+
+/// Legal content of an invoice in canonical field order, which the chain hash is computed over.
+///
+/// Fields added later are omitted when unset, so hashes of invoices issued before stay valid.
+#[derive(Serialize)]
+struct CanonicalInvoice<'a> {
+    id: String,
+    sequence_id: &'a Option<String>,
+    sequence_number: Option<i64>,
+    invoice_number: &'a str,
+    order_id: Option<String>,
+    user_id: String,
+    vendor_id: Option<String>,
+    issued_at: i64,
+    customer: &'a InvoiceParty,
+    vendor: &'a InvoiceParty,
+    line_items: Vec<CanonicalLineItem<'a>>,
+    total: &'a Money,
+    payment_terms: &'a PaymentTerms,
+    due_at: Option<i64>,
+    locale: &'a str,
+    content: &'a str,
+    previous_hash: &'a Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    document_type: Option<InvoiceDocumentType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    related_invoice_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shipment_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    order_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    billing_period: Option<(i64, i64)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subscription_id: Option<String>,
+}
+
+/// Line item in canonical field order.
+#[derive(Serialize)]
+struct CanonicalLineItem<'a> {
+    order_item_id: Option<String>,
+    product_variant_id: Option<String>,
+    count: u64,
+    amount: &'a Money,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    undiscounted_amount: Option<&'a Money>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    discounts: Vec<CanonicalLineItemDiscount<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shipment_method_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tax_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tax_amount: Option<&'a Money>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    product_variant_version_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    product: Option<&'a LineItemProduct>,
+}
+
+/// Discount of a line item in canonical field order.
+#[derive(Serialize)]
+struct CanonicalLineItemDiscount<'a> {
+    discount_id: String,
+    description: Option<&'a str>,
+    discount: f64,
+    amount: &'a Money,
+}
+
+impl<'a> From<&'a InvoiceLineItem> for CanonicalLineItem<'a> {
+    fn from(value: &'a InvoiceLineItem) -> Self {
+        Self {
+            order_item_id: value.order_item_id.map(|id| id.to_string()),
+            product_variant_id: value.product_variant_id.map(|id| id.to_string()),
+            count: value.count,
+            amount: &value.amount,
+            description: value.description.as_deref(),
+            order_id: value.order_id.map(|id| id.to_string()),
+            undiscounted_amount: value.undiscounted_amount.as_ref(),
+            discounts: value
+                .discounts
+                .iter()
+                .map(|discount| CanonicalLineItemDiscount {
+                    discount_id: discount.discount_id.to_string(),
+                    description: discount.description.as_deref(),
+                    discount: discount.discount,
+                    amount: &discount.amount,
+                })
+                .collect(),
+            shipment_method_id: value.shipment_method_id.map(|id| id.to_string()),
+            tax_rate: value.tax_rate,
+            tax_amount: value.tax_amount.as_ref(),
+            product_variant_version_id: value.product_variant_version_id.map(|id| id.to_string()),
+            product: value.product.as_ref(),
+        }
+    }
+}
+
+/// Computes the hex encoded SHA-256 hash over the canonical legal content of an invoice, including its previous hash.
+pub fn invoice_hash(invoice: &Invoice) -> String {
+    let canonical = CanonicalInvoice {
+        id: invoice._id.to_string(),
+        sequence_id: &invoice.sequence_id,
+        sequence_number: invoice.sequence_number,
+        invoice_number: &invoice.invoice_number,
+        order_id: invoice.order_id.map(|id| id.to_string()),
+        user_id: invoice.user_id.to_string(),
+        vendor_id: invoice.vendor_id.map(|id| id.to_string()),
+        issued_at: invoice.issued_at.timestamp_millis(),
+        customer: &invoice.customer,
+        vendor: &invoice.vendor,
+        line_items: invoice.line_items.iter().map(CanonicalLineItem::from).collect(),
+        total: &invoice.total,
+        payment_terms: &invoice.payment_terms,
+        due_at: invoice.due_at.map(|due_at| due_at.timestamp_millis()),
+        locale: &invoice.locale,
+        content: &invoice.content,
+        previous_hash: &invoice.previous_hash,
+        document_type: Some(invoice.document_type)
+            .filter(|document_type| *document_type != InvoiceDocumentType::Invoice),
+        related_invoice_id: invoice.related_invoice_id.map(|id| id.to_string()),
+        reason: invoice.reason.as_deref(),
+        shipment_id: invoice.shipment_id.map(|id| id.to_string()),
+        order_ids: invoice.order_ids.iter().map(|id| id.to_string()).collect(),
+        billing_period: invoice.billing_period.map(|period| {
+            (period.start.timestamp_millis(), period.end.timestamp_millis())
+        }),
+        subscription_id: invoice.subscription_id.map(|id| id.to_string()),
+    };
+    let json = serde_json::to_vec(&canonical).expect("Canonical invoice is always serializable.");
+    Sha256::digest(json)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Kind of break in an invoice hash chain.
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq)]
+pub enum ChainBreakKind {
+    /// The recorded hash does not match the invoice content, the invoice was altered.
+    HashMismatch,
+    /// The previous hash does not match the hash of the preceding invoice.
+    PreviousHashMismatch,
+    /// An invoice number of the sequence has no invoice.
+    MissingInvoice,
+}
+
+/// Break in an invoice hash chain.
+#[derive(Debug, SimpleObject, Clone)]
+pub struct ChainBreak {
+    /// Kind of break.
+    pub kind: ChainBreakKind,
+    /// Number in the sequence where the chain breaks.
+    pub sequence_number: i64,
+    /// UUID of the invoice where the chain breaks, `None` for missing invoices.
+    pub invoice_id: Option<Uuid>,
+    /// Description of the break.
+    pub message: String,
+}
+
+/// Result of the verification of the hash chain of a numbering sequence.
+#[derive(Debug, SimpleObject, Clone)]
+pub struct ChainVerification {
+    /// Verified numbering sequence.
+    pub sequence_id: String,
+    /// Number of chained invoices in the sequence.
+    pub invoice_count: u64,
+    /// Whether the chain is intact.
+    pub valid: bool,
+    /// Breaks found in the chain.
+    pub breaks: Vec<ChainBreak>,
+}
+
+/// Walks the hash chains of all or one numbering sequence and reports any break.
+///
+/// Invoices issued before chaining was introduced have no hash and are skipped.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `sequence_id` - Numbering sequence to verify, all sequences if `None`.
+pub async fn verify_invoice_chains(
+    collection: &Collection<Invoice>,
+    sequence_id: Option<String>,
+) -> Result<Vec<ChainVerification>> {
+    let mut filter = doc! {"hash": {"$ne": null}};
+    if let Some(sequence_id) = sequence_id {
+        filter.insert("sequence_id", sequence_id);
+    }
+    let find_options = FindOptions::builder()
+        .sort(doc! {"sequence_id": 1, "sequence_number": 1})
+        .build();
+    let mut cursor = collection.find(filter, find_options).await?;
+    let mut verifications: Vec<ChainVerification> = Vec::new();
+    let mut previous: Option<(i64, String)> = None;
+    while let Some(invoice) = cursor.try_next().await? {
+        let sequence_id = invoice.sequence_id.clone().unwrap_or_default();
+        if verifications.last().map(|v| &v.sequence_id) != Some(&sequence_id) {
+            verifications.push(ChainVerification {
+                sequence_id,
+                invoice_count: 0,
+                valid: true,
+                breaks: vec![],
+            });
+            previous = None;
+        }
+        let verification = verifications.last_mut().expect("Verification was pushed.");
+        verification.invoice_count += 1;
+        let breaks = verify_link(&invoice, previous.as_ref());
+        verification.valid &= breaks.is_empty();
+        verification.breaks.extend(breaks);
+        previous = Some((
+            invoice.sequence_number.unwrap_or_default(),
+            invoice.hash.clone().unwrap_or_default(),
+        ));
+    }
+    Ok(verifications)
+}
+
+/// Verifies an invoice's own hash and its link to the preceding invoice of the sequence.
+fn verify_link(invoice: &Invoice, previous: Option<&(i64, String)>) -> Vec<ChainBreak> {
+    let sequence_number = invoice.sequence_number.unwrap_or_default();
+    let mut breaks = vec![];
+    if invoice.hash.as_deref() != Some(invoice_hash(invoice).as_str()) {
+        breaks.push(ChainBreak {
+            kind: ChainBreakKind::HashMismatch,
+            sequence_number,
+            invoice_id: Some(invoice._id),
+            message: format!(
+                "Invoice `{}` does not match its recorded hash.",
+                invoice.invoice_number
+            ),
+        });
+    }
+    if let Some((previous_number, previous_hash)) = previous {
+        for missing in previous_number + 1..sequence_number {
+            breaks.push(ChainBreak {
+                kind: ChainBreakKind::MissingInvoice,
+                sequence_number: missing,
+                invoice_id: None,
+                message: format!("Invoice number {} of the sequence is missing.", missing),
+            });
+        }
+        if invoice.previous_hash.as_ref() != Some(previous_hash) {
+            breaks.push(ChainBreak {
+                kind: ChainBreakKind::PreviousHashMismatch,
+                sequence_number,
+                invoice_id: Some(invoice._id),
+                message: format!(
+                    "Invoice `{}` is not linked to the hash of its preceding invoice.",
+                    invoice.invoice_number
+                ),
+            });
+        }
+    }
+    breaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql::model::invoice::tests::invoice;
+
+    /// Invoices of numbers in a sequence, each linked to the hash of its predecessor.
+    fn chain(numbers: &[i64]) -> Vec<Invoice> {
+        let mut previous_hash = None;
+        numbers
+            .iter()
+            .map(|number| {
+                let mut invoice = invoice(100 * number);
+                invoice.sequence_id = Some("default".to_string());
+                invoice.sequence_number = Some(*number);
+                invoice.invoice_number = format!("RE-{:08}", number);
+                invoice.previous_hash = previous_hash.clone();
+                invoice.hash = Some(invoice_hash(&invoice));
+                previous_hash = invoice.hash.clone();
+                invoice
+            })
+            .collect()
+    }
+
+    /// Verifies the links of invoices in order, like `verify_invoice_chains` does for a sequence.
+    fn verify(invoices: &[Invoice]) -> Vec<ChainBreak> {
+        let mut previous = None;
+        let mut breaks = vec![];
+        for invoice in invoices {
+            breaks.extend(verify_link(invoice, previous.as_ref()));
+            previous = Some((
+                invoice.sequence_number.unwrap_or_default(),
+                invoice.hash.clone().unwrap_or_default(),
+            ));
+        }
+        breaks
+    }
+
+    fn kinds(breaks: &[ChainBreak]) -> Vec<(ChainBreakKind, i64)> {
+        breaks
+            .iter()
+            .map(|chain_break| (chain_break.kind, chain_break.sequence_number))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_intact_chain() {
+        assert!(verify(&chain(&[1, 2, 3])).is_empty());
+    }
+
+    #[test]
+    fn hashes_canonical_content_deterministically() {
+        let invoice = invoice(100);
+        assert_eq!(invoice_hash(&invoice), invoice_hash(&invoice.clone()));
+        let mut credit_note = invoice.clone();
+        credit_note.document_type = InvoiceDocumentType::CreditNote;
+        assert_ne!(invoice_hash(&invoice), invoice_hash(&credit_note));
+        let mut delivered = invoice.clone();
+        delivered.delivery.recipient = Some("customer@example.com".to_string());
+        assert_eq!(invoice_hash(&invoice), invoice_hash(&delivered));
+    }
+
+    #[test]
+    fn detects_altered_invoice() {
+        let mut invoices = chain(&[1, 2, 3]);
+        invoices[1].total.amount += 1;
+        assert_eq!(
+            kinds(&verify(&invoices)),
+            vec![(ChainBreakKind::HashMismatch, 2)]
+        );
+    }
+
+    #[test]
+    fn detects_missing_invoice() {
+        let mut invoices = chain(&[1, 2, 3]);
+        invoices.remove(1);
+        assert_eq!(
+            kinds(&verify(&invoices)),
+            vec![
+                (ChainBreakKind::MissingInvoice, 2),
+                (ChainBreakKind::PreviousHashMismatch, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_rehashed_invoice_by_its_successor() {
+        let mut invoices = chain(&[1, 2, 3]);
+        invoices[1].total.amount += 1;
+        invoices[1].hash = Some(invoice_hash(&invoices[1]));
+        assert_eq!(
+            kinds(&verify(&invoices)),
+            vec![(ChainBreakKind::PreviousHashMismatch, 3)]
+        );
+    }
+}
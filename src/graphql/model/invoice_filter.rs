@@ -0,0 +1,145 @@
+use async_graphql::InputObject;
+use bson::{doc, DateTime, Document, Uuid};
+
+use super::invoice::{Invoice, InvoiceDocumentType, InvoiceStatus};
+
+/// Filter for invoice queries. All set criteria must match.
+#[derive(Debug, InputObject, Default, Clone)]
+pub struct InvoiceFilter {
+    /// Only invoices of the user with this UUID.
+    pub user_id: Option<Uuid>,
+    /// Only invoices with this payment status.
+    pub status: Option<InvoiceStatus>,
+    /// `true` for only open invoices past their due date, `false` for only invoices which are not overdue.
+    pub overdue: Option<bool>,
+    /// Only invoices due before this timestamp.
+    pub due_before: Option<DateTime>,
+    /// Only documents of this type, e.g. to retrieve proformas.
+    pub document_type: Option<InvoiceDocumentType>,
+}
+
+impl InvoiceFilter {
+    /// Builds the MongoDB filter document of the filter, evaluating overdue at `now`.
+    pub fn to_document(&self, now: DateTime) -> Document {
+        let mut filter = doc! {};
+        if let Some(user_id) = self.user_id {
+            filter.insert("user_id", user_id);
+        }
+        if let Some(status) = self.status {
+            filter.insert("status", status_filter(status));
+        }
+        if let Some(document_type) = self.document_type {
+            filter.insert("document_type", document_type_filter(document_type));
+        }
+        if let Some(due_before) = self.due_before {
+            filter.insert("due_at", doc! {"$lt": due_before});
+        }
+        let overdue = doc! {
+            "status": status_filter(InvoiceStatus::Open),
+            "due_at": {"$lt": now},
+        };
+        match self.overdue {
+            Some(true) => {
+                filter.insert("$and", vec![overdue]);
+            }
+            Some(false) => {
+                filter.insert("$nor", vec![overdue]);
+            }
+            None => {}
+        }
+        filter
+    }
+}
+
+/// Matches the invoices ordered after an invoice by due date, issue date and UUID, to retrieve the page following it.
+///
+/// Invoices without due date, like proformas, are ordered first, as MongoDB orders missing values before dates.
+pub fn invoices_after(invoice: &Invoice) -> Document {
+    let issued_after = doc! {"$or": [
+        {"issued_at": {"$gt": invoice.issued_at}},
+        {"issued_at": invoice.issued_at, "_id": {"$gt": invoice._id}},
+    ]};
+    match invoice.due_at {
+        Some(due_at) => doc! {"$or": [
+            {"due_at": {"$gt": due_at}},
+            {"$and": [{"due_at": due_at}, issued_after]},
+        ]},
+        None => doc! {"$or": [
+            {"due_at": {"$type": "date"}},
+            {"$and": [{"due_at": null}, issued_after]},
+        ]},
+    }
+}
+
+/// Matches a status, treating invoices stored before payment status was recorded as open.
+fn status_filter(status: InvoiceStatus) -> Document {
+    match status {
+        InvoiceStatus::Open => doc! {"$in": ["Open", null]},
+        InvoiceStatus::Paid => doc! {"$eq": "Paid"},
+        InvoiceStatus::Cancelled => doc! {"$eq": "Cancelled"},
+    }
+}
+
+/// Matches a document type, treating invoices stored before document types were recorded as regular invoices.
+fn document_type_filter(document_type: InvoiceDocumentType) -> Document {
+    let document_type_bson =
+        bson::to_bson(&document_type).expect("Document type is always serializable.");
+    match document_type {
+        InvoiceDocumentType::Invoice => doc! {"$in": [document_type_bson, null]},
+        _ => doc! {"$eq": document_type_bson},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_open_invoices_past_their_due_date_as_overdue() {
+        let now = DateTime::now();
+        let filter = InvoiceFilter {
+            overdue: Some(true),
+            ..Default::default()
+        };
+        let overdue = doc! {
+            "status": {"$in": ["Open", null]},
+            "due_at": {"$lt": now},
+        };
+        assert_eq!(filter.to_document(now), doc! {"$and": [overdue]});
+    }
+
+    #[test]
+    fn excludes_overdue_invoices_if_not_overdue() {
+        let now = DateTime::now();
+        let filter = InvoiceFilter {
+            overdue: Some(false),
+            ..Default::default()
+        };
+        let overdue = doc! {
+            "status": {"$in": ["Open", null]},
+            "due_at": {"$lt": now},
+        };
+        assert_eq!(filter.to_document(now), doc! {"$nor": [overdue]});
+    }
+
+    #[test]
+    fn combines_criteria() {
+        let now = DateTime::now();
+        let user_id = Uuid::new();
+        let filter = InvoiceFilter {
+            user_id: Some(user_id),
+            status: Some(InvoiceStatus::Paid),
+            due_before: Some(now),
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.to_document(now),
+            doc! {
+                "user_id": user_id,
+                "status": {"$eq": "Paid"},
+                "due_at": {"$lt": now},
+            }
+        );
+        assert_eq!(InvoiceFilter::default().to_document(now), doc! {});
+    }
+}
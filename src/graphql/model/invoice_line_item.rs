@@ -0,0 +1,688 @@
+use async_graphql::{Error, Result, SimpleObject};
+use bson::{doc, Uuid};
+use futures::TryStreamExt;
+use log::warn;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::event::http_event_service::{OrderEventData, OrderItemEventData};
+
+use super::{
+    foreign_types::{Discount, ProductCharacteristic, ProductVariantVersion, ShipmentMethod},
+    money::Money,
+};
+
+/// Line of an invoice, describing an invoiced order item, the shipping costs of a shipment method or a free-form charge.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
+pub struct InvoiceLineItem {
+    /// UUID of the order of the line, only set on collective invoices.
+    #[serde(default)]
+    pub order_id: Option<Uuid>,
+    /// UUID of the invoiced order item, `None` for lines of manual invoices.
+    pub order_item_id: Option<Uuid>,
+    /// UUID of the product variant of the order item, `None` for lines of manual invoices.
+    pub product_variant_id: Option<Uuid>,
+    /// UUID of the ordered product variant version, `None` for lines of manual invoices.
+    #[serde(default)]
+    pub product_variant_version_id: Option<Uuid>,
+    /// Snapshot of the ordered product variant version, `None` if the version is not known.
+    #[serde(default)]
+    pub product: Option<LineItemProduct>,
+    /// Free-form description of the line.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Quantity of the order item.
+    pub count: u64,
+    /// Total compensatable amount of the line.
+    pub amount: Money,
+    /// Amount of the line before its discounts, `None` for lines without discounts.
+    #[serde(default)]
+    pub undiscounted_amount: Option<Money>,
+    /// Discounts which reduce the undiscounted amount to the amount, in order of application.
+    #[serde(default)]
+    pub discounts: Vec<LineItemDiscount>,
+    /// UUID of the shipment method of a shipping line, `None` for other lines.
+    #[serde(default)]
+    pub shipment_method_id: Option<Uuid>,
+    /// VAT rate included in the amount of a line with its own tax treatment, e.g. `0.19` of shipping lines.
+    #[serde(default)]
+    pub tax_rate: Option<f64>,
+    /// VAT included in the amount of a line with its own tax treatment.
+    #[serde(default)]
+    pub tax_amount: Option<Money>,
+}
+
+/// Product variant version of a line, as it was ordered.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
+pub struct LineItemProduct {
+    /// Name of the product variant.
+    pub name: String,
+    /// Stock keeping unit of the product variant.
+    pub sku: Option<String>,
+    /// Description of the product variant.
+    pub description: Option<String>,
+    /// Characteristics of the product variant, e.g. its color or size.
+    pub characteristics: Vec<ProductCharacteristic>,
+}
+
+impl From<ProductVariantVersion> for LineItemProduct {
+    fn from(value: ProductVariantVersion) -> Self {
+        Self {
+            name: value.name,
+            sku: value.sku,
+            description: value.description,
+            characteristics: value.characteristics,
+        }
+    }
+}
+
+/// Discount applied to a line of an invoice.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
+pub struct LineItemDiscount {
+    /// UUID of the discount.
+    pub discount_id: Uuid,
+    /// Description of the discount.
+    pub description: Option<String>,
+    /// Fraction of the price which is discounted, e.g. `0.1` for 10%.
+    pub discount: f64,
+    /// Reduction of the line by the discount, a negative amount.
+    pub amount: Money,
+}
+
+impl InvoiceLineItem {
+    /// Returns the line with negated amount, as refunded by a credit note.
+    pub fn negated(&self) -> Self {
+        Self {
+            amount: negate(&self.amount),
+            undiscounted_amount: self.undiscounted_amount.as_ref().map(negate),
+            tax_amount: self.tax_amount.as_ref().map(negate),
+            discounts: self
+                .discounts
+                .iter()
+                .map(|discount| LineItemDiscount {
+                    amount: negate(&discount.amount),
+                    ..discount.clone()
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Explains the amount of the line by the discounts which were applied to it in order.
+    ///
+    /// Order events only carry the discounted amount, so the undiscounted amount is derived from it.
+    /// This assumes the discount service applies the discounts multiplicatively in the order of `discount_ids`,
+    /// each to the price reduced by the previous ones, so the amount is the undiscounted amount times `1 - discount` of each discount.
+    /// Amounts are rounded to minor units per discount, rounding differences are attributed to the last discount.
+    ///
+    /// The line is returned unchanged if any of the discounts is unknown, or discounts all of the price,
+    /// as the undiscounted amount cannot be derived from an amount of zero.
+    ///
+    /// * `discount_ids` - UUIDs of the discounts applied to the line, in order of application.
+    /// * `discounts` - Known discounts.
+    pub fn with_discounts(self, discount_ids: &[Uuid], discounts: &[Discount]) -> Self {
+        if discount_ids.is_empty() {
+            return self;
+        }
+        let applied: Option<Vec<&Discount>> = discount_ids
+            .iter()
+            .map(|id| discounts.iter().find(|discount| discount._id == *id))
+            .collect();
+        let Some(applied) = applied else {
+            warn!(
+                "Discounts of order item {:?} are not all known, its discounts are not explained.",
+                self.order_item_id
+            );
+            return self;
+        };
+        if let Some(discount) = applied
+            .iter()
+            .find(|discount| !(discount.discount > 0.0 && discount.discount < 1.0))
+        {
+            warn!(
+                "Discount of UUID: `{}` of order item {:?} is {}, its discounts are not explained.",
+                discount._id, self.order_item_id, discount.discount
+            );
+            return self;
+        }
+        let factor: f64 = applied
+            .iter()
+            .map(|discount| 1.0 - discount.discount)
+            .product();
+        let undiscounted = (self.amount.amount as f64 / factor).round() as i64;
+        let mut remaining = undiscounted;
+        let mut line_discounts = vec![];
+        for (index, discount) in applied.iter().enumerate() {
+            let reduction = match index + 1 == applied.len() {
+                true => remaining - self.amount.amount,
+                false => (remaining as f64 * discount.discount).round() as i64,
+            };
+            remaining -= reduction;
+            line_discounts.push(LineItemDiscount {
+                discount_id: discount._id,
+                description: discount.description.clone(),
+                discount: discount.discount,
+                amount: Money {
+                    amount: -reduction,
+                    currency: self.amount.currency.clone(),
+                },
+            });
+        }
+        Self {
+            undiscounted_amount: Some(Money {
+                amount: undiscounted,
+                currency: self.amount.currency.clone(),
+            }),
+            discounts: line_discounts,
+            ..self
+        }
+    }
+}
+
+impl From<&OrderItemEventData> for InvoiceLineItem {
+    fn from(value: &OrderItemEventData) -> Self {
+        Self {
+            order_id: None,
+            order_item_id: Some(value.id),
+            product_variant_id: Some(value.product_variant_id),
+            product_variant_version_id: Some(value.product_variant_version_id),
+            product: None,
+            description: None,
+            count: value.count,
+            amount: value.compensatable_amount.clone(),
+            undiscounted_amount: None,
+            discounts: vec![],
+            shipment_method_id: None,
+            tax_rate: None,
+            tax_amount: None,
+        }
+    }
+}
+
+/// Creates the lines of order items, describing their ordered product variant versions and explaining their discounted amounts by the replicated discounts.
+///
+/// * `discount_collection` - MongoDB collection of discounts.
+/// * `product_variant_version_collection` - MongoDB collection of product variant versions.
+/// * `order_items` - Invoiced order items.
+pub async fn order_item_line_items(
+    discount_collection: &Collection<Discount>,
+    product_variant_version_collection: &Collection<ProductVariantVersion>,
+    order_items: &[OrderItemEventData],
+) -> Result<Vec<InvoiceLineItem>> {
+    let version_ids: Vec<Uuid> = order_items
+        .iter()
+        .map(|item| item.product_variant_version_id)
+        .collect();
+    let versions: Vec<ProductVariantVersion> = product_variant_version_collection
+        .find(doc! {"_id": {"$in": version_ids}}, None)
+        .await?
+        .try_collect()
+        .await?;
+    let discount_ids: Vec<Uuid> = order_items
+        .iter()
+        .flat_map(|item| item.discount_ids.iter().copied())
+        .collect();
+    let discounts: Vec<Discount> = match discount_ids.is_empty() {
+        true => vec![],
+        false => {
+            discount_collection
+                .find(doc! {"_id": {"$in": discount_ids}}, None)
+                .await?
+                .try_collect()
+                .await?
+        }
+    };
+    Ok(describe_order_items(order_items, &versions, &discounts))
+}
+
+/// Creates the lines of order items, described by the product variant version each was ordered in.
+///
+/// * `order_items` - Invoiced order items.
+/// * `versions` - Known product variant versions of the order items.
+/// * `discounts` - Known discounts of the order items.
+fn describe_order_items(
+    order_items: &[OrderItemEventData],
+    versions: &[ProductVariantVersion],
+    discounts: &[Discount],
+) -> Vec<InvoiceLineItem> {
+    order_items
+        .iter()
+        .map(|item| {
+            let product = versions
+                .iter()
+                .find(|version| version._id == item.product_variant_version_id)
+                .cloned()
+                .map(LineItemProduct::from);
+            if product.is_none() {
+                warn!(
+                    "Product variant version of UUID: `{}` is not known, order item is described by its UUID.",
+                    item.product_variant_version_id
+                );
+            }
+            InvoiceLineItem {
+                product,
+                ..InvoiceLineItem::from(item)
+            }
+            .with_discounts(&item.discount_ids, discounts)
+        })
+        .collect()
+}
+
+/// Creates a shipping line per shipment method of an order, charging the shipping which the order total includes beyond its order items.
+///
+/// The shipping is split over the shipment methods of the order items, weighted by their base fee plus their fee per item,
+/// so the lines add up to what the order charged, also if the fees of a method changed since.
+/// Methods are weighted equally if no fees are known at all, methods without known fees are not charged otherwise.
+/// Lines are in order of first appearance of their shipment method, rounding differences are attributed to the last line.
+///
+/// * `collection` - MongoDB collection of shipment methods.
+/// * `order` - Order of the shipping, with currency already validated.
+pub async fn order_shipping_line_items(
+    collection: &Collection<ShipmentMethod>,
+    order: &OrderEventData,
+) -> Result<Vec<InvoiceLineItem>> {
+    let currency = order.currency()?;
+    let items_total = Money::sum(
+        &currency,
+        order.order_items.iter().map(|item| &item.compensatable_amount),
+    )?;
+    let shipping = order
+        .compensatable_order_amount
+        .amount
+        .checked_sub(items_total.amount)
+        .ok_or(Error::new("Shipping of order overflows."))?;
+    if shipping < 0 {
+        warn!(
+            "Total of order of UUID: `{}` is less than the total of its items, shipping is not charged.",
+            order.id
+        );
+    }
+    if shipping <= 0 {
+        return Ok(vec![]);
+    }
+    let mut item_counts: Vec<(Uuid, u64)> = vec![];
+    for item in &order.order_items {
+        match item_counts
+            .iter_mut()
+            .find(|(id, _)| *id == item.shipment_method_id)
+        {
+            Some((_, count)) => *count += item.count,
+            None => item_counts.push((item.shipment_method_id, item.count)),
+        }
+    }
+    let mut weighted_methods = vec![];
+    for (shipment_method_id, item_count) in item_counts {
+        let shipment_method = collection
+            .find_one(doc! {"_id": shipment_method_id}, None)
+            .await?;
+        let weight = match &shipment_method {
+            Some(shipment_method) => {
+                match fees_of_shipment_method(shipment_method, item_count, &currency) {
+                    Ok(fees) => fees,
+                    Err(e) => {
+                        warn!("{} Its shipping is weighted by no fees.", e.message);
+                        0
+                    }
+                }
+            }
+            None => {
+                warn!(
+                    "Shipment method of UUID: `{}` is not known, its shipping is weighted by no fees.",
+                    shipment_method_id
+                );
+                0
+            }
+        };
+        weighted_methods.push((shipment_method_id, shipment_method, weight));
+    }
+    if weighted_methods.iter().all(|(_, _, weight)| *weight <= 0) {
+        for (_, _, weight) in weighted_methods.iter_mut() {
+            *weight = 1;
+        }
+    } else {
+        weighted_methods.retain(|(_, _, weight)| *weight > 0);
+    }
+    let total_weight: i128 = weighted_methods
+        .iter()
+        .map(|(_, _, weight)| i128::from(*weight))
+        .sum();
+    let method_count = weighted_methods.len();
+    let mut remaining = shipping;
+    let mut line_items = vec![];
+    for (index, (shipment_method_id, shipment_method, weight)) in
+        weighted_methods.into_iter().enumerate()
+    {
+        let amount = match index + 1 == method_count {
+            true => remaining,
+            false => (i128::from(shipping) * i128::from(weight) / total_weight) as i64,
+        };
+        remaining -= amount;
+        let (name, tax_rate) = match shipment_method {
+            Some(shipment_method) => (shipment_method.name, shipment_method.tax_rate),
+            None => (shipment_method_id.to_string(), None),
+        };
+        line_items.push(InvoiceLineItem {
+            order_id: None,
+            order_item_id: None,
+            product_variant_id: None,
+            product_variant_version_id: None,
+            product: None,
+            description: Some(name),
+            count: 1,
+            amount: Money {
+                amount,
+                currency: currency.clone(),
+            },
+            undiscounted_amount: None,
+            discounts: vec![],
+            shipment_method_id: Some(shipment_method_id),
+            tax_rate,
+            tax_amount: tax_rate.map(|rate| Money {
+                amount: (amount as f64 * rate / (1.0 + rate)).round() as i64,
+                currency: currency.clone(),
+            }),
+        });
+    }
+    Ok(line_items)
+}
+
+/// Returns the fees of a shipment method for a number of items, its base fee plus its fee per item.
+///
+/// * `shipment_method` - Shipment method of the items.
+/// * `item_count` - Number of items shipped with the method.
+/// * `currency` - Currency of the order, which the fees must be in.
+fn fees_of_shipment_method(
+    shipment_method: &ShipmentMethod,
+    item_count: u64,
+    currency: &str,
+) -> Result<i64> {
+    if shipment_method.base_fee.currency != currency
+        || shipment_method.fee_per_item.currency != currency
+    {
+        return Err(Error::new(format!(
+            "Fees of shipment method of UUID: `{}` are not in currency `{}` of the order.",
+            shipment_method._id, currency
+        )));
+    }
+    i64::try_from(item_count)
+        .ok()
+        .and_then(|item_count| shipment_method.fee_per_item.amount.checked_mul(item_count))
+        .and_then(|fee_per_item| fee_per_item.checked_add(shipment_method.base_fee.amount))
+        .ok_or(Error::new(format!(
+            "Fees of shipment method of UUID: `{}` for {} items overflow.",
+            shipment_method._id, item_count
+        )))
+}
+
+/// Returns the shipping lines of an order which are charged on the invoice of some of its order items.
+///
+/// The shipping of a shipment method is charged once, on the invoice of the method's first order item,
+/// so orders which are invoiced per shipment or per vendor are not charged it repeatedly.
+///
+/// * `shipping_line_items` - Shipping lines of the whole order.
+/// * `order` - Order of the shipping.
+/// * `order_item_ids` - UUIDs of the invoiced order items.
+pub fn invoiced_shipping_line_items(
+    shipping_line_items: &[InvoiceLineItem],
+    order: &OrderEventData,
+    order_item_ids: &[Uuid],
+) -> Vec<InvoiceLineItem> {
+    shipping_line_items
+        .iter()
+        .filter(|line_item| {
+            order
+                .order_items
+                .iter()
+                .find(|item| Some(item.shipment_method_id) == line_item.shipment_method_id)
+                .is_some_and(|first_item| order_item_ids.contains(&first_item.id))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Creates the negative lines of a credit note which refunds part of an invoice.
+///
+/// The credited amount is split pro rata over the VAT rates of the credited lines, so each line states the VAT it refunds.
+/// Rounding differences are attributed to the last line.
+///
+/// * `line_items` - Lines of the credited invoice.
+/// * `credited` - Credited amount, positive.
+/// * `description` - Description of the lines, e.g. `Partial credit of invoice 42`.
+pub fn partial_credit_line_items(
+    line_items: &[InvoiceLineItem],
+    credited: &Money,
+    description: &str,
+) -> Vec<InvoiceLineItem> {
+    let mut rate_amounts: Vec<(Option<f64>, i64)> = vec![];
+    for item in line_items {
+        match rate_amounts
+            .iter_mut()
+            .find(|(rate, _)| *rate == item.tax_rate)
+        {
+            Some((_, amount)) => *amount += item.amount.amount,
+            None => rate_amounts.push((item.tax_rate, item.amount.amount)),
+        }
+    }
+    rate_amounts.retain(|(_, amount)| *amount > 0);
+    let total: i64 = rate_amounts.iter().map(|(_, amount)| amount).sum();
+    if rate_amounts.is_empty() {
+        rate_amounts.push((None, credited.amount));
+    }
+    let mut remaining = credited.amount;
+    let mut line_items = vec![];
+    for (index, (rate, rate_amount)) in rate_amounts.iter().enumerate() {
+        let share = match index + 1 == rate_amounts.len() {
+            true => remaining,
+            false => (credited.amount as i128 * *rate_amount as i128 / total as i128) as i64,
+        };
+        remaining -= share;
+        line_items.push(InvoiceLineItem {
+            order_id: None,
+            order_item_id: None,
+            product_variant_id: None,
+            product_variant_version_id: None,
+            product: None,
+            description: Some(description.to_string()),
+            count: 1,
+            amount: negate(&Money {
+                amount: share,
+                currency: credited.currency.clone(),
+            }),
+            undiscounted_amount: None,
+            discounts: vec![],
+            shipment_method_id: None,
+            tax_rate: *rate,
+            tax_amount: rate.map(|rate| Money {
+                amount: -(share as f64 * rate / (1.0 + rate)).round() as i64,
+                currency: credited.currency.clone(),
+            }),
+        });
+    }
+    line_items
+}
+
+/// Returns the negated amount.
+fn negate(money: &Money) -> Money {
+    Money {
+        amount: -money.amount,
+        ..money.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::http_event_service::tests::order_item;
+
+    fn line(amount: i64) -> InvoiceLineItem {
+        InvoiceLineItem {
+            order_id: None,
+            order_item_id: Some(Uuid::new()),
+            product_variant_id: None,
+            product_variant_version_id: None,
+            product: None,
+            description: None,
+            count: 1,
+            amount: Money {
+                amount,
+                currency: "EUR".to_string(),
+            },
+            undiscounted_amount: None,
+            discounts: vec![],
+            shipment_method_id: None,
+            tax_rate: None,
+            tax_amount: None,
+        }
+    }
+
+    /// Version of a product variant with a name and a color.
+    fn version(product_variant_id: Uuid, name: &str, color: &str) -> ProductVariantVersion {
+        ProductVariantVersion {
+            _id: Uuid::new(),
+            product_variant_id,
+            name: name.to_string(),
+            sku: Some("SHIRT-1".to_string()),
+            description: Some(format!("{} made of cotton", name)),
+            characteristics: vec![ProductCharacteristic {
+                name: "Color".to_string(),
+                value: color.to_string(),
+            }],
+        }
+    }
+
+    fn discount(fraction: f64) -> Discount {
+        Discount {
+            _id: Uuid::new(),
+            discount: fraction,
+            description: None,
+        }
+    }
+
+    fn undiscounted(line: &InvoiceLineItem) -> Option<i64> {
+        line.undiscounted_amount.as_ref().map(|money| money.amount)
+    }
+
+    fn reductions(line: &InvoiceLineItem) -> Vec<i64> {
+        line.discounts
+            .iter()
+            .map(|discount| discount.amount.amount)
+            .collect()
+    }
+
+    #[test]
+    fn describes_order_items_by_their_ordered_version() {
+        let product_variant_id = Uuid::new();
+        let old = version(product_variant_id, "Shirt", "Red");
+        let new = version(product_variant_id, "Classic shirt", "Blue");
+        let mut item = order_item(1990, Uuid::new());
+        item.product_variant_id = product_variant_id;
+        item.product_variant_version_id = old._id;
+        let line_items = describe_order_items(&[item.clone()], &[new, old], &[]);
+        let product = line_items[0].product.as_ref().unwrap();
+        assert_eq!(product.name, "Shirt");
+        assert_eq!(product.description.as_deref(), Some("Shirt made of cotton"));
+        assert_eq!(product.characteristics[0].value, "Red");
+        assert_eq!(line_items[0].product_variant_version_id, Some(item.product_variant_version_id));
+        assert_eq!(line_items[0].amount.amount, 1990);
+    }
+
+    #[test]
+    fn leaves_order_items_of_unknown_versions_undescribed() {
+        let known = version(Uuid::new(), "Shirt", "Red");
+        let item = order_item(1990, Uuid::new());
+        let line_items = describe_order_items(&[item], &[known], &[]);
+        assert!(line_items[0].product.is_none());
+    }
+
+    #[test]
+    fn derives_undiscounted_amount_of_single_discount() {
+        let discounts = vec![discount(0.2)];
+        let line = line(800).with_discounts(&[discounts[0]._id], &discounts);
+        assert_eq!(undiscounted(&line), Some(1000));
+        assert_eq!(reductions(&line), vec![-200]);
+    }
+
+    #[test]
+    fn applies_discounts_multiplicatively_in_order() {
+        // 1000 - 10% = 900, 900 - 50% = 450
+        let discounts = vec![discount(0.1), discount(0.5)];
+        let ids: Vec<Uuid> = discounts.iter().map(|discount| discount._id).collect();
+        let line = line(450).with_discounts(&ids, &discounts);
+        assert_eq!(undiscounted(&line), Some(1000));
+        assert_eq!(reductions(&line), vec![-100, -450]);
+    }
+
+    #[test]
+    fn attributes_rounding_to_last_discount() {
+        // 999 / (2/3 * 2/3) = 2247.75, rounded to 2248; 2248 - 749 = 1499, 1499 - 500 = 999
+        let discounts = vec![discount(1.0 / 3.0), discount(1.0 / 3.0)];
+        let ids: Vec<Uuid> = discounts.iter().map(|discount| discount._id).collect();
+        let line = line(999).with_discounts(&ids, &discounts);
+        assert_eq!(undiscounted(&line), Some(2248));
+        assert_eq!(reductions(&line), vec![-749, -500]);
+        let total: i64 = reductions(&line).iter().sum();
+        assert_eq!(2248 + total, 999);
+    }
+
+    #[test]
+    fn keeps_line_of_full_discount_unexplained() {
+        let discounts = vec![discount(1.0)];
+        let line = line(0).with_discounts(&[discounts[0]._id], &discounts);
+        assert!(line.undiscounted_amount.is_none());
+        assert!(line.discounts.is_empty());
+    }
+
+    #[test]
+    fn keeps_line_of_unknown_discount_unexplained() {
+        let discounts = vec![discount(0.2)];
+        let line = line(800).with_discounts(&[discounts[0]._id, Uuid::new()], &discounts);
+        assert!(line.undiscounted_amount.is_none());
+        assert!(line.discounts.is_empty());
+    }
+
+    #[test]
+    fn negates_discounts_of_credited_line() {
+        let discounts = vec![discount(0.2)];
+        let line = line(800)
+            .with_discounts(&[discounts[0]._id], &discounts)
+            .negated();
+        assert_eq!(line.amount.amount, -800);
+        assert_eq!(undiscounted(&line), Some(-1000));
+        assert_eq!(reductions(&line), vec![200]);
+    }
+
+    fn shipment_method(base_fee: i64, fee_per_item: i64, currency: &str) -> ShipmentMethod {
+        ShipmentMethod {
+            _id: Uuid::new(),
+            name: "Parcel".to_string(),
+            base_fee: Money {
+                amount: base_fee,
+                currency: currency.to_string(),
+            },
+            fee_per_item: Money {
+                amount: fee_per_item,
+                currency: "EUR".to_string(),
+            },
+            tax_rate: None,
+        }
+    }
+
+    #[test]
+    fn weights_shipment_method_by_base_fee_plus_fee_per_item() {
+        let shipment_method = shipment_method(490, 100, "EUR");
+        assert_eq!(fees_of_shipment_method(&shipment_method, 3, "EUR").unwrap(), 790);
+    }
+
+    #[test]
+    fn rejects_overflowing_fees() {
+        let shipment_method = shipment_method(0, i64::MAX / 2, "EUR");
+        assert!(fees_of_shipment_method(&shipment_method, 3, "EUR").is_err());
+        assert!(fees_of_shipment_method(&shipment_method, u64::MAX, "EUR").is_err());
+    }
+
+    #[test]
+    fn rejects_fees_in_other_currency() {
+        let shipment_method = shipment_method(490, 100, "USD");
+        assert!(fees_of_shipment_method(&shipment_method, 1, "EUR").is_err());
+    }
+}
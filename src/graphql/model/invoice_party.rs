@@ -0,0 +1,73 @@
+use async_graphql::SimpleObject;
+use serde::{Deserialize, Serialize};
+
+use super::foreign_types::{User, UserAddress, VendorAddress};
+
+/// Immutable snapshot of a party (customer or vendor) taken when the invoice is issued.
+///
+/// Later changes to the replicated users and addresses do not affect already issued invoices.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone, Default, PartialEq, Eq)]
+pub struct InvoiceParty {
+    /// Name of the person or company.
+    pub name: String,
+    /// Company name, if the party is a company.
+    pub company: Option<String>,
+    /// Address lines, excluding the country.
+    pub address_lines: Vec<String>,
+    /// Country code of the address, as replicated from the address service.
+    pub country_code: String,
+    /// VAT identification number of the party.
+    pub vat_id: Option<String>,
+}
+
+impl InvoiceParty {
+    /// Creates a snapshot of a customer from the user, the invoice address and the VAT number of the order.
+    pub fn customer(user: &User, user_address: &UserAddress, vat_id: Option<String>) -> Self {
+        Self {
+            name: format!("{} {}", user.first_name, user.last_name),
+            company: non_empty(&user_address.company_name),
+            address_lines: address_lines(
+                &user_address.street1,
+                &user_address.street2,
+                &user_address.postal_code,
+                &user_address.city,
+            ),
+            country_code: user_address.country.clone(),
+            vat_id,
+        }
+    }
+}
+
+impl From<&VendorAddress> for InvoiceParty {
+    fn from(value: &VendorAddress) -> Self {
+        Self {
+            name: value.company_name.clone(),
+            company: non_empty(&value.company_name),
+            address_lines: address_lines(
+                &value.street1,
+                &value.street2,
+                &value.postal_code,
+                &value.city,
+            ),
+            country_code: value.country.clone(),
+            vat_id: value.vat_id.clone(),
+        }
+    }
+}
+
+/// Builds the address lines of a party, skipping empty lines.
+fn address_lines(street1: &str, street2: &str, postal_code: &str, city: &str) -> Vec<String> {
+    let city_line = format!("{} {}", postal_code, city).trim().to_string();
+    [street1.to_string(), street2.to_string(), city_line]
+        .into_iter()
+        .filter(|line| !line.trim().is_empty())
+        .collect()
+}
+
+/// Returns `None` for empty or whitespace only strings.
+fn non_empty(value: &str) -> Option<String> {
+    match value.trim() {
+        "" => None,
+        trimmed => Some(trimmed.to_string()),
+    }
+}
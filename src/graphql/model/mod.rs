@@ -1,3 +1,17 @@
+pub mod audit_entry;
+pub mod billing;
+pub mod credit;
+pub mod delivery;
+pub mod dunning;
 pub mod foreign_types;
 pub mod invoice;
+pub mod invoice_chain;
+pub mod invoice_filter;
+pub mod invoice_line_item;
+pub mod invoice_party;
+pub mod money;
 pub mod order;
+pub mod order_invoicing;
+pub mod payment_terms;
+pub mod recurring;
+pub mod stored_document;
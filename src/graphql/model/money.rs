@@ -0,0 +1,286 @@
+use std::{env, fmt};
+
+use async_graphql::{ComplexObject, Error, Result, SimpleObject};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// ISO 4217 currencies known to the service with their number of minor unit digits and symbol.
+static CURRENCIES: &[(&str, u32, &str)] = &[
+    ("AUD", 2, "A$"),
+    ("BHD", 3, "BHD"),
+    ("CAD", 2, "CA$"),
+    ("CHF", 2, "CHF"),
+    ("CNY", 2, "CN¥"),
+    ("CZK", 2, "Kč"),
+    ("DKK", 2, "kr."),
+    ("EUR", 2, "€"),
+    ("GBP", 2, "£"),
+    ("HUF", 2, "Ft"),
+    ("ISK", 0, "kr"),
+    ("JPY", 0, "¥"),
+    ("KRW", 0, "₩"),
+    ("KWD", 3, "KWD"),
+    ("NOK", 2, "kr"),
+    ("PLN", 2, "zł"),
+    ("SEK", 2, "kr"),
+    ("USD", 2, "$"),
+];
+
+/// Currency of amounts which are received without a currency, configured by `$DEFAULT_CURRENCY`.
+pub static DEFAULT_CURRENCY: Lazy<String> = Lazy::new(|| {
+    let currency = env::var("DEFAULT_CURRENCY").unwrap_or("EUR".to_string());
+    match currency_info(&currency) {
+        Some(_) => currency.to_uppercase(),
+        None => panic!("$DEFAULT_CURRENCY `{}` is not a supported ISO 4217 currency.", currency),
+    }
+});
+
+/// Returns the minor unit digits and symbol of an ISO 4217 currency code.
+fn currency_info(code: &str) -> Option<(u32, &'static str)> {
+    CURRENCIES
+        .iter()
+        .find(|(currency, _, _)| currency.eq_ignore_ascii_case(code))
+        .map(|(_, minor_units, symbol)| (*minor_units, *symbol))
+}
+
+/// Describes how monetary amounts are formatted for a locale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoneyFormat {
+    /// Separator between the major and the minor units.
+    pub decimal_separator: char,
+    /// Separator between groups of three digits of the major units.
+    pub group_separator: char,
+    /// Whether the currency symbol is placed in front of the amount.
+    pub symbol_first: bool,
+    /// Whether a space separates the currency symbol and the amount.
+    pub symbol_spaced: bool,
+}
+
+impl Default for MoneyFormat {
+    /// English formatting, for example `€1,234.56`.
+    fn default() -> Self {
+        Self {
+            decimal_separator: '.',
+            group_separator: ',',
+            symbol_first: true,
+            symbol_spaced: false,
+        }
+    }
+}
+
+/// Monetary amount in the minor units of an ISO 4217 currency.
+#[derive(Debug, Serialize, SimpleObject, Clone, PartialEq, Eq)]
+#[graphql(complex)]
+pub struct Money {
+    /// Amount in minor units of the currency, for example cents.
+    pub amount: i64,
+    /// ISO 4217 currency code.
+    pub currency: String,
+}
+
+#[ComplexObject]
+impl Money {
+    /// Number of minor unit digits of the currency.
+    async fn minor_units(&self) -> u32 {
+        self.minor_unit_digits()
+    }
+
+    /// Amount formatted in English, for example `€1,234.56`.
+    async fn formatted(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Money {
+    /// Creates an amount of minor units in a currency, rejecting unsupported currencies.
+    pub fn new(amount: i64, currency: &str) -> Result<Self> {
+        match currency_info(currency) {
+            Some(_) => Ok(Self {
+                amount,
+                currency: currency.to_uppercase(),
+            }),
+            None => Err(Error::new(format!(
+                "Currency `{}` is not a supported ISO 4217 currency.",
+                currency
+            ))),
+        }
+    }
+
+    /// Creates a zero amount in a currency.
+    pub fn zero(currency: &str) -> Result<Self> {
+        Self::new(0, currency)
+    }
+
+    /// Number of minor unit digits of the currency.
+    pub fn minor_unit_digits(&self) -> u32 {
+        currency_info(&self.currency).map_or(2, |(minor_units, _)| minor_units)
+    }
+
+    /// Adds two amounts, failing if their currencies differ or the sum overflows.
+    pub fn checked_add(&self, other: &Money) -> Result<Money> {
+        if self.currency != other.currency {
+            return Err(Error::new(format!(
+                "Cannot add amounts of different currencies `{}` and `{}`.",
+                self.currency, other.currency
+            )));
+        }
+        let amount = self.amount.checked_add(other.amount).ok_or_else(|| {
+            Error::new(format!("Sum of {} and {} overflows.", self.amount, other.amount))
+        })?;
+        Ok(Money {
+            amount,
+            currency: self.currency.clone(),
+        })
+    }
+
+    /// Sums amounts in a currency, failing if any amount is in a different currency.
+    pub fn sum<'a>(currency: &str, amounts: impl IntoIterator<Item = &'a Money>) -> Result<Money> {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(currency)?, |total, amount| {
+                total.checked_add(amount)
+            })
+    }
+
+    /// Formats the amount as plain decimal number without group separators and symbol, for example `-1234.56`.
+    pub fn decimal(&self) -> String {
+        let minor_units = self.minor_unit_digits();
+        let divisor = 10_u64.pow(minor_units);
+        let absolute = self.amount.unsigned_abs();
+        let sign = if self.amount < 0 { "-" } else { "" };
+        match minor_units {
+            0 => format!("{}{}", sign, absolute),
+            _ => format!(
+                "{}{}.{:0width$}",
+                sign,
+                absolute / divisor,
+                absolute % divisor,
+                width = minor_units as usize
+            ),
+        }
+    }
+
+    /// Formats the amount with the separators and symbol placement of a locale.
+    pub fn format_with(&self, format: &MoneyFormat) -> String {
+        let minor_units = self.minor_unit_digits();
+        let divisor = 10_u64.pow(minor_units);
+        let absolute = self.amount.unsigned_abs();
+        let major = group_digits(absolute / divisor, format.group_separator);
+        let number = match minor_units {
+            0 => major,
+            _ => format!(
+                "{}{}{:0width$}",
+                major,
+                format.decimal_separator,
+                absolute % divisor,
+                width = minor_units as usize
+            ),
+        };
+        let symbol = currency_info(&self.currency).map_or(self.currency.as_str(), |(_, s)| s);
+        let space = if format.symbol_spaced { "\u{a0}" } else { "" };
+        let sign = if self.amount < 0 { "-" } else { "" };
+        match format.symbol_first {
+            true => format!("{}{}{}{}", sign, symbol, space, number),
+            false => format!("{}{}{}{}", sign, number, space, symbol),
+        }
+    }
+}
+
+impl Default for Money {
+    /// Zero in the default currency.
+    fn default() -> Self {
+        Self {
+            amount: 0,
+            currency: DEFAULT_CURRENCY.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format_with(&MoneyFormat::default()))
+    }
+}
+
+/// Wire representation of money, also accepting bare amounts which are in the default currency.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MoneyRepresentation {
+    Amount(i64),
+    Money { amount: i64, currency: String },
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let money = match MoneyRepresentation::deserialize(deserializer)? {
+            MoneyRepresentation::Amount(amount) => Money::new(amount, &DEFAULT_CURRENCY),
+            MoneyRepresentation::Money { amount, currency } => Money::new(amount, &currency),
+        };
+        money.map_err(|e| serde::de::Error::custom(e.message))
+    }
+}
+
+/// Inserts a group separator between each group of three digits.
+pub fn group_digits(value: u64, separator: char) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eur(amount: i64) -> Money {
+        Money::new(amount, "EUR").unwrap()
+    }
+
+    #[test]
+    fn adds_amounts_of_same_currency() {
+        assert_eq!(eur(150).checked_add(&eur(-50)).unwrap(), eur(100));
+        assert_eq!(Money::sum("EUR", &[eur(1), eur(2), eur(3)]).unwrap(), eur(6));
+        assert_eq!(Money::sum("EUR", &[]).unwrap(), eur(0));
+    }
+
+    #[test]
+    fn rejects_overflowing_sum() {
+        assert!(eur(i64::MAX).checked_add(&eur(1)).is_err());
+        assert!(eur(i64::MIN).checked_add(&eur(-1)).is_err());
+        assert!(Money::sum("EUR", &[eur(i64::MAX), eur(1), eur(-1)]).is_err());
+    }
+
+    #[test]
+    fn rejects_mixed_currencies() {
+        let usd = Money::new(100, "USD").unwrap();
+        assert!(eur(100).checked_add(&usd).is_err());
+        assert!(Money::sum("EUR", &[eur(100), usd.clone()]).is_err());
+        assert!(Money::sum("EUR", &[usd]).is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_currency() {
+        assert!(Money::new(100, "XYZ").is_err());
+        assert_eq!(Money::new(100, "eur").unwrap().currency, "EUR");
+    }
+
+    #[test]
+    fn formats_amounts_in_minor_units_of_currency() {
+        assert_eq!(eur(-123456).decimal(), "-1234.56");
+        assert_eq!(Money::new(1234, "JPY").unwrap().decimal(), "1234");
+        assert_eq!(Money::new(1234, "KWD").unwrap().decimal(), "1.234");
+        assert_eq!(eur(123456789).to_string(), "€1,234,567.89");
+        let german = MoneyFormat {
+            decimal_separator: ',',
+            group_separator: '.',
+            symbol_first: false,
+            symbol_spaced: true,
+        };
+        assert_eq!(eur(-123456).format_with(&german), "-1.234,56\u{a0}€");
+    }
+}
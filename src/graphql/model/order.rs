@@ -2,15 +2,21 @@ use async_graphql::{Enum, SimpleObject};
 use bson::Uuid;
 use serde::{Deserialize, Serialize};
 
-use super::invoice::Invoice;
+use super::{invoice::Invoice, order_invoicing::InvoicingProgress};
 
 /// Foreign type of an order.
 #[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
 pub struct Order {
     /// UUID of the order.
     pub _id: Uuid,
-    /// Invoice of the order.
-    pub invoice: Invoice,
+    /// First invoice of the order, `None` until the first order items are invoiced.
+    #[graphql(deprecation = "An order can have an invoice per vendor, use `invoices` instead.")]
+    pub invoice: Option<Invoice>,
+    /// Invoices of the order, one per vendor and shipment, or the collective invoices which consolidate it.
+    pub invoices: Vec<Invoice>,
+    /// Progress of invoicing the order items, `None` for orders validated before it was tracked.
+    #[serde(skip)]
+    pub invoicing_progress: Option<InvoicingProgress>,
 }
 
 /// Describes if order is placed, or yet pending. An order can be rejected during its lifetime.
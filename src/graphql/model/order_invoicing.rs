@@ -0,0 +1,295 @@
+use async_graphql::{Error, Result, SimpleObject};
+use bson::{doc, DateTime, Uuid};
+use mongodb::{
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::event::http_event_service::{OrderEventData, OrderItemEventData};
+
+use super::{
+    invoice_line_item::{invoiced_shipping_line_items, InvoiceLineItem},
+    money::Money,
+};
+
+/// Invoicing state of a validated order, tracking which of its order items are already invoiced.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderInvoicing {
+    /// UUID of the order.
+    pub _id: Uuid,
+    /// Snapshot of the validated order without payment authorization.
+    pub order: OrderEventData,
+    /// UUIDs of the order items which are already invoiced.
+    #[serde(default)]
+    pub invoiced_order_item_ids: Vec<Uuid>,
+    /// Timestamp when the order was validated, `None` for orders recorded before it was tracked.
+    #[serde(default)]
+    pub validated_at: Option<DateTime>,
+    /// Whether the order is invoiced in a collective invoice of its billing period.
+    #[serde(default)]
+    pub collective: bool,
+    /// Shipping lines of the order, recorded at validation so later changes of shipment methods do not reprice it.
+    /// `None` for orders recorded before shipping was recorded.
+    #[serde(default)]
+    pub shipping_line_items: Option<Vec<InvoiceLineItem>>,
+}
+
+/// Invoicing progress of an order, which is invoiced per shipment.
+#[derive(Debug, SimpleObject, Clone)]
+pub struct InvoicingProgress {
+    /// Number of order items of the order.
+    pub total_item_count: u64,
+    /// Number of order items which are already invoiced.
+    pub invoiced_item_count: u64,
+    /// Total compensatable amount of the order.
+    pub total_amount: Money,
+    /// Compensatable amount of the order items which are already invoiced.
+    pub invoiced_amount: Money,
+    /// Whether all order items are invoiced.
+    pub completed: bool,
+}
+
+impl OrderInvoicing {
+    /// Returns the order items of the given UUIDs which are not invoiced yet.
+    pub fn uninvoiced_items(&self, order_item_ids: &[Uuid]) -> Vec<OrderItemEventData> {
+        self.order
+            .order_items
+            .iter()
+            .filter(|item| order_item_ids.contains(&item.id))
+            .filter(|item| !self.invoiced_order_item_ids.contains(&item.id))
+            .cloned()
+            .collect()
+    }
+
+    /// Computes the invoicing progress of the order.
+    ///
+    /// The invoiced amount includes the shipping of the shipment methods whose first order item is invoiced.
+    pub fn progress(&self) -> Result<InvoicingProgress> {
+        let invoiced_items: Vec<&OrderItemEventData> = self
+            .order
+            .order_items
+            .iter()
+            .filter(|item| self.invoiced_order_item_ids.contains(&item.id))
+            .collect();
+        let completed = invoiced_items.len() == self.order.order_items.len();
+        let invoiced_amount = match &self.shipping_line_items {
+            // Orders recorded before shipping was recorded are invoiced at their total.
+            None if completed => self.order.compensatable_order_amount.clone(),
+            shipping_line_items => {
+                let invoiced_shipping = invoiced_shipping_line_items(
+                    shipping_line_items.as_deref().unwrap_or_default(),
+                    &self.order,
+                    &self.invoiced_order_item_ids,
+                );
+                Money::sum(
+                    &self.order.currency()?,
+                    invoiced_items
+                        .iter()
+                        .map(|item| &item.compensatable_amount)
+                        .chain(invoiced_shipping.iter().map(|line_item| &line_item.amount)),
+                )?
+            }
+        };
+        Ok(InvoicingProgress {
+            total_item_count: self.order.order_items.len() as u64,
+            invoiced_item_count: invoiced_items.len() as u64,
+            total_amount: self.order.compensatable_order_amount.clone(),
+            invoiced_amount,
+            completed,
+        })
+    }
+}
+
+/// Records a validated order to be invoiced, keeping the invoicing state if it is already recorded.
+///
+/// Returns the recorded invoicing state, which keeps the billing mode of the first recording on redelivered events.
+///
+/// * `collection` - MongoDB collection of order invoicing states.
+/// * `order` - Validated order.
+/// * `shipping_line_items` - Shipping lines of the order.
+/// * `collective` - Whether the order is invoiced in a collective invoice of its billing period.
+pub async fn record_order_invoicing(
+    collection: &Collection<OrderInvoicing>,
+    order: &OrderEventData,
+    shipping_line_items: &[InvoiceLineItem],
+    collective: bool,
+) -> Result<OrderInvoicing> {
+    let order = OrderEventData {
+        payment_authorization: None,
+        ..order.clone()
+    };
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+    collection
+        .find_one_and_update(
+            doc! {"_id": order.id},
+            doc! {"$setOnInsert": {
+                "order": bson::to_bson(&order)?,
+                "invoiced_order_item_ids": [],
+                "validated_at": DateTime::now(),
+                "collective": collective,
+                "shipping_line_items": bson::to_bson(shipping_line_items)?,
+            }},
+            options,
+        )
+        .await?
+        .ok_or(Error::new(format!(
+            "Invoicing state of order of UUID: `{}` could not be recorded.",
+            order.id
+        )))
+}
+
+/// Marks order items as invoiced, failing if any of them is already invoiced.
+///
+/// Claiming the items before issuing their collective invoice ensures that concurrent runs never invoice an item twice.
+///
+/// * `collection` - MongoDB collection of order invoicing states.
+/// * `order_id` - UUID of the order.
+/// * `order_item_ids` - UUIDs of the order items to invoice.
+pub async fn claim_order_items(
+    collection: &Collection<OrderInvoicing>,
+    order_id: Uuid,
+    order_item_ids: &[Uuid],
+) -> Result<bool> {
+    let result = collection
+        .update_one(
+            doc! {"_id": order_id, "invoiced_order_item_ids": {"$nin": order_item_ids}},
+            doc! {"$addToSet": {"invoiced_order_item_ids": {"$each": order_item_ids}}},
+            None,
+        )
+        .await?;
+    Ok(result.modified_count == 1)
+}
+
+/// Marks order items as invoiced after their invoice is stored.
+///
+/// Idempotent, so items of invoices found on redelivered events are marked as well if marking them failed before.
+///
+/// * `collection` - MongoDB collection of order invoicing states.
+/// * `order_id` - UUID of the order.
+/// * `order_item_ids` - UUIDs of the invoiced order items.
+pub async fn mark_order_items_invoiced(
+    collection: &Collection<OrderInvoicing>,
+    order_id: Uuid,
+    order_item_ids: &[Uuid],
+) -> Result<()> {
+    collection
+        .update_one(
+            doc! {"_id": order_id},
+            doc! {"$addToSet": {"invoiced_order_item_ids": {"$each": order_item_ids}}},
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Releases claimed order items whose invoices could not be issued.
+///
+/// * `collection` - MongoDB collection of order invoicing states.
+/// * `order_id` - UUID of the order.
+/// * `order_item_ids` - UUIDs of the claimed order items.
+pub async fn release_order_items(
+    collection: &Collection<OrderInvoicing>,
+    order_id: Uuid,
+    order_item_ids: &[Uuid],
+) -> Result<()> {
+    collection
+        .update_one(
+            doc! {"_id": order_id},
+            doc! {"$pullAll": {"invoiced_order_item_ids": order_item_ids}},
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::http_event_service::tests::{order, order_item};
+
+    fn shipping_line(amount: i64, shipment_method_id: Uuid) -> InvoiceLineItem {
+        InvoiceLineItem {
+            order_id: None,
+            order_item_id: None,
+            product_variant_id: None,
+            product_variant_version_id: None,
+            product: None,
+            description: None,
+            count: 1,
+            amount: Money {
+                amount,
+                currency: "EUR".to_string(),
+            },
+            undiscounted_amount: None,
+            discounts: vec![],
+            shipment_method_id: Some(shipment_method_id),
+            tax_rate: Some(0.19),
+            tax_amount: None,
+        }
+    }
+
+    /// Order of three items, the first two shipped with one method, the third with another.
+    fn order_invoicing(shipping_recorded: bool) -> OrderInvoicing {
+        let (parcel, freight) = (Uuid::new(), Uuid::new());
+        let items = vec![
+            order_item(100, parcel),
+            order_item(200, parcel),
+            order_item(300, freight),
+        ];
+        OrderInvoicing {
+            _id: Uuid::new(),
+            order: order(items, 720),
+            invoiced_order_item_ids: vec![],
+            validated_at: Some(DateTime::now()),
+            collective: false,
+            shipping_line_items: shipping_recorded
+                .then(|| vec![shipping_line(50, parcel), shipping_line(70, freight)]),
+        }
+    }
+
+    fn invoice_items(invoicing: &mut OrderInvoicing, indices: &[usize]) {
+        for index in indices {
+            let id = invoicing.order.order_items[*index].id;
+            invoicing.invoiced_order_item_ids.push(id);
+        }
+    }
+
+    #[test]
+    fn counts_shipping_with_the_first_item_of_its_shipment_method() {
+        let mut invoicing = order_invoicing(true);
+        invoice_items(&mut invoicing, &[1]);
+        let progress = invoicing.progress().unwrap();
+        assert_eq!(progress.invoiced_item_count, 1);
+        assert_eq!(progress.invoiced_amount.amount, 200);
+        invoice_items(&mut invoicing, &[0]);
+        let progress = invoicing.progress().unwrap();
+        assert_eq!(progress.invoiced_item_count, 2);
+        assert_eq!(progress.invoiced_amount.amount, 350);
+        assert!(!progress.completed);
+    }
+
+    #[test]
+    fn completes_at_order_total_once_all_shipments_are_invoiced() {
+        let mut invoicing = order_invoicing(true);
+        assert_eq!(invoicing.progress().unwrap().invoiced_amount.amount, 0);
+        invoice_items(&mut invoicing, &[2, 0, 1]);
+        let progress = invoicing.progress().unwrap();
+        assert_eq!(progress.total_item_count, 3);
+        assert_eq!(progress.invoiced_item_count, 3);
+        assert_eq!(progress.invoiced_amount, progress.total_amount);
+        assert!(progress.completed);
+    }
+
+    #[test]
+    fn invoices_orders_recorded_without_shipping_at_their_total() {
+        let mut invoicing = order_invoicing(false);
+        invoice_items(&mut invoicing, &[0, 2]);
+        assert_eq!(invoicing.progress().unwrap().invoiced_amount.amount, 400);
+        invoice_items(&mut invoicing, &[1]);
+        assert_eq!(invoicing.progress().unwrap().invoiced_amount.amount, 720);
+    }
+}
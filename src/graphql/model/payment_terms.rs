@@ -0,0 +1,164 @@
+use async_graphql::{Result, SimpleObject};
+use bson::{doc, DateTime, Uuid};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::config::env_var;
+
+use super::money::Money;
+
+/// Payment terms of an invoice, snapshotted at issuance.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone, PartialEq)]
+pub struct PaymentTerms {
+    /// Number of days after issuance until the invoice is due.
+    pub net_days: u32,
+    /// Percentage of the total which is discounted on early payment.
+    pub early_payment_discount_percentage: Option<f64>,
+    /// Number of days after issuance in which the early payment discount applies.
+    pub early_payment_discount_days: Option<u32>,
+}
+
+impl Default for PaymentTerms {
+    /// Payment terms of 14 days net without early payment discount, e.g. of invoices stored before payment terms were recorded.
+    fn default() -> Self {
+        Self {
+            net_days: 14,
+            early_payment_discount_percentage: None,
+            early_payment_discount_days: None,
+        }
+    }
+}
+
+impl PaymentTerms {
+    /// Reads the default payment terms from `$PAYMENT_TERMS_NET_DAYS`, `$PAYMENT_TERMS_DISCOUNT_PERCENTAGE` and `$PAYMENT_TERMS_DISCOUNT_DAYS`.
+    ///
+    /// Read once at startup, as invalid values panic.
+    pub fn from_env() -> Self {
+        Self {
+            net_days: env_var("PAYMENT_TERMS_NET_DAYS").unwrap_or(14),
+            early_payment_discount_percentage: env_var("PAYMENT_TERMS_DISCOUNT_PERCENTAGE"),
+            early_payment_discount_days: env_var("PAYMENT_TERMS_DISCOUNT_DAYS"),
+        }
+    }
+
+    /// Computes the due date of an invoice issued at `issued_at`.
+    pub fn due_at(&self, issued_at: DateTime) -> DateTime {
+        add_days(issued_at, self.net_days)
+    }
+
+    /// Computes the deadline for the early payment discount of an invoice issued at `issued_at`, if any.
+    pub fn discount_deadline(&self, issued_at: DateTime) -> Option<DateTime> {
+        self.early_payment_discount()
+            .map(|(_, days)| add_days(issued_at, days))
+    }
+
+    /// Computes the early payment discount on a total, rounded to minor units, if any.
+    pub fn discount_amount(&self, total: &Money) -> Option<Money> {
+        self.early_payment_discount().map(|(percentage, _)| Money {
+            amount: (total.amount as f64 * percentage / 100.0).round() as i64,
+            currency: total.currency.clone(),
+        })
+    }
+
+    fn early_payment_discount(&self) -> Option<(f64, u32)> {
+        match (
+            self.early_payment_discount_percentage,
+            self.early_payment_discount_days,
+        ) {
+            (Some(percentage), Some(days)) if percentage > 0.0 => Some((percentage, days)),
+            _ => None,
+        }
+    }
+}
+
+/// Configured payment terms for a vendor and/or customer group, stored in the `payment_terms` collection.
+///
+/// A rule with only `customer_group` set applies to all vendors, a rule with only `vendor_id` set to all customer groups.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PaymentTermsRule {
+    pub _id: Uuid,
+    /// UUID of the vendor the rule applies to.
+    pub vendor_id: Option<Uuid>,
+    /// Customer group the rule applies to.
+    pub customer_group: Option<String>,
+    /// Payment terms of the rule.
+    pub terms: PaymentTerms,
+}
+
+/// Queries the payment terms which apply to a vendor and customer group.
+///
+/// The most specific rule wins: vendor and customer group, customer group only, vendor only, and finally the default terms.
+///
+/// * `collection` - MongoDB collection of payment terms rules.
+/// * `default_terms` - Configured default payment terms.
+/// * `vendor_id` - UUID of the vendor issuing the invoice.
+/// * `customer_group` - Customer group of the invoiced user.
+pub async fn query_payment_terms(
+    collection: &Collection<PaymentTermsRule>,
+    default_terms: &PaymentTerms,
+    vendor_id: Option<Uuid>,
+    customer_group: Option<&str>,
+) -> Result<PaymentTerms> {
+    let candidates = [
+        (vendor_id, customer_group),
+        (None, customer_group),
+        (vendor_id, None),
+    ];
+    for (vendor_id, customer_group) in candidates {
+        if vendor_id.is_none() && customer_group.is_none() {
+            continue;
+        }
+        let filter = doc! {"vendor_id": vendor_id, "customer_group": customer_group};
+        if let Some(rule) = collection.find_one(filter, None).await? {
+            return Ok(rule.terms);
+        }
+    }
+    Ok(default_terms.clone())
+}
+
+/// Adds a number of days to a timestamp.
+fn add_days(date_time: DateTime, days: u32) -> DateTime {
+    DateTime::from_chrono(date_time.to_chrono() + chrono::Duration::days(days.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(rfc3339: &str) -> DateTime {
+        DateTime::parse_rfc3339_str(rfc3339).unwrap()
+    }
+
+    fn terms(percentage: Option<f64>, days: Option<u32>) -> PaymentTerms {
+        PaymentTerms {
+            net_days: 30,
+            early_payment_discount_percentage: percentage,
+            early_payment_discount_days: days,
+        }
+    }
+
+    #[test]
+    fn computes_due_date_across_months() {
+        let issued_at = date("2026-01-20T10:00:00Z");
+        assert_eq!(terms(None, None).due_at(issued_at), date("2026-02-19T10:00:00Z"));
+        assert_eq!(PaymentTerms::default().due_at(issued_at), date("2026-02-03T10:00:00Z"));
+    }
+
+    #[test]
+    fn computes_early_payment_discount() {
+        let terms = terms(Some(2.0), Some(10));
+        let issued_at = date("2026-01-20T10:00:00Z");
+        assert_eq!(terms.discount_deadline(issued_at), Some(date("2026-01-30T10:00:00Z")));
+        let total = Money::new(12345, "EUR").unwrap();
+        assert_eq!(terms.discount_amount(&total), Some(Money::new(247, "EUR").unwrap()));
+    }
+
+    #[test]
+    fn ignores_incomplete_early_payment_discount() {
+        let total = Money::new(12345, "EUR").unwrap();
+        for terms in [terms(Some(2.0), None), terms(None, Some(10)), terms(Some(0.0), Some(10))] {
+            assert_eq!(terms.discount_deadline(date("2026-01-20T10:00:00Z")), None);
+            assert_eq!(terms.discount_amount(&total), None);
+        }
+    }
+}
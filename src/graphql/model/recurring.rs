@@ -0,0 +1,281 @@
+use async_graphql::{Enum, Error, Result, SimpleObject};
+use bson::{DateTime, Uuid};
+use chrono::{Datelike, Months};
+use serde::{Deserialize, Serialize};
+
+use crate::event::http_event_service::{BillingPlanEventData, SubscriptionEventData};
+
+use super::{billing::BillingPeriod, money::Money};
+
+/// Interval in which a subscription is billed.
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BillingInterval {
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl BillingInterval {
+    /// Number of months of a billing period.
+    fn months(&self) -> u32 {
+        match self {
+            BillingInterval::Monthly => 1,
+            BillingInterval::Quarterly => 3,
+            BillingInterval::Yearly => 12,
+        }
+    }
+
+    /// Returns the billing period of this interval which starts at a timestamp, of a subscription started at `anchor`.
+    ///
+    /// Periods end a whole number of intervals after the anchor, so a subscription started on the 31st is billed
+    /// on the last day of shorter months and on the 31st again afterwards.
+    ///
+    /// * `anchor` - Timestamp when the subscription started.
+    /// * `start` - Start of the period.
+    pub fn period_starting_at(&self, anchor: DateTime, start: DateTime) -> Result<BillingPeriod> {
+        let (anchor_date, start_date) = (anchor.to_chrono(), start.to_chrono());
+        let elapsed_months = (start_date.year() - anchor_date.year()) * 12
+            + start_date.month() as i32
+            - anchor_date.month() as i32;
+        let mut intervals = (elapsed_months.max(0) as u32 / self.months()).max(1);
+        loop {
+            let end = anchor_date
+                .checked_add_months(Months::new(intervals * self.months()))
+                .ok_or(Error::new("Billing period ends out of range."))?;
+            if end > start_date {
+                return Ok(BillingPeriod {
+                    start,
+                    end: DateTime::from_chrono(end),
+                });
+            }
+            intervals += 1;
+        }
+    }
+}
+
+/// Billing plan of a subscription product, replicated from the subscription service.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
+pub struct BillingPlan {
+    /// UUID of the billing plan.
+    pub _id: Uuid,
+    /// Name of the plan, printed on invoices.
+    pub name: String,
+    /// UUID of the vendor selling the plan, `None` for the shop's own vendor.
+    pub vendor_id: Option<Uuid>,
+    /// Price of one billing period.
+    pub price: Money,
+    /// Interval in which the plan is billed.
+    pub interval: BillingInterval,
+}
+
+impl From<BillingPlanEventData> for BillingPlan {
+    fn from(value: BillingPlanEventData) -> Self {
+        Self {
+            _id: value.id,
+            name: value.name,
+            vendor_id: value.vendor_id,
+            price: value.price,
+            interval: value.interval,
+        }
+    }
+}
+
+impl BillingPlan {
+    /// Returns the price of the plan for the remainder of a billing period, rounded to minor units.
+    ///
+    /// * `period` - Billing period the plan is prorated in.
+    /// * `from` - Timestamp from which the plan is prorated.
+    pub fn prorated_price(&self, period: &BillingPeriod, from: DateTime) -> Money {
+        let length = period.end.timestamp_millis() - period.start.timestamp_millis();
+        let remaining = (period.end.timestamp_millis() - from.timestamp_millis()).clamp(0, length);
+        let amount = match length {
+            0 => 0,
+            _ => {
+                let scaled = i128::from(self.price.amount) * i128::from(remaining);
+                let length = i128::from(length);
+                ((scaled + length / 2) / length) as i64
+            }
+        };
+        Money {
+            amount,
+            currency: self.price.currency.clone(),
+        }
+    }
+}
+
+/// Status of a customer subscription.
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum SubscriptionStatus {
+    /// Subscription is billed each period.
+    #[default]
+    Active,
+    /// Subscription is cancelled and no longer billed.
+    Cancelled,
+}
+
+/// Subscription of a customer to a billing plan, replicated from the subscription service.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
+pub struct CustomerSubscription {
+    /// UUID of the subscription.
+    pub _id: Uuid,
+    /// UUID of the subscribed user.
+    pub user_id: Uuid,
+    /// UUID of the current billing plan.
+    pub plan_id: Uuid,
+    /// UUID of the address invoices of the subscription are issued to.
+    pub invoice_address_id: Uuid,
+    /// VAT number of the customer, set for B2B subscriptions.
+    pub vat_number: Option<String>,
+    /// Status of the subscription.
+    pub status: SubscriptionStatus,
+    /// Timestamp when the subscription started.
+    pub started_at: DateTime,
+    /// Timestamp when the subscription was cancelled.
+    pub cancelled_at: Option<DateTime>,
+    /// Start of the next billing period, which is invoiced in advance once it begins.
+    pub next_billing_at: DateTime,
+    /// Billing period which was invoiced last, `None` before the first invoice.
+    pub current_period: Option<BillingPeriod>,
+    /// UUID of the invoice of the current billing period.
+    pub current_invoice_id: Option<Uuid>,
+}
+
+impl From<SubscriptionEventData> for CustomerSubscription {
+    fn from(value: SubscriptionEventData) -> Self {
+        let now = DateTime::now();
+        Self {
+            _id: value.id,
+            user_id: value.user_id,
+            plan_id: value.plan_id,
+            invoice_address_id: value.invoice_address_id,
+            vat_number: value.vat_number,
+            status: SubscriptionStatus::Active,
+            started_at: now,
+            cancelled_at: None,
+            next_billing_at: now,
+            current_period: None,
+            current_invoice_id: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+    fn plan(amount: i64) -> BillingPlan {
+        BillingPlan {
+            _id: Uuid::new(),
+            name: "Premium".to_string(),
+            vendor_id: None,
+            price: Money {
+                amount,
+                currency: "EUR".to_string(),
+            },
+            interval: BillingInterval::Monthly,
+        }
+    }
+
+    /// Billing period of 30 days.
+    fn period() -> BillingPeriod {
+        BillingPeriod {
+            start: DateTime::from_millis(0),
+            end: DateTime::from_millis(30 * DAY_MILLIS),
+        }
+    }
+
+    #[test]
+    fn prorates_remainder_of_period() {
+        let from = DateTime::from_millis(10 * DAY_MILLIS);
+        assert_eq!(plan(3000).prorated_price(&period(), from).amount, 2000);
+    }
+
+    #[test]
+    fn charges_whole_period_from_its_start() {
+        let price = plan(999).prorated_price(&period(), period().start);
+        assert_eq!(price.amount, 999);
+        assert_eq!(price.currency, "EUR");
+    }
+
+    #[test]
+    fn clamps_timestamps_outside_of_period() {
+        let before = DateTime::from_millis(-DAY_MILLIS);
+        let after = DateTime::from_millis(31 * DAY_MILLIS);
+        assert_eq!(plan(3000).prorated_price(&period(), before).amount, 3000);
+        assert_eq!(plan(3000).prorated_price(&period(), after).amount, 0);
+    }
+
+    #[test]
+    fn rounds_to_nearest_minor_unit() {
+        // 1000 * 29 / 30 = 966.67
+        let from = DateTime::from_millis(DAY_MILLIS);
+        assert_eq!(plan(1000).prorated_price(&period(), from).amount, 967);
+        // 1000 * 1 / 30 = 33.33
+        let from = DateTime::from_millis(29 * DAY_MILLIS);
+        assert_eq!(plan(1000).prorated_price(&period(), from).amount, 33);
+    }
+
+    #[test]
+    fn credit_and_charge_of_plan_change_net_to_price_difference() {
+        let from = DateTime::from_millis(15 * DAY_MILLIS);
+        let credit = plan(1000).prorated_price(&period(), from);
+        let charge = plan(3000).prorated_price(&period(), from);
+        assert_eq!(charge.amount - credit.amount, 1000);
+    }
+
+    fn date(rfc3339: &str) -> DateTime {
+        DateTime::parse_rfc3339_str(rfc3339).unwrap()
+    }
+
+    /// Ends of the first billing periods of a subscription started at an RFC 3339 timestamp.
+    fn period_ends(interval: BillingInterval, started_at: &str, count: usize) -> Vec<DateTime> {
+        let anchor = date(started_at);
+        let mut start = anchor;
+        let mut ends = vec![];
+        for _ in 0..count {
+            start = interval.period_starting_at(anchor, start).unwrap().end;
+            ends.push(start);
+        }
+        ends
+    }
+
+    #[test]
+    fn bills_monthly_on_anchor_day_after_shorter_months() {
+        let ends = period_ends(BillingInterval::Monthly, "2026-01-31T09:00:00Z", 4);
+        let expected = [
+            "2026-02-28T09:00:00Z",
+            "2026-03-31T09:00:00Z",
+            "2026-04-30T09:00:00Z",
+            "2026-05-31T09:00:00Z",
+        ];
+        assert_eq!(ends, expected.map(date));
+    }
+
+    #[test]
+    fn bills_quarterly_and_yearly_from_anchor() {
+        let ends = period_ends(BillingInterval::Quarterly, "2025-11-30T00:00:00Z", 2);
+        assert_eq!(ends, ["2026-02-28T00:00:00Z", "2026-05-30T00:00:00Z"].map(date));
+        let ends = period_ends(BillingInterval::Yearly, "2024-02-29T00:00:00Z", 2);
+        assert_eq!(ends, ["2025-02-28T00:00:00Z", "2026-02-28T00:00:00Z"].map(date));
+    }
+
+    #[test]
+    fn realigns_drifted_period_to_anchor_day() {
+        let period = BillingInterval::Monthly
+            .period_starting_at(date("2026-01-31T00:00:00Z"), date("2026-03-28T00:00:00Z"))
+            .unwrap();
+        assert_eq!(period.start, date("2026-03-28T00:00:00Z"));
+        assert_eq!(period.end, date("2026-03-31T00:00:00Z"));
+    }
+
+    #[test]
+    fn prorates_empty_period_to_zero() {
+        let empty = BillingPeriod {
+            start: DateTime::from_millis(0),
+            end: DateTime::from_millis(0),
+        };
+        assert_eq!(plan(3000).prorated_price(&empty, empty.start).amount, 0);
+    }
+}
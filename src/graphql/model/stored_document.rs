@@ -0,0 +1,65 @@
+use async_graphql::{Enum, SimpleObject};
+use bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::render::{pdf::render_invoice_pdf, xml::render_invoice_xml};
+
+use super::invoice::Invoice;
+
+/// Format of a generated invoice document.
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DocumentFormat {
+    Pdf,
+    Xml,
+}
+
+impl DocumentFormat {
+    /// All formats which are generated for each invoice.
+    pub const ALL: [DocumentFormat; 2] = [DocumentFormat::Pdf, DocumentFormat::Xml];
+
+    /// File extension of the format, also used in download URLs.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DocumentFormat::Pdf => "pdf",
+            DocumentFormat::Xml => "xml",
+        }
+    }
+
+    /// MIME type of the format.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            DocumentFormat::Pdf => "application/pdf",
+            DocumentFormat::Xml => "application/xml",
+        }
+    }
+
+    /// Parses a format from its file extension.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+
+    /// Renders an invoice in the format.
+    pub fn render(&self, invoice: &Invoice) -> Vec<u8> {
+        match self {
+            DocumentFormat::Pdf => render_invoice_pdf(invoice),
+            DocumentFormat::Xml => render_invoice_xml(invoice).into_bytes(),
+        }
+    }
+}
+
+/// Generated invoice document persisted in object storage.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
+pub struct StoredDocument {
+    /// Format of the document.
+    pub format: DocumentFormat,
+    /// Key of the document in the object storage.
+    pub storage_key: String,
+    /// Hex encoded SHA-256 hash of the document content.
+    pub content_hash: String,
+    /// Size of the document in bytes.
+    pub size: u64,
+    /// Timestamp when the document was stored.
+    pub stored_at: DateTime,
+}
@@ -0,0 +1,559 @@
+use async_graphql::{Context, Error, Object, Result};
+use bson::{doc, DateTime, Uuid};
+use log::warn;
+use mongodb::Collection;
+use serde::Serialize;
+
+use crate::{
+    authorization::{authorize_roles, Role},
+    config::ServiceContext,
+    dispatch::dispatch_invoice,
+    i18n::Locale,
+    event::{
+        bus::InvoiceBusEvent,
+        http_event_service::{
+            create_or_update_vendor_address_in_mongodb, issue_order_invoices, publish_event,
+            publish_pending_change_events, publish_pending_event, spawn_invoice_post_processing,
+            HttpEventServiceState,
+        },
+        model::{
+            invoice_status_changed_dto::InvoiceStatusChangedDTO,
+            vendor_address_set_dto::VendorAddressSetDTO,
+        },
+    },
+};
+
+use super::{
+    model::{
+        audit_entry::AuditAction,
+        billing::BillingMode,
+        credit::{release_credit, reserve_credit},
+        foreign_types::{User, VendorAddress},
+        invoice::{Invoice, InvoiceDocumentType, InvoiceStatus, IssuedEvent, PendingEvent},
+        invoice_chain::InvoiceNumberSequence,
+        invoice_line_item::partial_credit_line_items,
+        money::Money,
+    },
+    mutation_input_structs::{
+        CancelInvoiceInput, CreateManualInvoiceInput, IssueCreditNoteInput, OrderInput,
+        ReissueInvoiceInput, ResendInvoiceInput, VendorAddressInput,
+    },
+    query::{audit_entry_of_request, query_object},
+};
+
+/// Describes GraphQL invoice mutations.
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    /// Back-office mutation which creates a manual invoice of free-form lines, e.g. for service fees.
+    ///
+    /// The invoice is numbered, rendered, sent and published like invoices of orders.
+    async fn create_manual_invoice<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "Customer, invoice address and lines of the invoice.")]
+        input: CreateManualInvoiceInput,
+    ) -> Result<Invoice> {
+        authorize_roles(ctx, &[Role::Admin, Role::Employee])?;
+        let context = ctx.data::<ServiceContext>()?;
+        let invoice = Invoice::new_manual(input, context).await?;
+        context
+            .audit_log
+            .record(
+                audit_entry_of_request(ctx, AuditAction::InvoiceCreated, Some(invoice._id))
+                    .with_details(format!(
+                        "Manual invoice `{}` of {}.",
+                        invoice.invoice_number, invoice.total
+                    )),
+            )
+            .await;
+        publish_pending_event(&invoice_collection(context), &invoice).await;
+        context
+            .event_bus
+            .publish(InvoiceBusEvent::Created(invoice.clone()));
+        spawn_post_processing(context, invoice.clone());
+        Ok(invoice)
+    }
+
+    /// Back-office mutation which issues non-binding proformas of a pending order, one per vendor.
+    ///
+    /// The order is not replicated before it is created, so its items and amounts are taken from the input.
+    /// The proformas are converted to invoices when the order is validated.
+    async fn create_proforma<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "Pending order to issue proformas of.")] order: OrderInput,
+    ) -> Result<Vec<Invoice>> {
+        authorize_roles(ctx, &[Role::Admin, Role::Employee])?;
+        let context = ctx.data::<ServiceContext>()?;
+        let order = order.into_order_event_data()?;
+        order.currency()?;
+        let state = HttpEventServiceState::new(context.clone());
+        let audit_entry = audit_entry_of_request(ctx, AuditAction::InvoiceCreated, None);
+        issue_order_invoices(
+            &state,
+            &order,
+            &order.order_items,
+            None,
+            InvoiceDocumentType::Proforma,
+            &audit_entry,
+        )
+        .await
+        .map_err(|_| Error::new(format!("Issuing proformas of order `{}` failed.", order.id)))
+    }
+
+    /// Admin mutation which supersedes an invoice by a corrective invoice with corrected customer data.
+    ///
+    /// The original invoice is cancelled, the corrective invoice is issued in the same numbering sequence and sent to the customer.
+    async fn reissue_invoice<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "Invoice to correct and corrected data.")] input: ReissueInvoiceInput,
+    ) -> Result<Invoice> {
+        authorize_roles(ctx, &[Role::Admin])?;
+        let context = ctx.data::<ServiceContext>()?;
+        let collection = invoice_collection(context);
+        let invoice = query_object(&collection, input.id).await?;
+        if matches!(
+            invoice.document_type,
+            InvoiceDocumentType::CreditNote | InvoiceDocumentType::Proforma
+        ) {
+            return Err(Error::new("Credit notes and proformas cannot be reissued."));
+        }
+        let mut corrective =
+            invoice.derive_document(InvoiceDocumentType::CorrectiveInvoice, input.reason.clone());
+        if let Some(customer) = input.customer {
+            corrective.customer = customer.into();
+            corrective.vat_number = corrective.customer.vat_id.clone();
+        }
+        corrective.status = invoice.status;
+        corrective.paid_at = invoice.paid_at;
+        corrective.pending_event = Some(IssuedEvent::CorrectiveInvoice);
+        let status_changed = PendingEvent::new(
+            "invoice/invoice/status-changed",
+            &InvoiceStatusChangedDTO::from((
+                &invoice,
+                InvoiceStatus::Cancelled,
+                Some(input.reason.clone()),
+            )),
+        )?;
+        supersede_invoice(&collection, &invoice, corrective._id, &input.reason, &status_changed)
+            .await?;
+        if let Err(e) = issue_and_insert(context, &mut corrective).await {
+            restore_superseded_invoice(&collection, &invoice, &status_changed).await;
+            return Err(e);
+        }
+        context
+            .audit_log
+            .record(
+                audit_entry_of_request(ctx, AuditAction::InvoiceReissued, Some(invoice._id))
+                    .with_details(format!(
+                        "Superseded by `{}`: {}",
+                        corrective.invoice_number, input.reason
+                    )),
+            )
+            .await;
+        let superseded = query_object(&collection, invoice._id).await?;
+        publish_pending_change_events(&collection, &superseded).await;
+        publish_pending_event(&collection, &corrective).await;
+        context
+            .event_bus
+            .publish(InvoiceBusEvent::StatusChanged(superseded));
+        context
+            .event_bus
+            .publish(InvoiceBusEvent::Created(corrective.clone()));
+        spawn_post_processing(context, corrective.clone());
+        Ok(corrective)
+    }
+
+    /// Admin mutation which cancels an open invoice.
+    async fn cancel_invoice<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "Invoice to cancel and reason.")] input: CancelInvoiceInput,
+    ) -> Result<Invoice> {
+        authorize_roles(ctx, &[Role::Admin])?;
+        let context = ctx.data::<ServiceContext>()?;
+        let collection = invoice_collection(context);
+        let invoice = query_object(&collection, input.id).await?;
+        let status_changed = PendingEvent::new(
+            "invoice/invoice/status-changed",
+            &InvoiceStatusChangedDTO::from((
+                &invoice,
+                InvoiceStatus::Cancelled,
+                Some(input.reason.clone()),
+            )),
+        )?;
+        let result = collection
+            .update_one(
+                doc! {"_id": invoice._id, "status": {"$in": ["Open", null]}},
+                doc! {
+                    "$set": {
+                        "status": bson::to_bson(&InvoiceStatus::Cancelled)?,
+                        "cancelled_at": DateTime::now(),
+                        "cancellation_reason": &input.reason,
+                    },
+                    "$push": {"pending_events": bson::to_bson(&status_changed)?},
+                },
+                None,
+            )
+            .await?;
+        if result.modified_count == 0 {
+            return Err(Error::new(format!(
+                "Invoice of UUID: `{}` is not open and cannot be cancelled.",
+                invoice._id
+            )));
+        }
+        context
+            .audit_log
+            .record(
+                audit_entry_of_request(ctx, AuditAction::InvoiceCancelled, Some(invoice._id))
+                    .with_details(input.reason),
+            )
+            .await;
+        let invoice = query_object(&collection, invoice._id).await?;
+        publish_pending_change_events(&collection, &invoice).await;
+        context
+            .event_bus
+            .publish(InvoiceBusEvent::StatusChanged(invoice.clone()));
+        Ok(invoice)
+    }
+
+    /// Admin mutation which issues a credit note refunding all or part of an invoice.
+    ///
+    /// The credited amount is limited to the part of the invoice total which is not yet credited.
+    async fn issue_credit_note<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "Invoice to credit, credited amount and reason.")]
+        input: IssueCreditNoteInput,
+    ) -> Result<Invoice> {
+        authorize_roles(ctx, &[Role::Admin])?;
+        let context = ctx.data::<ServiceContext>()?;
+        let collection = invoice_collection(context);
+        let invoice = query_object(&collection, input.invoice_id).await?;
+        if matches!(
+            invoice.document_type,
+            InvoiceDocumentType::CreditNote | InvoiceDocumentType::Proforma
+        ) {
+            return Err(Error::new("Credit notes and proformas cannot be credited."));
+        }
+        if invoice.status == InvoiceStatus::Cancelled {
+            return Err(Error::new(format!(
+                "Invoice of UUID: `{}` is cancelled and cannot be credited.",
+                invoice._id
+            )));
+        }
+        let amount = reserve_credit(&collection, &invoice, input.amount).await?;
+        let mut credit_note =
+            invoice.derive_document(InvoiceDocumentType::CreditNote, input.reason.clone());
+        credit_note.total = Money::new(-amount, &invoice.total.currency)?;
+        credit_note.line_items = match amount == invoice.total.amount {
+            true => credit_note
+                .line_items
+                .iter()
+                .map(|item| item.negated())
+                .collect(),
+            false => partial_credit_line_items(
+                &invoice.line_items,
+                &Money::new(amount, &invoice.total.currency)?,
+                &Locale::from_tag(&invoice.locale)
+                    .message("invoice.partial_credit")
+                    .replace("{number}", &invoice.invoice_number),
+            ),
+        };
+        credit_note.due_at = None;
+        credit_note.discount_deadline = None;
+        credit_note.early_payment_discount = None;
+        credit_note.pending_event = Some(IssuedEvent::CreditNote);
+        if let Err(e) = issue_and_insert(context, &mut credit_note).await {
+            release_credit(&collection, &invoice, amount).await;
+            return Err(e);
+        }
+        context
+            .audit_log
+            .record(
+                audit_entry_of_request(ctx, AuditAction::CreditNoteIssued, Some(invoice._id))
+                    .with_details(format!(
+                        "Credit note `{}` of {}: {}",
+                        credit_note.invoice_number, credit_note.total, input.reason
+                    )),
+            )
+            .await;
+        publish_pending_event(&collection, &credit_note).await;
+        context
+            .event_bus
+            .publish(InvoiceBusEvent::Created(credit_note.clone()));
+        spawn_post_processing(context, credit_note.clone());
+        Ok(credit_note)
+    }
+
+    /// Back-office mutation which marks an open invoice as paid.
+    async fn mark_invoice_paid<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "UUID of invoice to mark as paid.")] id: Uuid,
+    ) -> Result<Invoice> {
+        authorize_roles(ctx, &[Role::Admin, Role::Employee])?;
+        let context = ctx.data::<ServiceContext>()?;
+        let collection = invoice_collection(context);
+        let invoice = query_object(&collection, id).await?;
+        if invoice.document_type == InvoiceDocumentType::Proforma {
+            return Err(Error::new("Proformas are non-binding and cannot be paid."));
+        }
+        let status_changed = PendingEvent::new(
+            "invoice/invoice/status-changed",
+            &InvoiceStatusChangedDTO::from((&invoice, InvoiceStatus::Paid, None)),
+        )?;
+        let result = collection
+            .update_one(
+                doc! {"_id": invoice._id, "status": {"$in": ["Open", null]}},
+                doc! {
+                    "$set": {
+                        "status": bson::to_bson(&InvoiceStatus::Paid)?,
+                        "paid_at": DateTime::now(),
+                    },
+                    "$push": {"pending_events": bson::to_bson(&status_changed)?},
+                },
+                None,
+            )
+            .await?;
+        if result.modified_count == 0 {
+            return Err(Error::new(format!(
+                "Invoice of UUID: `{}` is not open and cannot be marked as paid.",
+                invoice._id
+            )));
+        }
+        context
+            .audit_log
+            .record(
+                audit_entry_of_request(ctx, AuditAction::StatusChanged, Some(invoice._id))
+                    .with_details(format!("{:?} -> {:?}", invoice.status, InvoiceStatus::Paid)),
+            )
+            .await;
+        let invoice = query_object(&collection, invoice._id).await?;
+        publish_pending_change_events(&collection, &invoice).await;
+        context
+            .event_bus
+            .publish(InvoiceBusEvent::StatusChanged(invoice.clone()));
+        Ok(invoice)
+    }
+
+    /// Back-office mutation which sends an invoice to the customer again, optionally to a different recipient.
+    async fn resend_invoice<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "Invoice to send and optional recipient.")] input: ResendInvoiceInput,
+    ) -> Result<Invoice> {
+        authorize_roles(ctx, &[Role::Admin, Role::Employee])?;
+        let context = ctx.data::<ServiceContext>()?;
+        let collection = invoice_collection(context);
+        let mut invoice = query_object(&collection, input.id).await?;
+        if let Some(recipient) = input.recipient {
+            collection
+                .update_one(
+                    doc! {"_id": invoice._id},
+                    doc! {"$set": {"delivery.recipient": &recipient}},
+                    None,
+                )
+                .await?;
+            invoice.delivery.recipient = Some(recipient);
+        }
+        let status = dispatch_invoice(
+            &collection,
+            &context.dispatch_config,
+            &context.audit_log,
+            &invoice,
+            true,
+        )
+        .await?;
+        context
+            .audit_log
+            .record(
+                audit_entry_of_request(ctx, AuditAction::InvoiceResent, Some(invoice._id))
+                    .with_details(format!(
+                        "{:?} to {}",
+                        status,
+                        invoice.delivery.recipient.as_deref().unwrap_or("-")
+                    )),
+            )
+            .await;
+        let invoice = query_object(&collection, invoice._id).await?;
+        publish_pending_change_events(&collection, &invoice).await;
+        Ok(invoice)
+    }
+
+    /// Admin mutation which sets the vendor address used on invoices issued afterwards.
+    async fn set_vendor_address<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "Vendor address to create or replace.")] input: VendorAddressInput,
+    ) -> Result<VendorAddress> {
+        authorize_roles(ctx, &[Role::Admin])?;
+        let context = ctx.data::<ServiceContext>()?;
+        let collection = context
+            .db_client
+            .collection::<VendorAddress>("vendor_address");
+        let vendor_address = VendorAddress::from(input);
+        create_or_update_vendor_address_in_mongodb(&collection, vendor_address.clone())
+            .await
+            .map_err(|_| Error::new("Setting vendor address failed."))?;
+        context
+            .audit_log
+            .record(
+                audit_entry_of_request(ctx, AuditAction::VendorAddressSet, None).with_details(
+                    format!(
+                        "Vendor address of UUID: `{}` of vendor {:?}.",
+                        vendor_address._id, vendor_address.vendor_id
+                    ),
+                ),
+            )
+            .await;
+        publish(
+            "invoice/vendor-address/set",
+            &VendorAddressSetDTO::from(&vendor_address),
+        )
+        .await;
+        Ok(vendor_address)
+    }
+
+    /// Back-office mutation which sets whether the orders of a user are invoiced per order or collectively per month.
+    ///
+    /// Applies to orders validated afterwards.
+    async fn set_billing_mode<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "UUID of the user.")] user_id: Uuid,
+        #[graphql(desc = "Billing mode of the user's orders.")] billing_mode: BillingMode,
+    ) -> Result<BillingMode> {
+        authorize_roles(ctx, &[Role::Admin, Role::Employee])?;
+        let context = ctx.data::<ServiceContext>()?;
+        let result = context
+            .db_client
+            .collection::<User>("user")
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$set": {"billing_mode": bson::to_bson(&billing_mode)?}},
+                None,
+            )
+            .await?;
+        if result.matched_count == 0 {
+            return Err(Error::new(format!("User with UUID: `{}` not found.", user_id)));
+        }
+        context
+            .audit_log
+            .record(
+                audit_entry_of_request(ctx, AuditAction::BillingModeSet, None).with_details(
+                    format!("Billing mode of user `{}` set to {:?}.", user_id, billing_mode),
+                ),
+            )
+            .await;
+        Ok(billing_mode)
+    }
+}
+
+/// Returns the MongoDB collection of invoices.
+fn invoice_collection(context: &ServiceContext) -> Collection<Invoice> {
+    context.db_client.collection::<Invoice>("invoices")
+}
+
+/// Issues a document in the numbering sequence of its vendor and inserts it.
+async fn issue_and_insert(context: &ServiceContext, invoice: &mut Invoice) -> Result<()> {
+    let sequence_collection = context
+        .db_client
+        .collection::<InvoiceNumberSequence>("invoice_number_sequences");
+    invoice
+        .issue(
+            &sequence_collection,
+            &invoice_collection(context),
+            context.signer.as_deref(),
+        )
+        .await
+}
+
+/// Stores the documents of a newly issued document and sends it to the customer in the background.
+fn spawn_post_processing(context: &ServiceContext, invoice: Invoice) {
+    spawn_invoice_post_processing(
+        &invoice_collection(context),
+        &context.dispatch_config,
+        &context.storage_config,
+        &context.audit_log,
+        invoice,
+    );
+}
+
+/// Cancels an invoice which is superseded by a corrective invoice, failing if it is already cancelled.
+///
+/// The status change event is recorded with the cancellation.
+async fn supersede_invoice(
+    collection: &Collection<Invoice>,
+    invoice: &Invoice,
+    corrective_id: Uuid,
+    reason: &str,
+    status_changed: &PendingEvent,
+) -> Result<()> {
+    let result = collection
+        .update_one(
+            doc! {"_id": invoice._id, "status": {"$ne": bson::to_bson(&InvoiceStatus::Cancelled)?}},
+            doc! {
+                "$set": {
+                    "status": bson::to_bson(&InvoiceStatus::Cancelled)?,
+                    "cancelled_at": DateTime::now(),
+                    "cancellation_reason": reason,
+                    "superseded_by": corrective_id,
+                },
+                "$push": {"pending_events": bson::to_bson(status_changed)?},
+            },
+            None,
+        )
+        .await?;
+    match result.modified_count {
+        0 => Err(Error::new(format!(
+            "Invoice of UUID: `{}` is cancelled and cannot be reissued.",
+            invoice._id
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Restores the status of an invoice after issuing its corrective invoice failed, discarding the recorded status change event.
+async fn restore_superseded_invoice(
+    collection: &Collection<Invoice>,
+    invoice: &Invoice,
+    status_changed: &PendingEvent,
+) {
+    let restore = async {
+        collection
+            .update_one(
+                doc! {"_id": invoice._id},
+                doc! {
+                    "$set": {
+                        "status": bson::to_bson(&invoice.status)?,
+                        "cancelled_at": invoice.cancelled_at,
+                        "cancellation_reason": &invoice.cancellation_reason,
+                        "superseded_by": invoice.superseded_by,
+                    },
+                    "$pull": {"pending_events": {"_id": status_changed._id}},
+                },
+                None,
+            )
+            .await?;
+        Ok::<(), Error>(())
+    };
+    if let Err(e) = restore.await {
+        warn!(
+            "Restoring invoice of UUID: `{}` after failed reissue failed: {}",
+            invoice._id, e.message
+        );
+    }
+}
+
+/// Publishes an event of a mutation which is not recorded with a change of an invoice.
+///
+/// The mutation is already committed, so a failure is logged instead of failing the mutation.
+async fn publish<T: Serialize>(topic: &str, data: &T) {
+    if publish_event(topic, data).await.is_err() {
+        warn!("Publishing `{}` event failed.", topic);
+    }
+}
@@ -0,0 +1,266 @@
+use async_graphql::{InputObject, Result};
+use bson::Uuid;
+
+use crate::event::http_event_service::{OrderEventData, OrderItemEventData};
+
+use super::model::{
+    foreign_types::VendorAddress, invoice_line_item::InvoiceLineItem,
+    invoice_party::InvoiceParty, money::Money, order::OrderStatus,
+};
+
+/// Corrected data of the customer of an invoice.
+#[derive(Debug, InputObject, Clone)]
+pub struct InvoicePartyInput {
+    /// Name of the person or company.
+    pub name: String,
+    /// Company name, if the customer is a company.
+    pub company: Option<String>,
+    /// Address lines, excluding the country.
+    pub address_lines: Vec<String>,
+    /// Country code of the address.
+    pub country_code: String,
+    /// VAT identification number of the customer.
+    pub vat_id: Option<String>,
+}
+
+impl From<InvoicePartyInput> for InvoiceParty {
+    fn from(value: InvoicePartyInput) -> Self {
+        Self {
+            name: value.name,
+            company: value.company,
+            address_lines: value.address_lines,
+            country_code: value.country_code,
+            vat_id: value.vat_id,
+        }
+    }
+}
+
+/// Input to reissue an invoice as corrective invoice.
+#[derive(Debug, InputObject)]
+pub struct ReissueInvoiceInput {
+    /// UUID of the invoice to correct.
+    pub id: Uuid,
+    /// Corrected customer data, the customer of the invoice if not set.
+    pub customer: Option<InvoicePartyInput>,
+    /// Reason for the correction, printed on the corrective invoice.
+    pub reason: String,
+}
+
+/// Input to cancel an invoice.
+#[derive(Debug, InputObject)]
+pub struct CancelInvoiceInput {
+    /// UUID of the invoice to cancel.
+    pub id: Uuid,
+    /// Reason for the cancellation.
+    pub reason: String,
+}
+
+/// Input to issue a credit note for an invoice.
+#[derive(Debug, InputObject)]
+pub struct IssueCreditNoteInput {
+    /// UUID of the invoice to credit.
+    pub invoice_id: Uuid,
+    /// Credited amount in minor units of the invoice currency, the whole invoice if not set.
+    pub amount: Option<i64>,
+    /// Reason for the credit note, printed on the credit note.
+    pub reason: String,
+}
+
+/// Input to send an invoice to the customer again.
+#[derive(Debug, InputObject)]
+pub struct ResendInvoiceInput {
+    /// UUID of the invoice to send.
+    pub id: Uuid,
+    /// Email address to send the invoice to, the recipient of the invoice if not set.
+    pub recipient: Option<String>,
+}
+
+/// Input to set the vendor address used on invoices.
+#[derive(Debug, InputObject)]
+pub struct VendorAddressInput {
+    /// UUID of the vendor address.
+    pub id: Uuid,
+    /// UUID of the vendor, `None` for the shop's own vendor address.
+    pub vendor_id: Option<Uuid>,
+    /// First street field.
+    pub street1: String,
+    /// Second street field.
+    pub street2: String,
+    /// City of the vendor.
+    pub city: String,
+    /// Postal code of the vendor.
+    pub postal_code: String,
+    /// Country which the vendor is located in.
+    pub country: String,
+    /// Name of the vendor.
+    pub company_name: String,
+    /// VAT identification number of the vendor.
+    pub vat_id: Option<String>,
+}
+
+impl From<VendorAddressInput> for VendorAddress {
+    fn from(value: VendorAddressInput) -> Self {
+        Self {
+            _id: value.id,
+            street1: value.street1,
+            street2: value.street2,
+            city: value.city,
+            postal_code: value.postal_code,
+            country: value.country,
+            company_name: value.company_name,
+            vendor_id: value.vendor_id,
+            vat_id: value.vat_id,
+        }
+    }
+}
+
+/// Input to create a manual invoice which is not related to an order.
+#[derive(Debug, InputObject)]
+pub struct CreateManualInvoiceInput {
+    /// UUID of the invoiced user.
+    pub user_id: Uuid,
+    /// UUID of the address of the user to invoice to.
+    pub invoice_address_id: Uuid,
+    /// UUID of the vendor issuing the invoice, `None` for the shop's own vendor.
+    pub vendor_id: Option<Uuid>,
+    /// ISO 4217 code of the currency of all lines.
+    pub currency: String,
+    /// VAT identification number of the customer.
+    pub vat_number: Option<String>,
+    /// Invoiced lines.
+    pub line_items: Vec<ManualLineItemInput>,
+}
+
+/// Free-form line of a manual invoice, e.g. a service fee.
+#[derive(Debug, InputObject)]
+pub struct ManualLineItemInput {
+    /// Description of the charge, printed on the invoice.
+    pub description: String,
+    /// Quantity of the charge.
+    #[graphql(default = 1)]
+    pub count: u64,
+    /// Total amount of the line in minor units, negative for deductions.
+    pub amount: i64,
+}
+
+impl ManualLineItemInput {
+    /// Converts the input into an invoice line in a currency.
+    pub fn into_line_item(self, currency: &str) -> Result<InvoiceLineItem> {
+        Ok(InvoiceLineItem {
+            order_id: None,
+            order_item_id: None,
+            product_variant_id: None,
+            product_variant_version_id: None,
+            product: None,
+            description: Some(self.description),
+            count: self.count,
+            amount: Money::new(self.amount, currency)?,
+            undiscounted_amount: None,
+            discounts: vec![],
+            shipment_method_id: None,
+            tax_rate: None,
+            tax_amount: None,
+        })
+    }
+}
+
+/// Amount of money in minor units of a currency.
+#[derive(Debug, InputObject)]
+pub struct MoneyInput {
+    /// Amount in minor units, e.g. cents.
+    pub amount: i64,
+    /// ISO 4217 code of the currency.
+    pub currency: String,
+}
+
+impl MoneyInput {
+    /// Converts the input into money, rejecting unsupported currencies.
+    pub fn into_money(self) -> Result<Money> {
+        Money::new(self.amount, &self.currency)
+    }
+}
+
+/// Pending order to issue proformas of, shaped like the order of order events.
+#[derive(Debug, InputObject)]
+pub struct OrderInput {
+    /// Order UUID.
+    pub id: Uuid,
+    /// UUID of user connected with order.
+    pub user_id: Uuid,
+    /// OrderItems associated with the order.
+    pub order_items: Vec<OrderItemInput>,
+    /// UUID of address to where the order should be shipped to.
+    pub shipment_address_id: Uuid,
+    /// UUID of address of invoice.
+    pub invoice_address_id: Uuid,
+    /// Total compensatable amount of order.
+    pub compensatable_order_amount: MoneyInput,
+    /// UUID of payment information that the order should be processed with.
+    pub payment_information_id: Uuid,
+    /// Optional VAT number.
+    pub vat_number: Option<String>,
+}
+
+/// Order item of a pending order, shaped like the order items of order events.
+#[derive(Debug, InputObject)]
+pub struct OrderItemInput {
+    /// Order item UUID.
+    pub id: Uuid,
+    /// UUID of product variant associated with order item.
+    pub product_variant_id: Uuid,
+    /// UUID of product variant version associated with order item.
+    pub product_variant_version_id: Uuid,
+    /// UUID of tax rate version associated with order item.
+    pub tax_rate_version_id: Uuid,
+    /// UUID of shopping cart item associated with order item.
+    pub shopping_cart_item_id: Uuid,
+    /// Specifies the quantity of the order item.
+    pub count: u64,
+    /// Total cost of product item, which can also be refunded.
+    pub compensatable_amount: MoneyInput,
+    /// UUID of shipment method of order item.
+    pub shipment_method_id: Uuid,
+    /// UUIDs of discounts applied to order item.
+    #[graphql(default)]
+    pub discount_ids: Vec<Uuid>,
+}
+
+impl OrderInput {
+    /// Converts the input into the order data of a pending order created now.
+    pub fn into_order_event_data(self) -> Result<OrderEventData> {
+        let now = chrono::Utc::now();
+        let order_items = self
+            .order_items
+            .into_iter()
+            .map(|item| {
+                Ok(OrderItemEventData {
+                    id: item.id,
+                    created_at: now,
+                    product_variant_id: item.product_variant_id,
+                    product_variant_version_id: item.product_variant_version_id,
+                    tax_rate_version_id: item.tax_rate_version_id,
+                    shopping_cart_item_id: item.shopping_cart_item_id,
+                    count: item.count,
+                    compensatable_amount: item.compensatable_amount.into_money()?,
+                    shipment_method_id: item.shipment_method_id,
+                    discount_ids: item.discount_ids,
+                })
+            })
+            .collect::<Result<Vec<OrderItemEventData>>>()?;
+        Ok(OrderEventData {
+            id: self.id,
+            user_id: self.user_id,
+            created_at: now,
+            order_status: OrderStatus::Pending,
+            placed_at: now,
+            rejection_reason: None,
+            order_items,
+            shipment_address_id: self.shipment_address_id,
+            invoice_address_id: self.invoice_address_id,
+            compensatable_order_amount: self.compensatable_order_amount.into_money()?,
+            payment_information_id: self.payment_information_id,
+            payment_authorization: None,
+            vat_number: self.vat_number,
+        })
+    }
+}
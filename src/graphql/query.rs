@@ -2,11 +2,29 @@ use std::any::type_name;
 
 use async_graphql::{Context, Error, Object, Result};
 
-use bson::Uuid;
-use mongodb::{bson::doc, Collection, Database};
+use bson::{DateTime, Uuid};
+use futures::TryStreamExt;
+use log::info;
+use mongodb::{bson::doc, options::FindOptions, Collection, Database};
 use serde::Deserialize;
 
-use super::model::{invoice::Invoice, order::Order};
+use crate::{
+    audit::{AuditLog, TraceId},
+    authorization::{authorize_roles, authorize_user_or_roles, AuthorizedUser, Role},
+};
+
+use super::model::{
+    audit_entry::{AuditAction, AuditEntry, AuditFilter},
+    invoice::{Invoice, InvoiceDocumentType},
+    invoice_chain::{verify_invoice_chains, ChainVerification},
+    invoice_filter::{invoices_after, InvoiceFilter},
+    order::Order,
+    order_invoicing::OrderInvoicing,
+    recurring::{BillingPlan, CustomerSubscription},
+};
+
+/// Maximum number of invoices of a page of the `invoices` query.
+const MAX_INVOICE_PAGE_SIZE: i64 = 200;
 
 /// Describes GraphQL invoice queries.
 pub struct Query;
@@ -14,6 +32,8 @@ pub struct Query;
 #[Object]
 impl Query {
     /// Entity resolver for order of specific UUID.
+    ///
+    /// Buyers only resolve their own orders, admins and employees those of any user.
     #[graphql(entity)]
     async fn order_entity_resolver<'a>(
         &self,
@@ -22,12 +42,42 @@ impl Query {
     ) -> Result<Order> {
         let db_client = ctx.data::<Database>()?;
         let collection: Collection<Invoice> = db_client.collection::<Invoice>("invoices");
-        let invoice = query_invoice_by_order_id(&collection, id).await?;
-        let order = Order { _id: id, invoice };
+        let invoices = query_invoices_by_order_id(&collection, id).await?;
+        let order_invoicing = db_client
+            .collection::<OrderInvoicing>("order_invoicing")
+            .find_one(doc! {"_id": id}, None)
+            .await?;
+        let user_id = invoices
+            .first()
+            .map(|invoice| invoice.user_id)
+            .or(order_invoicing.as_ref().map(|order_invoicing| order_invoicing.order.user_id));
+        let Some(user_id) = user_id else {
+            return Err(Error::new(format!(
+                "Neither invoices nor invoicing state of order UUID: `{}` found.",
+                id
+            )));
+        };
+        authorize_user_or_roles(ctx, user_id, &[Role::Admin, Role::Employee])?;
+        let invoicing_progress = order_invoicing
+            .map(|order_invoicing| order_invoicing.progress())
+            .transpose()?;
+        let entries = invoices
+            .iter()
+            .map(|invoice| audit_entry_of_request(ctx, AuditAction::InvoiceRead, Some(invoice._id)))
+            .collect();
+        ctx.data::<AuditLog>()?.record_many(entries).await;
+        let order = Order {
+            _id: id,
+            invoice: invoices.first().cloned(),
+            invoices,
+            invoicing_progress,
+        };
         Ok(order)
     }
 
     /// Entity resolver for invoice of specific UUID.
+    ///
+    /// Buyers only resolve their own invoices, admins and employees those of any user.
     #[graphql(entity)]
     async fn invoice_entity_resolver<'a>(
         &self,
@@ -37,10 +87,16 @@ impl Query {
         let db_client = ctx.data::<Database>()?;
         let collection: Collection<Invoice> = db_client.collection::<Invoice>("invoices");
         let invoice = query_object(&collection, id).await?;
+        authorize_user_or_roles(ctx, invoice.user_id, &[Role::Admin, Role::Employee])?;
+        ctx.data::<AuditLog>()?
+            .record(audit_entry_of_request(ctx, AuditAction::InvoiceRead, Some(id)))
+            .await;
         Ok(invoice)
     }
 
     /// Query for invoice of specific UUID.
+    ///
+    /// Buyers only retrieve their own invoices, admins and employees those of any user.
     async fn invoice<'a>(
         &self,
         ctx: &Context<'a>,
@@ -49,20 +105,178 @@ impl Query {
         let db_client = ctx.data::<Database>()?;
         let collection: Collection<Invoice> = db_client.collection::<Invoice>("invoices");
         let invoice = query_object(&collection, id).await?;
+        authorize_user_or_roles(ctx, invoice.user_id, &[Role::Admin, Role::Employee])?;
+        ctx.data::<AuditLog>()?
+            .record(audit_entry_of_request(ctx, AuditAction::InvoiceRead, Some(id)))
+            .await;
         Ok(invoice)
     }
+
+    /// Query for a page of invoices matching a filter, ordered by their due date.
+    ///
+    /// Buyers only retrieve their own invoices, admins and employees those of any user.
+    async fn invoices<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "Filter invoices must match, e.g. to retrieve overdue invoices.")]
+        filter: Option<InvoiceFilter>,
+        #[graphql(
+            desc = "Maximum number of invoices to retrieve, at most 200.",
+            default = 50
+        )]
+        limit: i64,
+        #[graphql(desc = "UUID of the last invoice of the previous page, the first page if not set.")]
+        after: Option<Uuid>,
+    ) -> Result<Vec<Invoice>> {
+        let mut filter = filter.unwrap_or_default();
+        let back_office = [Role::Admin, Role::Employee];
+        if let Some(user) = ctx.data_opt::<AuthorizedUser>()
+            && !user.has_any_role(&back_office)
+        {
+            filter.user_id = Some(user.id);
+        }
+        match filter.user_id {
+            Some(user_id) => authorize_user_or_roles(ctx, user_id, &back_office)?,
+            None => authorize_roles(ctx, &back_office)?,
+        };
+        let db_client = ctx.data::<Database>()?;
+        let collection: Collection<Invoice> = db_client.collection::<Invoice>("invoices");
+        let mut filter = filter.to_document(DateTime::now());
+        if let Some(after) = after {
+            let previous = query_object(&collection, after).await?;
+            filter = doc! {"$and": [filter, invoices_after(&previous)]};
+        }
+        let find_options = FindOptions::builder()
+            .sort(doc! {"due_at": 1, "issued_at": 1, "_id": 1})
+            .limit(limit.clamp(1, MAX_INVOICE_PAGE_SIZE))
+            .build();
+        let invoices: Vec<Invoice> = collection
+            .find(filter, find_options)
+            .await?
+            .try_collect()
+            .await?;
+        let entries = invoices
+            .iter()
+            .map(|invoice| audit_entry_of_request(ctx, AuditAction::InvoiceRead, Some(invoice._id)))
+            .collect();
+        ctx.data::<AuditLog>()?.record_many(entries).await;
+        Ok(invoices)
+    }
+
+    /// Query for the billing plans of subscription products.
+    async fn billing_plans<'a>(&self, ctx: &Context<'a>) -> Result<Vec<BillingPlan>> {
+        let db_client = ctx.data::<Database>()?;
+        let billing_plans = db_client
+            .collection::<BillingPlan>("billing_plans")
+            .find(None, None)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(billing_plans)
+    }
+
+    /// Query for the subscriptions of a user, readable by the user and back-office.
+    async fn customer_subscriptions<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "UUID of the subscribed user.")] user_id: Uuid,
+    ) -> Result<Vec<CustomerSubscription>> {
+        authorize_user_or_roles(ctx, user_id, &[Role::Admin, Role::Employee])?;
+        let db_client = ctx.data::<Database>()?;
+        let find_options = FindOptions::builder()
+            .sort(doc! {"started_at": 1})
+            .build();
+        let subscriptions = db_client
+            .collection::<CustomerSubscription>("customer_subscriptions")
+            .find(doc! {"user_id": user_id}, find_options)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(subscriptions)
+    }
+
+    /// Admin query which walks the hash chains of the invoice numbering sequences and reports any break.
+    async fn verify_invoice_chain<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "Numbering sequence to verify, all sequences if not set.")]
+        sequence_id: Option<String>,
+    ) -> Result<Vec<ChainVerification>> {
+        let user = authorize_roles(ctx, &[Role::Admin])?;
+        info!("User of UUID: `{}` verifies invoice chains.", user.id);
+        let db_client = ctx.data::<Database>()?;
+        let collection: Collection<Invoice> = db_client.collection::<Invoice>("invoices");
+        let verifications = verify_invoice_chains(&collection, sequence_id).await?;
+        let broken = verifications.iter().filter(|v| !v.valid).count();
+        ctx.data::<AuditLog>()?
+            .record(
+                audit_entry_of_request(ctx, AuditAction::ChainVerified, None).with_details(
+                    format!("{} sequences, {} broken.", verifications.len(), broken),
+                ),
+            )
+            .await;
+        Ok(verifications)
+    }
+
+    /// Admin query for entries of the invoice audit log, most recent first.
+    async fn audit_entries<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "Filter audit entries must match.")] filter: Option<AuditFilter>,
+        #[graphql(desc = "Maximum number of entries to retrieve.", default = 100)] limit: i64,
+    ) -> Result<Vec<AuditEntry>> {
+        authorize_roles(ctx, &[Role::Admin])?;
+        let audit_log = ctx.data::<AuditLog>()?;
+        let find_options = FindOptions::builder()
+            .sort(doc! {"occurred_at": -1})
+            .limit(limit)
+            .build();
+        let entries = audit_log
+            .collection()
+            .find(filter.unwrap_or_default().to_document(), find_options)
+            .await?
+            .try_collect()
+            .await?;
+        audit_log
+            .record(audit_entry_of_request(ctx, AuditAction::AuditLogRead, None))
+            .await;
+        Ok(entries)
+    }
 }
 
-/// Shared function to query an invoice by an order UUID.
-pub async fn query_invoice_by_order_id(
+/// Creates an audit entry of an action performed in a GraphQL request, attributed to its user and trace.
+pub fn audit_entry_of_request(
+    ctx: &Context<'_>,
+    action: AuditAction,
+    invoice_id: Option<Uuid>,
+) -> AuditEntry {
+    AuditEntry::new(action, invoice_id)
+        .by_user(ctx.data_opt::<AuthorizedUser>())
+        .with_trace_id(ctx.data_opt::<TraceId>().map(|trace_id| trace_id.0.clone()))
+}
+
+/// Shared function to query all invoices of an order UUID, ordered by their issue date.
+///
+/// Includes the collective invoices which consolidate the order, but not its non-binding proformas.
+pub async fn query_invoices_by_order_id(
     collection: &Collection<Invoice>,
     order_id: Uuid,
-) -> Result<Invoice> {
-    let message = format!("Invoice with order_id UUID: `{}` not found.", order_id);
-    collection
-        .find_one(doc! {"order_id": order_id }, None)
+) -> Result<Vec<Invoice>> {
+    let find_options = FindOptions::builder()
+        .sort(doc! {"issued_at": 1})
+        .build();
+    let invoices = collection
+        .find(
+            doc! {
+                "$or": [{"order_id": order_id}, {"order_ids": order_id}],
+                "document_type": {"$ne": bson::to_bson(&InvoiceDocumentType::Proforma)?},
+            },
+            find_options,
+        )
         .await?
-        .ok_or(Error::new(message))
+        .try_collect()
+        .await?;
+    Ok(invoices)
 }
 
 /// Shared function to query an object: `T` from a MongoDB collection of object: `T`.
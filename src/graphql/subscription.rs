@@ -0,0 +1,65 @@
+use async_graphql::{Context, Result, Subscription};
+use bson::Uuid;
+use futures::{future, Stream, StreamExt};
+
+use crate::{
+    authorization::{authorize_user_or_roles, Role},
+    config::ServiceContext,
+    event::bus::InvoiceBusEvent,
+};
+
+use super::{model::invoice::Invoice, query::query_object};
+
+/// Describes GraphQL invoice subscriptions, served over graphql-ws.
+pub struct Subscription;
+
+#[Subscription]
+impl Subscription {
+    /// Subscription to invoices issued to a user, including corrective invoices and credit notes.
+    ///
+    /// Users subscribe to their own invoices, admins and employees to those of any user.
+    async fn invoice_created<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "UUID of user whose issued invoices to receive.")] user_id: Uuid,
+    ) -> Result<impl Stream<Item = Invoice>> {
+        authorize_user_or_roles(ctx, user_id, &[Role::Admin, Role::Employee])?;
+        let context = ctx.data::<ServiceContext>()?;
+        Ok(context
+            .event_bus
+            .subscribe()
+            .filter_map(move |event| {
+                future::ready(match event {
+                    InvoiceBusEvent::Created(invoice) if invoice.user_id == user_id => {
+                        Some(invoice)
+                    }
+                    _ => None,
+                })
+            }))
+    }
+
+    /// Subscription to payment status changes of an invoice, yielding the updated invoice.
+    ///
+    /// Users subscribe to their own invoices, admins and employees to any invoice.
+    async fn invoice_status_changed<'a>(
+        &self,
+        ctx: &Context<'a>,
+        #[graphql(desc = "UUID of invoice whose status changes to receive.")] invoice_id: Uuid,
+    ) -> Result<impl Stream<Item = Invoice>> {
+        let context = ctx.data::<ServiceContext>()?;
+        let collection = context.db_client.collection::<Invoice>("invoices");
+        let invoice = query_object(&collection, invoice_id).await?;
+        authorize_user_or_roles(ctx, invoice.user_id, &[Role::Admin, Role::Employee])?;
+        Ok(context
+            .event_bus
+            .subscribe()
+            .filter_map(move |event| {
+                future::ready(match event {
+                    InvoiceBusEvent::StatusChanged(invoice) if invoice._id == invoice_id => {
+                        Some(invoice)
+                    }
+                    _ => None,
+                })
+            }))
+    }
+}
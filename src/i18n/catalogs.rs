@@ -0,0 +1,109 @@
+use super::Language;
+
+/// English messages of invoice documents.
+static EN: &[(&str, &str)] = &[
+    ("invoice.title", "Invoice"),
+    ("invoice.title_corrective", "Corrective invoice"),
+    ("invoice.title_credit_note", "Credit note"),
+    ("invoice.refers_to", "Refers to invoice {number}."),
+    ("invoice.reason", "Reason"),
+    ("invoice.partial_credit", "Partial credit of invoice {number}"),
+    ("invoice.title_proforma", "Proforma invoice"),
+    (
+        "invoice.proforma_notice",
+        "This proforma invoice is non-binding and not a request for payment.",
+    ),
+    ("invoice.billing_period", "Billing period: {start} to {end}"),
+    ("invoice.order", "Order {id}"),
+    ("invoice.company_information", "Company information"),
+    ("invoice.customer_information", "Customer information"),
+    ("invoice.customer_id", "ID"),
+    ("invoice.name", "Name"),
+    ("invoice.company", "Company"),
+    ("invoice.address", "Address"),
+    ("invoice.vat_number", "VAT number"),
+    ("invoice.invoice_number", "Invoice number"),
+    ("invoice.invoice_id", "ID"),
+    ("invoice.issued_at", "issued at"),
+    ("invoice.terms_label", "Terms and conditions"),
+    (
+        "invoice.terms",
+        "This invoice is created according the the companies terms and conditions specified on the website.",
+    ),
+    ("invoice.items_overview", "Purchased items overview"),
+    ("invoice.item", "Item"),
+    ("invoice.sku", "SKU"),
+    ("invoice.count", "Count"),
+    ("invoice.amount", "Compensatable amount"),
+    ("invoice.discount", "Discount"),
+    ("invoice.shipping", "Shipping: {name}"),
+    ("invoice.included_vat", "incl. {rate} VAT ({amount})"),
+    ("invoice.total", "Total compensatable amount"),
+    ("invoice.payment_terms", "Payment terms"),
+    ("invoice.net_days", "Payable within {days} days, due on {due_at}."),
+    (
+        "invoice.early_payment_discount",
+        "{percentage} early payment discount ({amount}) if paid by {deadline}.",
+    ),
+    ("invoice.email_subject", "Your invoice {number}"),
+];
+
+/// German messages of invoice documents.
+static DE: &[(&str, &str)] = &[
+    ("invoice.title", "Rechnung"),
+    ("invoice.title_corrective", "Rechnungskorrektur"),
+    ("invoice.title_credit_note", "Gutschrift"),
+    ("invoice.refers_to", "Bezieht sich auf Rechnung {number}."),
+    ("invoice.reason", "Grund"),
+    ("invoice.partial_credit", "Teilgutschrift zu Rechnung {number}"),
+    ("invoice.title_proforma", "Proformarechnung"),
+    (
+        "invoice.proforma_notice",
+        "Diese Proformarechnung ist unverbindlich und keine Zahlungsaufforderung.",
+    ),
+    ("invoice.billing_period", "Abrechnungszeitraum: {start} bis {end}"),
+    ("invoice.order", "Bestellung {id}"),
+    ("invoice.company_information", "Unternehmensangaben"),
+    ("invoice.customer_information", "Kundenangaben"),
+    ("invoice.customer_id", "Kundennummer"),
+    ("invoice.name", "Name"),
+    ("invoice.company", "Firma"),
+    ("invoice.address", "Anschrift"),
+    ("invoice.vat_number", "USt-IdNr."),
+    ("invoice.invoice_number", "Rechnungsnummer"),
+    ("invoice.invoice_id", "ID"),
+    ("invoice.issued_at", "ausgestellt am"),
+    ("invoice.terms_label", "Allgemeine Geschäftsbedingungen"),
+    (
+        "invoice.terms",
+        "Diese Rechnung wurde gemäß den auf der Website angegebenen Allgemeinen Geschäftsbedingungen des Unternehmens erstellt.",
+    ),
+    ("invoice.items_overview", "Übersicht der gekauften Artikel"),
+    ("invoice.item", "Artikel"),
+    ("invoice.sku", "Artikelnummer"),
+    ("invoice.count", "Menge"),
+    ("invoice.amount", "Erstattungsfähiger Betrag"),
+    ("invoice.discount", "Rabatt"),
+    ("invoice.shipping", "Versand: {name}"),
+    ("invoice.included_vat", "inkl. {rate} USt. ({amount})"),
+    ("invoice.total", "Erstattungsfähiger Gesamtbetrag"),
+    ("invoice.payment_terms", "Zahlungsbedingungen"),
+    ("invoice.net_days", "Zahlbar innerhalb von {days} Tagen, fällig am {due_at}."),
+    (
+        "invoice.early_payment_discount",
+        "{percentage} Skonto ({amount}) bei Zahlung bis {deadline}.",
+    ),
+    ("invoice.email_subject", "Ihre Rechnung {number}"),
+];
+
+/// Looks up the message of a key in the catalog of a language.
+pub(super) fn lookup(language: Language, key: &str) -> Option<&'static str> {
+    let catalog = match language {
+        Language::English => EN,
+        Language::German => DE,
+    };
+    catalog
+        .iter()
+        .find(|(message_key, _)| *message_key == key)
+        .map(|(_, message)| *message)
+}
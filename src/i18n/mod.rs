@@ -0,0 +1,141 @@
+use std::env;
+
+use bson::DateTime;
+use once_cell::sync::Lazy;
+
+use crate::graphql::model::money::{group_digits, Money, MoneyFormat};
+
+mod catalogs;
+
+/// Locale used if neither the preferred locale nor the country of a customer is supported, configured by `$DEFAULT_LOCALE`.
+static DEFAULT_LOCALE: Lazy<String> =
+    Lazy::new(|| env::var("DEFAULT_LOCALE").unwrap_or("en".to_string()));
+
+/// Languages for which message catalogs exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    English,
+    German,
+}
+
+impl Language {
+    /// Parses the primary language subtag of a BCP 47 language tag, e.g. `de` of `de-AT`.
+    fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next()?.to_lowercase();
+        match primary.as_str() {
+            "en" => Some(Language::English),
+            "de" => Some(Language::German),
+            _ => None,
+        }
+    }
+
+    /// Returns the language mostly spoken in a country, given as ISO 3166-1 code or English or native name.
+    fn from_country(country: &str) -> Option<Self> {
+        match country.trim().to_lowercase().as_str() {
+            "de" | "deu" | "germany" | "deutschland" | "at" | "aut" | "austria" | "österreich"
+            | "li" | "lie" | "liechtenstein" => Some(Language::German),
+            "gb" | "gbr" | "united kingdom" | "us" | "usa" | "united states" | "ie" | "irl"
+            | "ireland" | "au" | "aus" | "australia" | "ca" | "can" | "canada" => {
+                Some(Language::English)
+            }
+            _ => None,
+        }
+    }
+
+    fn tag(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+        }
+    }
+}
+
+/// Locale in which invoice documents are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    language: Language,
+}
+
+impl Locale {
+    /// Resolves the locale of a customer by the fallback chain:
+    /// preferred locale, language of the preferred locale, language of the country, `$DEFAULT_LOCALE`, English.
+    ///
+    /// * `preferred_locale` - Preferred locale of the customer as BCP 47 language tag.
+    /// * `country` - Country of the invoice address of the customer.
+    pub fn resolve(preferred_locale: Option<&str>, country: &str) -> Self {
+        let language = preferred_locale
+            .and_then(Language::from_tag)
+            .or_else(|| Language::from_country(country))
+            .or_else(|| Language::from_tag(&DEFAULT_LOCALE))
+            .unwrap_or(Language::English);
+        Self { language }
+    }
+
+    /// Parses a locale stored on an invoice, falling back to `$DEFAULT_LOCALE`.
+    pub fn from_tag(tag: &str) -> Self {
+        Self::resolve(Some(tag), "")
+    }
+
+    /// BCP 47 language tag of the locale.
+    pub fn tag(&self) -> &'static str {
+        self.language.tag()
+    }
+
+    /// Returns the message of a key in the catalog of the locale, falling back to English and finally the key itself.
+    pub fn message<'a>(&self, key: &'a str) -> &'a str {
+        catalogs::lookup(self.language, key)
+            .or_else(|| catalogs::lookup(Language::English, key))
+            .unwrap_or(key)
+    }
+
+    /// Formats a timestamp as date and time in UTC.
+    pub fn format_date_time(&self, date_time: DateTime) -> String {
+        let format = match self.language {
+            Language::English => "%Y-%m-%d %H:%M:%S UTC",
+            Language::German => "%d.%m.%Y %H:%M:%S UTC",
+        };
+        date_time.to_chrono().format(format).to_string()
+    }
+
+    /// Formats a timestamp as date only.
+    pub fn format_date(&self, date_time: DateTime) -> String {
+        let format = match self.language {
+            Language::English => "%Y-%m-%d",
+            Language::German => "%d.%m.%Y",
+        };
+        date_time.to_chrono().format(format).to_string()
+    }
+
+    /// Formats a percentage with the decimal separator of the locale, e.g. `2.5%` or `2,5 %`.
+    pub fn format_percentage(&self, percentage: f64) -> String {
+        let number = percentage
+            .to_string()
+            .replace('.', &self.money_format().decimal_separator.to_string());
+        match self.language {
+            Language::English => format!("{}%", number),
+            Language::German => format!("{}\u{a0}%", number),
+        }
+    }
+
+    /// Formats an integer with the group separator of the locale.
+    pub fn format_number(&self, number: u64) -> String {
+        group_digits(number, self.money_format().group_separator)
+    }
+
+    /// Formats a monetary amount with the separators and symbol placement of the locale.
+    pub fn format_money(&self, money: &Money) -> String {
+        money.format_with(&self.money_format())
+    }
+
+    fn money_format(&self) -> MoneyFormat {
+        match self.language {
+            Language::English => MoneyFormat::default(),
+            Language::German => MoneyFormat {
+                decimal_separator: ',',
+                group_separator: '.',
+                symbol_first: false,
+                symbol_spaced: true,
+            },
+        }
+    }
+}
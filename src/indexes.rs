@@ -0,0 +1,88 @@
+use async_graphql::Result;
+use bson::{doc, Document};
+use mongodb::{options::IndexOptions, Database, IndexModel};
+
+/// Index as collection name, index name, keys and, for unique indexes, the partial filter of the documents it covers.
+pub type IndexDefinition = (&'static str, &'static str, Document, Option<Document>);
+
+/// Current indexes of the invoice service.
+fn index_definitions() -> Vec<IndexDefinition> {
+    vec![
+        ("invoices", "order_id", doc! {"order_id": 1}, None),
+        ("invoices", "user_id", doc! {"user_id": 1}, None),
+        ("invoices", "issued_at", doc! {"issued_at": 1}, None),
+        (
+            "invoices",
+            "sequence",
+            doc! {"sequence_id": 1, "sequence_number": 1},
+            Some(doc! {"sequence_number": {"$type": "long"}}),
+        ),
+        ("invoices", "status_due_at", doc! {"status": 1, "due_at": 1}, None),
+        (
+            "invoices",
+            "order_vendor_shipment_invoice",
+            doc! {"order_id": 1, "vendor_id": 1, "shipment_id": 1},
+            Some(doc! {"document_type": "Invoice", "order_id": {"$type": "binData"}}),
+        ),
+        (
+            "invoices",
+            "order_vendor_proforma",
+            doc! {"order_id": 1, "vendor_id": 1},
+            Some(doc! {"document_type": "Proforma", "order_id": {"$type": "binData"}}),
+        ),
+        ("user", "addresses_id", doc! {"addresses._id": 1}, None),
+        (
+            "customer_subscriptions",
+            "status_next_billing_at",
+            doc! {"status": 1, "next_billing_at": 1},
+            None,
+        ),
+        ("customer_subscriptions", "user_id", doc! {"user_id": 1}, None),
+        (
+            "order_invoicing",
+            "order_user_id",
+            doc! {"order.user_id": 1},
+            None,
+        ),
+        (
+            "invoice_audit",
+            "invoice_id_occurred_at",
+            doc! {"invoice_id": 1, "occurred_at": -1},
+            None,
+        ),
+        ("invoice_audit", "actor_id", doc! {"actor_id": 1}, None),
+        ("invoice_audit", "occurred_at", doc! {"occurred_at": -1}, None),
+    ]
+}
+
+/// Creates all indexes of the invoice service which do not exist yet, returns the names of the ensured indexes.
+///
+/// * `db_client` - Database of the invoice service.
+pub async fn ensure_indexes(db_client: &Database) -> Result<Vec<String>> {
+    create_indexes(db_client, index_definitions()).await
+}
+
+/// Creates indexes which do not exist yet, returns the names of the created indexes.
+///
+/// * `db_client` - Database of the invoice service.
+/// * `definitions` - Definitions of the indexes.
+pub async fn create_indexes(
+    db_client: &Database,
+    definitions: Vec<IndexDefinition>,
+) -> Result<Vec<String>> {
+    let mut names = vec![];
+    for (collection, name, keys, unique_filter) in definitions {
+        let options = IndexOptions::builder()
+            .name(name.to_string())
+            .unique(unique_filter.as_ref().map(|_| true))
+            .partial_filter_expression(unique_filter)
+            .build();
+        let index = IndexModel::builder().keys(keys).options(options).build();
+        db_client
+            .collection::<Document>(collection)
+            .create_index(index, None)
+            .await?;
+        names.push(format!("{}.{}", collection, name));
+    }
+    Ok(names)
+}
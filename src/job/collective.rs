@@ -0,0 +1,302 @@
+use std::time::Duration;
+
+use async_graphql::{Error, Result};
+use bson::{doc, DateTime, Uuid};
+use futures::TryStreamExt;
+use log::{info, warn};
+use mongodb::options::FindOptions;
+
+use crate::{
+    config::env_var,
+    event::{
+        bus::InvoiceBusEvent,
+        http_event_service::{
+            group_order_items_by_vendor, publish_pending_event,
+            spawn_invoice_post_processing, HttpEventServiceState, OrderEventData,
+            OrderItemEventData,
+        },
+    },
+    graphql::model::{
+        audit_entry::{AuditAction, AuditEntry},
+        billing::BillingPeriod,
+        invoice::Invoice,
+        order_invoicing::{claim_order_items, release_order_items, OrderInvoicing},
+    },
+};
+
+/// Order with the order items which are invoiced collectively.
+type CollectiveOrder = (OrderEventData, Vec<OrderItemEventData>);
+
+/// Orders of one user which are consolidated in the collective invoices of a billing period.
+struct CollectiveGroup {
+    user_id: Uuid,
+    invoice_address_id: Uuid,
+    vat_number: Option<String>,
+    currency: String,
+    billing_period: BillingPeriod,
+    orders: Vec<CollectiveOrder>,
+}
+
+/// Runs the collective invoicing job every `$COLLECTIVE_INVOICING_INTERVAL_SECONDS` seconds, by default hourly.
+///
+/// * `state` - Service state containing database connections.
+pub async fn run(state: HttpEventServiceState) {
+    let period =
+        Duration::from_secs(env_var("COLLECTIVE_INVOICING_INTERVAL_SECONDS").unwrap_or(3600));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = issue_collective_invoices(&state, DateTime::now()).await {
+            warn!("Collective invoicing failed: {}", e.message);
+        }
+    }
+}
+
+/// Issues the collective invoices of all ended billing periods which have uninvoiced order items.
+///
+/// Orders are consolidated per user, invoice address, VAT number and currency, and invoiced per vendor.
+///
+/// * `state` - Service state containing database connections.
+/// * `now` - Current time, billing periods ending before it are invoiced.
+pub async fn issue_collective_invoices(state: &HttpEventServiceState, now: DateTime) -> Result<()> {
+    let current_period = BillingPeriod::month_of(now);
+    let filter = doc! {
+        "collective": true,
+        "validated_at": {"$lt": current_period.start},
+        "$expr": {"$lt": [
+            {"$size": "$invoiced_order_item_ids"},
+            {"$size": "$order.order_items"},
+        ]},
+    };
+    let options = FindOptions::builder()
+        .sort(doc! {"validated_at": 1})
+        .build();
+    let mut cursor = state
+        .order_invoicing_collection
+        .find(filter, options)
+        .await?;
+    let mut groups: Vec<CollectiveGroup> = Vec::new();
+    while let Some(order_invoicing) = cursor.try_next().await? {
+        add_to_collective_group(&mut groups, order_invoicing)?;
+    }
+    for group in groups {
+        if let Err(e) = issue_collective_group(state, group).await {
+            warn!("Issuing collective invoice failed: {}", e.message);
+        }
+    }
+    Ok(())
+}
+
+/// Adds the uninvoiced order items of a collective order to the group of its user and billing period.
+fn add_to_collective_group(
+    groups: &mut Vec<CollectiveGroup>,
+    order_invoicing: OrderInvoicing,
+) -> Result<()> {
+    let Some(validated_at) = order_invoicing.validated_at else {
+        return Ok(());
+    };
+    let all_item_ids: Vec<Uuid> = order_invoicing
+        .order
+        .order_items
+        .iter()
+        .map(|item| item.id)
+        .collect();
+    let order_items = order_invoicing.uninvoiced_items(&all_item_ids);
+    let order = order_invoicing.order;
+    let currency = order.currency()?;
+    let billing_period = BillingPeriod::month_of(validated_at);
+    match groups.iter_mut().find(|group| {
+        group.user_id == order.user_id
+            && group.invoice_address_id == order.invoice_address_id
+            && group.vat_number == order.vat_number
+            && group.currency == currency
+            && group.billing_period == billing_period
+    }) {
+        Some(group) => group.orders.push((order, order_items)),
+        None => groups.push(CollectiveGroup {
+            user_id: order.user_id,
+            invoice_address_id: order.invoice_address_id,
+            vat_number: order.vat_number.clone(),
+            currency,
+            billing_period,
+            orders: vec![(order, order_items)],
+        }),
+    }
+    Ok(())
+}
+
+/// Issues one collective invoice per vendor of a group of orders.
+///
+/// Order items are claimed before issuing, so concurrent runs never invoice an order item twice.
+/// Vendors whose invoice fails to be issued are invoiced in the next run, the remaining vendors are still invoiced.
+/// An event which fails to be published stays pending on its invoice and is published by the publication job.
+async fn issue_collective_group(
+    state: &HttpEventServiceState,
+    group: CollectiveGroup,
+) -> Result<()> {
+    let mut orders_by_vendor: Vec<(Option<Uuid>, Vec<CollectiveOrder>)> = Vec::new();
+    for (order, order_items) in group.orders {
+        let order_items_by_vendor =
+            group_order_items_by_vendor(&state.product_variant_collection, &order_items)
+                .await
+                .map_err(|_| Error::new("Vendors of order items could not be queried."))?;
+        for (vendor_id, order_items) in order_items_by_vendor {
+            let order = (order.clone(), order_items);
+            match orders_by_vendor.iter_mut().find(|(id, _)| *id == vendor_id) {
+                Some((_, orders)) => orders.push(order),
+                None => orders_by_vendor.push((vendor_id, vec![order])),
+            }
+        }
+    }
+    for (vendor_id, orders) in orders_by_vendor {
+        let mut claimed_orders = vec![];
+        for (order, order_items) in orders {
+            let order_item_ids: Vec<Uuid> = order_items.iter().map(|item| item.id).collect();
+            if claim_order_items(&state.order_invoicing_collection, order.id, &order_item_ids)
+                .await?
+            {
+                claimed_orders.push((order, order_items));
+            } else {
+                info!(
+                    "Order items of order UUID: `{}` are already invoiced, skipping vendor {:?}.",
+                    order.id, vendor_id
+                );
+            }
+        }
+        if claimed_orders.is_empty() {
+            continue;
+        }
+        let invoice =
+            Invoice::new_collective(&claimed_orders, vendor_id, group.billing_period, state).await;
+        let invoice = match invoice {
+            Ok(invoice) => invoice,
+            Err(e) => {
+                release_claimed_orders(state, &claimed_orders).await;
+                warn!(
+                    "Issuing collective invoice of vendor {:?} failed, retried next run: {}",
+                    vendor_id, e.message
+                );
+                continue;
+            }
+        };
+        state
+            .audit_log
+            .record(
+                AuditEntry::new(AuditAction::InvoiceCreated, Some(invoice._id)).with_details(
+                    format!(
+                        "Collective invoice `{}` of {} orders of user `{}`.",
+                        invoice.invoice_number,
+                        invoice.order_ids.len(),
+                        invoice.user_id
+                    ),
+                ),
+            )
+            .await;
+        publish_pending_event(&state.invoice_collection, &invoice).await;
+        state
+            .event_bus
+            .publish(InvoiceBusEvent::Created(invoice.clone()));
+        spawn_invoice_post_processing(
+            &state.invoice_collection,
+            &state.dispatch_config,
+            &state.storage_config,
+            &state.audit_log,
+            invoice,
+        );
+    }
+    Ok(())
+}
+
+/// Releases the claimed order items of a collective invoice which could not be issued.
+async fn release_claimed_orders(state: &HttpEventServiceState, claimed_orders: &[CollectiveOrder]) {
+    for (order, order_items) in claimed_orders {
+        let order_item_ids: Vec<Uuid> = order_items.iter().map(|item| item.id).collect();
+        if let Err(e) =
+            release_order_items(&state.order_invoicing_collection, order.id, &order_item_ids).await
+        {
+            warn!(
+                "Releasing order items of order UUID: `{}` failed: {}",
+                order.id, e.message
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::http_event_service::tests::{order, order_item};
+
+    /// Collective order of two items of a user validated at an RFC 3339 timestamp.
+    fn order_invoicing(
+        user_id: Uuid,
+        invoice_address_id: Uuid,
+        validated_at: &str,
+    ) -> OrderInvoicing {
+        let order_items = vec![order_item(100, Uuid::new()), order_item(200, Uuid::new())];
+        let mut order = order(order_items, 300);
+        order.user_id = user_id;
+        order.invoice_address_id = invoice_address_id;
+        OrderInvoicing {
+            _id: order.id,
+            order,
+            invoiced_order_item_ids: vec![],
+            validated_at: Some(DateTime::parse_rfc3339_str(validated_at).unwrap()),
+            collective: true,
+            shipping_line_items: Some(vec![]),
+        }
+    }
+
+    fn group(order_invoicings: Vec<OrderInvoicing>) -> Vec<CollectiveGroup> {
+        let mut groups = vec![];
+        for order_invoicing in order_invoicings {
+            add_to_collective_group(&mut groups, order_invoicing).unwrap();
+        }
+        groups
+    }
+
+    #[test]
+    fn consolidates_orders_of_user_address_and_billing_period() {
+        let (user_id, address_id) = (Uuid::new(), Uuid::new());
+        let groups = group(vec![
+            order_invoicing(user_id, address_id, "2026-01-02T08:00:00Z"),
+            order_invoicing(user_id, address_id, "2026-01-31T23:00:00Z"),
+        ]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].user_id, user_id);
+        assert_eq!(groups[0].currency, "EUR");
+        assert_eq!(groups[0].orders.len(), 2);
+    }
+
+    #[test]
+    fn separates_users_addresses_vat_numbers_and_billing_periods() {
+        let (user_id, address_id) = (Uuid::new(), Uuid::new());
+        let mut with_vat_number = order_invoicing(user_id, address_id, "2026-01-10T08:00:00Z");
+        with_vat_number.order.vat_number = Some("DE123456789".to_string());
+        let groups = group(vec![
+            order_invoicing(user_id, address_id, "2026-01-10T08:00:00Z"),
+            order_invoicing(Uuid::new(), address_id, "2026-01-10T08:00:00Z"),
+            order_invoicing(user_id, Uuid::new(), "2026-01-10T08:00:00Z"),
+            with_vat_number,
+            order_invoicing(user_id, address_id, "2026-02-01T00:00:00Z"),
+        ]);
+        assert_eq!(groups.len(), 5);
+        assert!(groups.iter().all(|group| group.orders.len() == 1));
+        assert_ne!(groups[0].billing_period, groups[4].billing_period);
+    }
+
+    #[test]
+    fn groups_only_uninvoiced_items_of_validated_orders() {
+        let (user_id, address_id) = (Uuid::new(), Uuid::new());
+        let mut partially_invoiced = order_invoicing(user_id, address_id, "2026-01-10T08:00:00Z");
+        let invoiced_item_id = partially_invoiced.order.order_items[0].id;
+        partially_invoiced.invoiced_order_item_ids = vec![invoiced_item_id];
+        let mut unvalidated = order_invoicing(user_id, address_id, "2026-01-10T08:00:00Z");
+        unvalidated.validated_at = None;
+        let groups = group(vec![partially_invoiced, unvalidated]);
+        assert_eq!(groups.len(), 1);
+        let (_, order_items) = &groups[0].orders[0];
+        assert_eq!(order_items.len(), 1);
+        assert_ne!(order_items[0].id, invoiced_item_id);
+    }
+}
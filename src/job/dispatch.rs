@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use async_graphql::Result;
+use bson::{doc, DateTime};
+use futures::TryStreamExt;
+use log::warn;
+use mongodb::Collection;
+
+use crate::{
+    audit::AuditLog,
+    config::env_var,
+    dispatch::{dispatch_invoice, DispatchConfig},
+    graphql::model::{delivery::DeliveryStatus, invoice::Invoice},
+};
+
+/// Runs the retry of failed and interrupted invoice deliveries every `$INVOICE_DISPATCH_RETRY_INTERVAL_SECONDS` seconds, by default every minute.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `audit_log` - Audit log to record delivery attempts in.
+pub async fn run(collection: Collection<Invoice>, audit_log: AuditLog) {
+    let config = DispatchConfig::from_env();
+    let period = Duration::from_secs(env_var("INVOICE_DISPATCH_RETRY_INTERVAL_SECONDS").unwrap_or(60));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = retry_failed_deliveries(&collection, &config, &audit_log).await {
+            warn!("Retrying failed invoice deliveries failed: {}", e.message);
+        }
+    }
+}
+
+/// Retries the delivery of invoices whose last attempt failed, with exponential backoff, until the maximum number of attempts is reached.
+///
+/// Deliveries still pending `pending_timeout_seconds` after issuance, e.g. because the service stopped before sending, are retried as well.
+/// A failure to record a retry is logged, so it does not hold up the retries of the other invoices.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `config` - Configuration of the invoice dispatch.
+/// * `audit_log` - Audit log to record delivery attempts in.
+pub async fn retry_failed_deliveries(
+    collection: &Collection<Invoice>,
+    config: &DispatchConfig,
+    audit_log: &AuditLog,
+) -> Result<()> {
+    let max_attempts_reached = format!("delivery.attempts.{}", config.max_attempts.saturating_sub(1));
+    let now = DateTime::now().to_chrono();
+    let pending_since =
+        DateTime::from_chrono(now - chrono::Duration::seconds(config.pending_timeout_seconds as i64));
+    let filter = doc! {
+        "$or": [
+            {"delivery.status": bson::to_bson(&DeliveryStatus::Failed)?},
+            {
+                "delivery.status": bson::to_bson(&DeliveryStatus::Pending)?,
+                "issued_at": {"$lt": pending_since},
+            },
+        ],
+        max_attempts_reached: {"$exists": false},
+    };
+    let mut cursor = collection.find(filter, None).await?;
+    while let Some(invoice) = cursor.try_next().await? {
+        let retry_at = match invoice.delivery.attempts.last() {
+            Some(last_attempt) => {
+                let backoff = config.retry_delay_seconds
+                    * 2_u64.pow(invoice.delivery.attempts.len().saturating_sub(1) as u32);
+                last_attempt.attempted_at.to_chrono() + chrono::Duration::seconds(backoff as i64)
+            }
+            None => now,
+        };
+        if retry_at <= now
+            && let Err(e) = dispatch_invoice(collection, config, audit_log, &invoice, false).await
+        {
+            warn!(
+                "Retrying delivery of invoice of UUID: `{}` failed: {}",
+                invoice._id, e.message
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use async_graphql::Result;
+use bson::{doc, DateTime};
+use futures::TryStreamExt;
+use log::{info, warn};
+use mongodb::Collection;
+
+use crate::{
+    audit::AuditLog,
+    config::env_var,
+    event::{
+        http_event_service::publish_pending_change_events,
+        model::dunning_level_changed_dto::DunningLevelChangedDTO,
+    },
+    graphql::model::{
+        audit_entry::{AuditAction, AuditEntry},
+        dunning::{DunningPolicy, DunningRecord},
+        invoice::{Invoice, PendingEvent},
+        invoice_filter::InvoiceFilter,
+    },
+};
+
+/// Runs the dunning job every `$DUNNING_INTERVAL_SECONDS` seconds, by default hourly.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `audit_log` - Audit log to record escalations in.
+pub async fn run(collection: Collection<Invoice>, audit_log: AuditLog) {
+    let policy = DunningPolicy::from_env();
+    let period = Duration::from_secs(env_var("DUNNING_INTERVAL_SECONDS").unwrap_or(3600));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = escalate_overdue_invoices(&collection, &policy, &audit_log).await {
+            warn!("Dunning of overdue invoices failed: {}", e.message);
+        }
+    }
+}
+
+/// Escalates all open invoices past their due date to their next dunning level, if reached.
+///
+/// Each run escalates an invoice by at most one level, so invoices which reached several levels step through them on subsequent runs.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `policy` - Configuration of the dunning levels.
+/// * `audit_log` - Audit log to record escalations in.
+pub async fn escalate_overdue_invoices(
+    collection: &Collection<Invoice>,
+    policy: &DunningPolicy,
+    audit_log: &AuditLog,
+) -> Result<()> {
+    let now = DateTime::now();
+    let filter = InvoiceFilter {
+        overdue: Some(true),
+        ..Default::default()
+    }
+    .to_document(now);
+    let mut cursor = collection.find(filter, None).await?;
+    while let Some(invoice) = cursor.try_next().await? {
+        if let Err(e) = escalate_invoice(collection, policy, audit_log, &invoice, now).await {
+            warn!(
+                "Dunning of invoice of UUID: `{}` failed: {}",
+                invoice._id, e.message
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Escalates an overdue invoice to the next dunning level, if reached, and publishes an `invoice/invoice/dunning-level-changed` event.
+///
+/// The update is conditional on the current level, so concurrent runs escalate each level only once.
+/// The event is recorded with the escalation, so it is published by the publication job if publishing it fails.
+async fn escalate_invoice(
+    collection: &Collection<Invoice>,
+    policy: &DunningPolicy,
+    audit_log: &AuditLog,
+    invoice: &Invoice,
+    now: DateTime,
+) -> Result<()> {
+    let Some(due_at) = invoice.due_at else {
+        return Ok(());
+    };
+    let days_overdue = (now.to_chrono() - due_at.to_chrono()).num_days();
+    let Some(level) = policy.next_level(invoice.dunning_level, days_overdue) else {
+        return Ok(());
+    };
+    let fee = policy.fee(level, &invoice.total);
+    let record = DunningRecord {
+        level,
+        escalated_at: now,
+        fee: fee.clone(),
+    };
+    let dunning_level_changed = PendingEvent::new(
+        "invoice/invoice/dunning-level-changed",
+        &DunningLevelChangedDTO::from((invoice, level, fee)),
+    )?;
+    let result = collection
+        .update_one(
+            doc! {"_id": invoice._id, "dunning_level": bson::to_bson(&invoice.dunning_level)?},
+            doc! {
+                "$set": {"dunning_level": bson::to_bson(&level)?},
+                "$push": {
+                    "dunning_history": bson::to_bson(&record)?,
+                    "pending_events": bson::to_bson(&dunning_level_changed)?,
+                },
+            },
+            None,
+        )
+        .await?;
+    if result.modified_count == 1 {
+        info!(
+            "Invoice of UUID: `{}` reached dunning level {:?}.",
+            invoice._id, level
+        );
+        audit_log
+            .record(
+                AuditEntry::new(AuditAction::DunningLevelChanged, Some(invoice._id))
+                    .with_details(format!("{:?} -> {:?}", invoice.dunning_level, level)),
+            )
+            .await;
+        let mut escalated = invoice.clone();
+        escalated.pending_events.push(dunning_level_changed);
+        publish_pending_change_events(collection, &escalated).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,6 @@
+pub mod collective;
+pub mod dispatch;
+pub mod dunning;
+pub mod publication;
+pub mod recurring;
+pub mod retention;
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use async_graphql::Result;
+use axum::http::StatusCode;
+use bson::{doc, DateTime};
+use futures::TryStreamExt;
+use log::warn;
+use mongodb::Collection;
+
+use crate::{
+    config::env_var,
+    event::http_event_service::{publish_change_event, publish_issued_event},
+    graphql::model::invoice::Invoice,
+};
+
+/// Runs the publication of pending events of issued documents every `$INVOICE_PUBLICATION_RETRY_INTERVAL_SECONDS` seconds, by default every minute.
+///
+/// * `collection` - MongoDB collection of invoices.
+pub async fn run(collection: Collection<Invoice>) {
+    let period_seconds: u64 = env_var("INVOICE_PUBLICATION_RETRY_INTERVAL_SECONDS").unwrap_or(60);
+    let mut interval = tokio::time::interval(Duration::from_secs(period_seconds));
+    loop {
+        interval.tick().await;
+        if let Err(e) = publish_pending_events(&collection, period_seconds).await {
+            warn!("Publishing pending invoice events failed: {}", e.message);
+        }
+    }
+}
+
+/// Publishes the events of documents which are still pending some time after issuance or a change, as publishing them failed or was interrupted.
+///
+/// Events recorded within the grace period are skipped, they are published by the issuing or changing handler, job or mutation.
+/// The events of a document are published in order, after its issuance.
+/// A failure is logged and the remaining documents are published anyway, the events of the failed document stay pending until the next run.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `grace_period_seconds` - Seconds after recording until a pending event is published by this job.
+pub async fn publish_pending_events(
+    collection: &Collection<Invoice>,
+    grace_period_seconds: u64,
+) -> Result<()> {
+    let pending_since = DateTime::from_chrono(
+        DateTime::now().to_chrono() - chrono::Duration::seconds(grace_period_seconds as i64),
+    );
+    let filter = doc! {"$or": [
+        {"pending_event": {"$ne": null}, "issued_at": {"$lt": pending_since}},
+        {"pending_events.recorded_at": {"$lt": pending_since}},
+    ]};
+    let mut cursor = collection.find(filter, None).await?;
+    while let Some(invoice) = cursor.try_next().await? {
+        if let Err(e) = publish_pending_events_of_invoice(collection, &invoice, pending_since).await
+        {
+            warn!(
+                "Publishing event of invoice of UUID: `{}` failed: {}",
+                invoice._id, e
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Publishes the pending events of a document recorded before a timestamp, stopping at the first failure.
+async fn publish_pending_events_of_invoice(
+    collection: &Collection<Invoice>,
+    invoice: &Invoice,
+    pending_since: DateTime,
+) -> Result<(), StatusCode> {
+    if invoice.pending_event.is_some() {
+        if invoice.issued_at >= pending_since {
+            return Ok(());
+        }
+        publish_issued_event(collection, invoice).await?;
+    }
+    for event in &invoice.pending_events {
+        if event.recorded_at >= pending_since {
+            break;
+        }
+        publish_change_event(collection, invoice._id, event).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,475 @@
+use std::{cmp::Ordering, time::Duration};
+
+use async_graphql::Result;
+use bson::{doc, DateTime, Uuid};
+use futures::TryStreamExt;
+use log::{info, warn};
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::env_var,
+    event::{
+        bus::InvoiceBusEvent,
+        http_event_service::{
+            publish_pending_event, spawn_invoice_post_processing,
+            HttpEventServiceState,
+        },
+    },
+    graphql::{
+        model::{
+            audit_entry::{AuditAction, AuditEntry},
+            billing::BillingPeriod,
+            credit::{release_credit, reserve_credit},
+            invoice::{Invoice, InvoiceDocumentType, InvoiceStatus, IssuedEvent},
+            invoice_line_item::InvoiceLineItem,
+            money::Money,
+            recurring::{BillingPlan, CustomerSubscription, SubscriptionStatus},
+        },
+        query::query_object,
+    },
+};
+
+/// Runs the recurring billing job every `$RECURRING_BILLING_INTERVAL_SECONDS` seconds, by default hourly.
+///
+/// * `state` - Service state containing database connections.
+pub async fn run(state: HttpEventServiceState) {
+    let period = Duration::from_secs(env_var("RECURRING_BILLING_INTERVAL_SECONDS").unwrap_or(3600));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = issue_due_subscription_invoices(&state, DateTime::now()).await {
+            warn!("Recurring billing failed: {}", e.message);
+        }
+    }
+}
+
+/// Invoices the billing periods of all active subscriptions which began, catching up on missed periods.
+///
+/// * `state` - Service state containing database connections.
+/// * `now` - Current time, billing periods beginning before it are invoiced.
+pub async fn issue_due_subscription_invoices(
+    state: &HttpEventServiceState,
+    now: DateTime,
+) -> Result<()> {
+    let filter = doc! {
+        "status": bson::to_bson(&SubscriptionStatus::Active)?,
+        "next_billing_at": {"$lte": now},
+    };
+    let mut cursor = state.subscription_collection.find(filter, None).await?;
+    while let Some(mut subscription) = cursor.try_next().await? {
+        while subscription.next_billing_at <= now {
+            match issue_period_invoice(state, &subscription).await {
+                Ok(Some(next)) => subscription = next,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!(
+                        "Invoicing subscription of UUID: `{}` failed: {}",
+                        subscription._id, e.message
+                    );
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Invoices the next billing period of a subscription in advance.
+///
+/// The period is claimed by advancing the subscription with a compare-and-set, so concurrent runs invoice each period once.
+/// Returns the advanced subscription, `None` if the period was claimed concurrently.
+async fn issue_period_invoice(
+    state: &HttpEventServiceState,
+    subscription: &CustomerSubscription,
+) -> Result<Option<CustomerSubscription>> {
+    let plan = query_object(&state.billing_plan_collection, subscription.plan_id).await?;
+    let period = plan
+        .interval
+        .period_starting_at(subscription.started_at, subscription.next_billing_at)?;
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+    let Some(claimed) = state
+        .subscription_collection
+        .find_one_and_update(
+            doc! {
+                "_id": subscription._id,
+                "status": bson::to_bson(&SubscriptionStatus::Active)?,
+                "next_billing_at": subscription.next_billing_at,
+            },
+            doc! {"$set": {
+                "next_billing_at": period.end,
+                "current_period": bson::to_bson(&period)?,
+                "current_invoice_id": null,
+            }},
+            options,
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+    if plan.price.amount == 0 {
+        return Ok(Some(claimed));
+    }
+    let line_items = vec![plan_line_item(&plan, &period, period.start)];
+    let invoice = match Invoice::new_subscription(
+        Uuid::new(),
+        &claimed,
+        plan.vendor_id,
+        period,
+        line_items,
+        state,
+    )
+    .await
+    {
+        Ok(invoice) => invoice,
+        Err(e) => {
+            state
+                .subscription_collection
+                .update_one(
+                    doc! {"_id": subscription._id, "next_billing_at": period.end},
+                    doc! {"$set": {
+                        "next_billing_at": subscription.next_billing_at,
+                        "current_period": bson::to_bson(&subscription.current_period)?,
+                        "current_invoice_id": subscription.current_invoice_id,
+                    }},
+                    None,
+                )
+                .await?;
+            return Err(e);
+        }
+    };
+    state
+        .subscription_collection
+        .update_one(
+            doc! {"_id": subscription._id},
+            doc! {"$set": {"current_invoice_id": invoice._id}},
+            None,
+        )
+        .await?;
+    publish_subscription_invoice(
+        state,
+        &invoice,
+        AuditEntry::new(AuditAction::InvoiceCreated, Some(invoice._id)).with_details(format!(
+            "Invoice `{}` of subscription `{}` with plan `{}`.",
+            invoice.invoice_number, subscription._id, plan._id
+        )),
+    )
+    .await;
+    Ok(Some(CustomerSubscription {
+        current_invoice_id: Some(invoice._id),
+        ..claimed
+    }))
+}
+
+/// Changes the plan of a subscription and prorates the change for the remainder of the invoiced billing period.
+///
+/// The proration documents are issued first, with UUIDs derived from the subscription and the event which changed the plan,
+/// so a redelivered event finds the documents it already issued instead of issuing them again.
+/// Only then the plan is changed with a compare-and-set, which is never reverted.
+/// Following billing periods are invoiced with the new plan.
+///
+/// * `state` - Service state containing database connections.
+/// * `subscription` - Subscription before the plan change.
+/// * `plan_id` - UUID of the new billing plan.
+/// * `event_id` - Id of the event which changed the plan.
+/// * `changed_at` - Timestamp of the event which changed the plan.
+/// * `trace_id` - Trace id of the event which changed the plan.
+pub async fn change_subscription_plan(
+    state: &HttpEventServiceState,
+    subscription: CustomerSubscription,
+    plan_id: Uuid,
+    event_id: &str,
+    changed_at: DateTime,
+    trace_id: Option<String>,
+) -> Result<()> {
+    let old_plan = query_object(&state.billing_plan_collection, subscription.plan_id).await?;
+    let new_plan = query_object(&state.billing_plan_collection, plan_id).await?;
+    let plan_change = PlanChange {
+        subscription: &subscription,
+        old_plan: &old_plan,
+        new_plan: &new_plan,
+        event_id,
+        changed_at,
+        trace_id,
+    };
+    let current_invoice_id = prorate_plan_change(state, &plan_change)
+        .await?
+        .or(subscription.current_invoice_id);
+    let result = state
+        .subscription_collection
+        .update_one(
+            doc! {"_id": subscription._id, "plan_id": subscription.plan_id},
+            doc! {"$set": {"plan_id": plan_id, "current_invoice_id": current_invoice_id}},
+            None,
+        )
+        .await?;
+    if result.modified_count == 0 {
+        info!(
+            "Plan of subscription of UUID: `{}` was changed concurrently.",
+            subscription._id
+        );
+    }
+    Ok(())
+}
+
+/// Plan change of a subscription, as received by an event.
+struct PlanChange<'a> {
+    /// Subscription before the plan change.
+    subscription: &'a CustomerSubscription,
+    old_plan: &'a BillingPlan,
+    new_plan: &'a BillingPlan,
+    /// Id of the event which changed the plan.
+    event_id: &'a str,
+    /// Timestamp of the event which changed the plan.
+    changed_at: DateTime,
+    /// Trace id of the event which changed the plan.
+    trace_id: Option<String>,
+}
+
+impl PlanChange<'_> {
+    /// Returns the UUID of a proration document of the plan change, derived from the subscription and the event.
+    ///
+    /// * `kind` - Kind of the document, distinguishing the documents of one plan change.
+    fn document_id(&self, kind: &str) -> Uuid {
+        let digest = Sha256::digest(format!(
+            "{}/{}/{}",
+            self.subscription._id, self.event_id, kind
+        ));
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        Uuid::from_bytes(bytes)
+    }
+}
+
+/// Credits the old plan and charges the new plan for the remainder of the current billing period.
+///
+/// Plans of the same vendor are netted: a positive difference is charged with a proration invoice,
+/// a negative difference is refunded with a credit note of the period's latest invoice.
+/// Plans of different vendors are charged before the old plan is credited, so a failure never leaves a credit without its charge.
+/// Returns the UUID of the proration invoice which becomes the period's latest invoice, `None` if none is issued.
+async fn prorate_plan_change(
+    state: &HttpEventServiceState,
+    plan_change: &PlanChange<'_>,
+) -> Result<Option<Uuid>> {
+    let subscription = plan_change.subscription;
+    let changed_at = plan_change.changed_at;
+    let Some(period) = subscription
+        .current_period
+        .filter(|period| period.start <= changed_at && changed_at < period.end)
+    else {
+        return Ok(None);
+    };
+    if subscription.status != SubscriptionStatus::Active {
+        return Ok(None);
+    }
+    let credit = plan_line_item(plan_change.old_plan, &period, changed_at).negated();
+    let charge = plan_line_item(plan_change.new_plan, &period, changed_at);
+    if plan_change.old_plan.vendor_id == plan_change.new_plan.vendor_id {
+        let difference = charge.amount.checked_add(&credit.amount)?;
+        let line_items = vec![credit, charge];
+        return match difference.amount.cmp(&0) {
+            Ordering::Greater => {
+                issue_proration_invoice(state, plan_change, period, line_items)
+                    .await
+                    .map(Some)
+            }
+            Ordering::Less => {
+                issue_proration_credit_note(state, plan_change, line_items, difference)
+                    .await
+                    .map(|_| None)
+            }
+            Ordering::Equal => Ok(None),
+        };
+    }
+    let proration_invoice_id = match charge.amount.amount > 0 {
+        true => Some(issue_proration_invoice(state, plan_change, period, vec![charge]).await?),
+        false => None,
+    };
+    let credited = credit.amount.clone();
+    issue_proration_credit_note(state, plan_change, vec![credit], credited).await?;
+    Ok(proration_invoice_id)
+}
+
+/// Issues an invoice which charges a plan change within a billing period and returns its UUID.
+///
+/// An invoice already issued for the plan change, by an earlier delivery of its event, is not issued again.
+async fn issue_proration_invoice(
+    state: &HttpEventServiceState,
+    plan_change: &PlanChange<'_>,
+    period: BillingPeriod,
+    line_items: Vec<InvoiceLineItem>,
+) -> Result<Uuid> {
+    let invoice_id = plan_change.document_id("invoice");
+    if state
+        .invoice_collection
+        .find_one(doc! {"_id": invoice_id}, None)
+        .await?
+        .is_some()
+    {
+        return Ok(invoice_id);
+    }
+    let subscription = plan_change.subscription;
+    let new_plan = plan_change.new_plan;
+    let invoice = Invoice::new_subscription(
+        invoice_id,
+        subscription,
+        new_plan.vendor_id,
+        period,
+        line_items,
+        state,
+    )
+    .await?;
+    publish_subscription_invoice(
+        state,
+        &invoice,
+        AuditEntry::new(AuditAction::InvoiceCreated, Some(invoice._id))
+            .with_trace_id(plan_change.trace_id.clone())
+            .with_details(format!(
+                "Proration invoice `{}` of subscription `{}` for plan change to `{}`.",
+                invoice.invoice_number, subscription._id, new_plan._id
+            )),
+    )
+    .await;
+    Ok(invoice_id)
+}
+
+/// Issues a credit note of the current period's latest invoice which refunds a plan change within the period.
+///
+/// Skipped if the period was not invoiced, e.g. for free plans, or its invoice is cancelled.
+/// A credit note already issued for the plan change, by an earlier delivery of its event, is not issued again.
+/// The credit is counted on the invoice before the credit note is issued, like credit notes of back-office, so it never exceeds the uncredited amount.
+async fn issue_proration_credit_note(
+    state: &HttpEventServiceState,
+    plan_change: &PlanChange<'_>,
+    line_items: Vec<InvoiceLineItem>,
+    total: Money,
+) -> Result<()> {
+    let subscription = plan_change.subscription;
+    let Some(invoice_id) = subscription.current_invoice_id else {
+        info!(
+            "Billing period of subscription of UUID: `{}` is not invoiced, skipping credit.",
+            subscription._id
+        );
+        return Ok(());
+    };
+    let credit_note_id = plan_change.document_id("credit-note");
+    if state
+        .invoice_collection
+        .find_one(doc! {"_id": credit_note_id}, None)
+        .await?
+        .is_some()
+    {
+        return Ok(());
+    }
+    let invoice = query_object(&state.invoice_collection, invoice_id).await?;
+    if invoice.status == InvoiceStatus::Cancelled {
+        info!(
+            "Invoice of UUID: `{}` is cancelled, skipping credit of plan change.",
+            invoice._id
+        );
+        return Ok(());
+    }
+    let mut credit_note = invoice.derive_document(
+        InvoiceDocumentType::CreditNote,
+        "Prorated plan change.".to_string(),
+    );
+    credit_note._id = credit_note_id;
+    credit_note.line_items = line_items;
+    credit_note.total = total;
+    credit_note.due_at = None;
+    credit_note.discount_deadline = None;
+    credit_note.early_payment_discount = None;
+    credit_note.pending_event = Some(IssuedEvent::CreditNote);
+    let amount = reserve_credit(
+        &state.invoice_collection,
+        &invoice,
+        Some(-credit_note.total.amount),
+    )
+    .await?;
+    if let Err(e) = credit_note
+        .issue(
+            &state.invoice_number_sequence_collection,
+            &state.invoice_collection,
+            state.signer.as_deref(),
+        )
+        .await
+    {
+        release_credit(&state.invoice_collection, &invoice, amount).await;
+        return Err(e);
+    }
+    state
+        .audit_log
+        .record(
+            AuditEntry::new(AuditAction::CreditNoteIssued, Some(invoice._id))
+                .with_trace_id(plan_change.trace_id.clone())
+                .with_details(format!(
+                    "Credit note `{}` of {}: plan change of subscription `{}`.",
+                    credit_note.invoice_number, credit_note.total, subscription._id
+                )),
+        )
+        .await;
+    publish_pending_event(&state.invoice_collection, &credit_note).await;
+    state
+        .event_bus
+        .publish(InvoiceBusEvent::Created(credit_note.clone()));
+    spawn_invoice_post_processing(
+        &state.invoice_collection,
+        &state.dispatch_config,
+        &state.storage_config,
+        &state.audit_log,
+        credit_note,
+    );
+    Ok(())
+}
+
+/// Records, publishes and dispatches an issued invoice of a subscription.
+///
+/// An event which fails to be published stays pending on the invoice and is published by the publication job.
+async fn publish_subscription_invoice(
+    state: &HttpEventServiceState,
+    invoice: &Invoice,
+    audit_entry: AuditEntry,
+) {
+    state.audit_log.record(audit_entry).await;
+    publish_pending_event(&state.invoice_collection, invoice).await;
+    state
+        .event_bus
+        .publish(InvoiceBusEvent::Created(invoice.clone()));
+    spawn_invoice_post_processing(
+        &state.invoice_collection,
+        &state.dispatch_config,
+        &state.storage_config,
+        &state.audit_log,
+        invoice.clone(),
+    );
+}
+
+/// Returns the line of a plan for a billing period, prorated from a timestamp.
+///
+/// The description names the plan and the covered days.
+fn plan_line_item(plan: &BillingPlan, period: &BillingPeriod, from: DateTime) -> InvoiceLineItem {
+    // The period ends exclusively, so its last day ends one millisecond earlier.
+    let last_day = DateTime::from_millis(period.end.timestamp_millis() - 1);
+    InvoiceLineItem {
+        order_id: None,
+        order_item_id: None,
+        product_variant_id: None,
+        product_variant_version_id: None,
+        product: None,
+        description: Some(format!(
+            "{} ({} - {})",
+            plan.name,
+            from.to_chrono().format("%Y-%m-%d"),
+            last_day.to_chrono().format("%Y-%m-%d")
+        )),
+        count: 1,
+        amount: plan.prorated_price(period, from),
+        undiscounted_amount: None,
+        discounts: vec![],
+        shipment_method_id: None,
+        tax_rate: None,
+        tax_amount: None,
+    }
+}
@@ -0,0 +1,234 @@
+use std::time::Duration;
+
+use async_graphql::Result;
+use bson::{doc, DateTime};
+use chrono::Months;
+use futures::TryStreamExt;
+use log::{info, warn};
+use mongodb::{options::FindOptions, Collection};
+
+use crate::{
+    audit::AuditLog,
+    config::env_var,
+    document::storage::{delete_document, StorageConfig},
+    graphql::model::{
+        audit_entry::{AuditAction, AuditEntry},
+        foreign_types::User,
+        invoice::Invoice,
+        order_invoicing::OrderInvoicing,
+        recurring::CustomerSubscription,
+    },
+};
+
+/// Configuration of the retention of invoices and personal data.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    /// Number of years invoices are retained after their issuance.
+    pub retention_years: u32,
+}
+
+impl RetentionPolicy {
+    /// Reads the policy from `$INVOICE_RETENTION_YEARS`, by default 10 years.
+    pub fn from_env() -> Self {
+        Self {
+            retention_years: env_var("INVOICE_RETENTION_YEARS").unwrap_or(10),
+        }
+    }
+
+    /// Returns the timestamp before which issued invoices are purged.
+    pub fn cutoff(&self, now: DateTime) -> DateTime {
+        let cutoff = now
+            .to_chrono()
+            .checked_sub_months(Months::new(12 * self.retention_years))
+            .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC);
+        DateTime::from_chrono(cutoff)
+    }
+}
+
+/// Runs the retention job every `$RETENTION_INTERVAL_SECONDS` seconds, by default daily.
+///
+/// * `collections` - MongoDB collections holding invoices and personal data.
+/// * `storage_config` - Configuration of the document storage.
+/// * `audit_log` - Audit log to record purges in.
+pub async fn run(
+    collections: RetentionCollections,
+    storage_config: StorageConfig,
+    audit_log: AuditLog,
+) {
+    let policy = RetentionPolicy::from_env();
+    let period = Duration::from_secs(env_var("RETENTION_INTERVAL_SECONDS").unwrap_or(86400));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = purge_expired_invoices(
+            &collections.invoices,
+            &storage_config,
+            &audit_log,
+            &policy,
+        )
+        .await
+        {
+            warn!("Purging expired invoices failed: {}", e.message);
+        }
+        if let Err(e) = purge_expired_records(&collections, &audit_log, &policy).await {
+            warn!("Purging expired subscriptions and order invoicing states failed: {}", e.message);
+        }
+        if let Err(e) = purge_erased_users(&collections, &audit_log).await {
+            warn!("Purging erased users failed: {}", e.message);
+        }
+    }
+}
+
+/// MongoDB collections holding invoices and personal data, which are subject to retention.
+#[derive(Clone)]
+pub struct RetentionCollections {
+    pub invoices: Collection<Invoice>,
+    pub users: Collection<User>,
+    pub subscriptions: Collection<CustomerSubscription>,
+    pub order_invoicing: Collection<OrderInvoicing>,
+}
+
+/// Purges all invoices issued before the retention period together with their stored documents.
+///
+/// Invoices are purged oldest first, so the remaining hash chain of each numbering sequence stays intact.
+///
+/// * `collection` - MongoDB collection of invoices.
+/// * `storage_config` - Configuration of the document storage.
+/// * `audit_log` - Audit log to record purges in.
+/// * `policy` - Configuration of the retention period.
+pub async fn purge_expired_invoices(
+    collection: &Collection<Invoice>,
+    storage_config: &StorageConfig,
+    audit_log: &AuditLog,
+    policy: &RetentionPolicy,
+) -> Result<()> {
+    let cutoff = policy.cutoff(DateTime::now());
+    let find_options = FindOptions::builder()
+        .sort(doc! {"sequence_id": 1, "sequence_number": 1, "issued_at": 1})
+        .build();
+    let mut cursor = collection
+        .find(doc! {"issued_at": {"$lt": cutoff}}, find_options)
+        .await?;
+    while let Some(invoice) = cursor.try_next().await? {
+        if let Err(e) = purge_invoice(collection, storage_config, audit_log, &invoice).await {
+            warn!(
+                "Purging invoice of UUID: `{}` failed: {}",
+                invoice._id, e.message
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the stored documents of an invoice and then the invoice itself.
+async fn purge_invoice(
+    collection: &Collection<Invoice>,
+    storage_config: &StorageConfig,
+    audit_log: &AuditLog,
+    invoice: &Invoice,
+) -> Result<()> {
+    for document in &invoice.documents {
+        delete_document(storage_config, document).await?;
+    }
+    let result = collection.delete_one(doc! {"_id": invoice._id}, None).await?;
+    if result.deleted_count == 1 {
+        info!(
+            "Invoice of UUID: `{}` was purged after its retention period.",
+            invoice._id
+        );
+        audit_log
+            .record(
+                AuditEntry::new(AuditAction::InvoicePurged, Some(invoice._id)).with_details(
+                    format!("Invoice `{}` issued at {}.", invoice.invoice_number, invoice.issued_at),
+                ),
+            )
+            .await;
+    }
+    Ok(())
+}
+
+/// Purges the invoicing states of orders validated and the subscriptions cancelled before the retention period.
+///
+/// Both hold a snapshot of the customer's data, like the VAT number and address references.
+/// Invoicing states recorded before the validation time was tracked are purged with the projection of their erased user.
+///
+/// * `collections` - MongoDB collections holding invoices and personal data.
+/// * `audit_log` - Audit log to record purges in.
+/// * `policy` - Configuration of the retention period.
+pub async fn purge_expired_records(
+    collections: &RetentionCollections,
+    audit_log: &AuditLog,
+    policy: &RetentionPolicy,
+) -> Result<()> {
+    let cutoff = policy.cutoff(DateTime::now());
+    let order_invoicing = collections
+        .order_invoicing
+        .delete_many(doc! {"validated_at": {"$lt": cutoff}}, None)
+        .await?;
+    let subscriptions = collections
+        .subscriptions
+        .delete_many(doc! {"cancelled_at": {"$lt": cutoff}}, None)
+        .await?;
+    if order_invoicing.deleted_count > 0 || subscriptions.deleted_count > 0 {
+        info!(
+            "Purged {} order invoicing states and {} subscriptions after their retention period.",
+            order_invoicing.deleted_count, subscriptions.deleted_count
+        );
+        audit_log
+            .record(
+                AuditEntry::new(AuditAction::RecordsPurged, None).with_details(format!(
+                    "{} order invoicing states validated and {} subscriptions cancelled before {}.",
+                    order_invoicing.deleted_count, subscriptions.deleted_count, cutoff
+                )),
+            )
+            .await;
+    }
+    Ok(())
+}
+
+/// Purges the pseudonymized projections of deleted users which have no invoices left,
+/// together with their subscriptions and the invoicing states of their orders.
+///
+/// * `collections` - MongoDB collections holding invoices and personal data.
+/// * `audit_log` - Audit log to record purges in.
+pub async fn purge_erased_users(
+    collections: &RetentionCollections,
+    audit_log: &AuditLog,
+) -> Result<()> {
+    let mut cursor = collections
+        .users
+        .find(doc! {"erased_at": {"$ne": null}}, None)
+        .await?;
+    while let Some(user) = cursor.try_next().await? {
+        let invoice_count = collections
+            .invoices
+            .count_documents(doc! {"user_id": user._id}, None)
+            .await?;
+        if invoice_count > 0 {
+            continue;
+        }
+        let subscriptions = collections
+            .subscriptions
+            .delete_many(doc! {"user_id": user._id}, None)
+            .await?;
+        let order_invoicing = collections
+            .order_invoicing
+            .delete_many(doc! {"order.user_id": user._id}, None)
+            .await?;
+        let result = collections
+            .users
+            .delete_one(doc! {"_id": user._id}, None)
+            .await?;
+        if result.deleted_count == 1 {
+            audit_log
+                .record(
+                    AuditEntry::new(AuditAction::UserPurged, None).with_details(format!(
+                        "User of UUID: `{}` with {} subscriptions and {} order invoicing states.",
+                        user._id, subscriptions.deleted_count, order_invoicing.deleted_count
+                    )),
+                )
+                .await;
+        }
+    }
+    Ok(())
+}
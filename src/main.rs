@@ -1,22 +1,23 @@
 use std::{env, fs::File, io::Write};
 
 use async_graphql::{
-    extensions::Logger, http::GraphiQLSource, EmptyMutation, EmptySubscription, SDLExportOptions,
-    Schema,
+    extensions::Logger,
+    http::{GraphiQLSource, ALL_WEBSOCKET_PROTOCOLS},
+    Data, SDLExportOptions, Schema,
 };
-use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
 
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::{self, IntoResponse},
+    extract::{State, WebSocketUpgrade},
+    http::{HeaderMap, StatusCode},
+    response::{self, IntoResponse, Response},
     routing::{get, post},
     Router,
 };
-use clap::{arg, command, Parser};
+use clap::{Parser, Subcommand};
 
 use log::{info, Level};
-use mongodb::{options::ClientOptions, Client, Database};
+use mongodb::{options::ClientOptions, Client};
 
 use once_cell::sync::Lazy;
 use axum_otel_metrics::HttpMetricsLayerBuilder;
@@ -27,23 +28,57 @@ use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider, Temporality};
 use opentelemetry_sdk::Resource;
 use opentelemetry_otlp::WithExportConfig;
 
+mod audit;
+mod authorization;
+mod binding;
+mod cli;
+mod config;
+mod dispatch;
+mod document;
 mod event;
+mod export;
 mod graphql;
+mod i18n;
+mod indexes;
+mod job;
+mod migration;
+mod render;
+mod signature;
 
 use event::http_event_service::{
     list_topic_subscriptions, on_discount_order_validation_succeeded_event,
+    on_order_created_event, on_product_variant_created_event, on_shipment_created_event,
     on_user_address_archived_event, on_user_address_creation_event, on_user_created_event,
-    on_vendor_address_created_event, HttpEventServiceState,
+    on_user_deleted_event, on_vendor_address_created_event, on_billing_plan_created_event,
+    on_subscription_created_event, on_subscription_updated_event,
+    on_subscription_cancelled_event, on_discount_created_event, on_shipment_method_event,
+    on_product_variant_version_created_event,
+    HttpEventServiceState,
 };
+use audit::{trace_id_from_headers, TraceId};
+use authorization::AuthorizedUser;
+use cli::AdminCommand;
+use config::ServiceContext;
+use document::http_document_service::{
+    download_invoice_document, get_signing_public_key, verify_invoice_signature,
+    HttpDocumentServiceState,
+};
+use export::http_export_service::{download_user_data_export, HttpExportServiceState};
 use graphql::model::{
-    foreign_types::{User, VendorAddress},
-    invoice::Invoice,
+    foreign_types::User, invoice::Invoice, order_invoicing::OrderInvoicing,
+    recurring::CustomerSubscription,
 };
-use graphql::query::Query;
+use graphql::{mutation::Mutation, query::Query, subscription::Subscription};
+use job::retention::RetentionCollections;
 
 /// Builds the GraphiQL frontend.
 async fn graphiql() -> impl IntoResponse {
-    response::Html(GraphiQLSource::build().endpoint("/").finish())
+    response::Html(
+        GraphiQLSource::build()
+            .endpoint("/")
+            .subscription_endpoint("/ws")
+            .finish(),
+    )
 }
 
 /// Establishes database connection and returns the client.
@@ -66,15 +101,9 @@ async fn db_connection() -> Client {
 /// Returns Router that establishes connection to Dapr.
 ///
 /// Adds endpoints to define pub/sub interaction with Dapr.
-async fn build_dapr_router(db_client: Database) -> Router {
-    let invoice_collection: mongodb::Collection<Invoice> =
-        db_client.collection::<Invoice>("invoices");
-    let vendor_address_collection: mongodb::Collection<VendorAddress> =
-        db_client.collection::<VendorAddress>("vendor_address");
-    let user_collection: mongodb::Collection<User> = db_client.collection::<User>("user");
-
+async fn build_dapr_router(context: ServiceContext) -> Router {
     // Define routes.
-    let app = Router::new()
+    Router::new()
         .route("/dapr/subscribe", get(list_topic_subscriptions))
         .route(
             "/on-discount-validation-succeded",
@@ -85,6 +114,7 @@ async fn build_dapr_router(db_client: Database) -> Router {
             post(on_vendor_address_created_event),
         )
         .route("/on-user-creation-event", post(on_user_created_event))
+        .route("/on-user-deletion-event", post(on_user_deleted_event))
         .route(
             "/on-user-address-creation-event",
             post(on_user_address_creation_event),
@@ -93,53 +123,185 @@ async fn build_dapr_router(db_client: Database) -> Router {
             "/on-user-address-archived-event",
             post(on_user_address_archived_event),
         )
-        .with_state(HttpEventServiceState {
+        .route(
+            "/on-product-variant-creation-event",
+            post(on_product_variant_created_event),
+        )
+        .route(
+            "/on-product-variant-version-creation-event",
+            post(on_product_variant_version_created_event),
+        )
+        .route("/on-order-creation-event", post(on_order_created_event))
+        .route("/on-shipment-creation-event", post(on_shipment_created_event))
+        .route("/on-discount-creation-event", post(on_discount_created_event))
+        .route(
+            "/on-shipment-method-creation-event",
+            post(on_shipment_method_event),
+        )
+        .route(
+            "/on-shipment-method-update-event",
+            post(on_shipment_method_event),
+        )
+        .route(
+            "/on-billing-plan-creation-event",
+            post(on_billing_plan_created_event),
+        )
+        .route(
+            "/on-subscription-creation-event",
+            post(on_subscription_created_event),
+        )
+        .route(
+            "/on-subscription-update-event",
+            post(on_subscription_updated_event),
+        )
+        .route(
+            "/on-subscription-cancellation-event",
+            post(on_subscription_cancelled_event),
+        )
+        .with_state(HttpEventServiceState::new(context))
+}
+
+/// Returns Router that serves generated invoice documents.
+async fn build_document_router(context: ServiceContext) -> Router {
+    let invoice_collection: mongodb::Collection<Invoice> =
+        context.db_client.collection::<Invoice>("invoices");
+
+    Router::new()
+        .route(
+            "/invoices/{id}/documents/{format}",
+            get(download_invoice_document),
+        )
+        .route("/signatures/verify", post(verify_invoice_signature))
+        .route("/signatures/keys/{key_id}", get(get_signing_public_key))
+        .with_state(HttpDocumentServiceState {
             invoice_collection,
-            vendor_address_collection,
-            user_collection,
-        });
-    app
+            storage_config: context.storage_config,
+            signer: context.signer,
+            audit_log: context.audit_log,
+        })
+}
+
+/// Returns Router that serves data exports.
+async fn build_export_router(context: ServiceContext) -> Router {
+    Router::new()
+        .route("/users/{id}/data-export", get(download_user_data_export))
+        .with_state(HttpExportServiceState {
+            invoice_collection: context.db_client.collection::<Invoice>("invoices"),
+            user_collection: context.db_client.collection::<User>("user"),
+            subscription_collection: context
+                .db_client
+                .collection::<CustomerSubscription>("customer_subscriptions"),
+            order_invoicing_collection: context
+                .db_client
+                .collection::<OrderInvoicing>("order_invoicing"),
+            audit_log: context.audit_log,
+        })
 }
 
-/// Command line argument to toggle schema generation instead of service execution.
+/// Command line arguments selecting the service or an administrative subcommand.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Generates GraphQL schema in `./schemas/invoice.graphql`.
-    #[arg(long)]
+    /// Generates GraphQL schema in `./schemas/invoice.graphql`, kept for compatibility with the `generate-schema` subcommand.
+    #[arg(long, hide = true)]
     generate_schema: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Subcommands of the invoice service, `serve` if none is given.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Starts the invoice service.
+    Serve,
+    /// Generates GraphQL schema in `./schemas/invoice.graphql`.
+    GenerateSchema,
+    #[command(flatten)]
+    Admin(AdminCommand),
 }
 
-/// Activates logger and parses argument for optional schema generation. Otherwise starts gRPC and GraphQL server.
+/// Activates logger and parses arguments for schema generation or administrative subcommands. Otherwise starts GraphQL server.
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     simple_logger::init_with_level(Level::Warn).unwrap();
 
     let args = Args::parse();
-    if args.generate_schema {
-        let schema = Schema::build(Query, EmptyMutation, EmptySubscription).finish();
-        let mut file = File::create("./schemas/invoice.graphql")?;
-        let sdl_export_options = SDLExportOptions::new().federation();
-        let schema_sdl = schema.sdl_with_options(sdl_export_options);
-        file.write_all(schema_sdl.as_bytes())?;
-        info!("GraphQL schema: ./schemas/invoice.graphql was successfully generated!");
-    } else {
-        start_service().await;
+    match args.command {
+        _ if args.generate_schema => generate_schema()?,
+        Some(Command::GenerateSchema) => generate_schema()?,
+        Some(Command::Admin(command)) => {
+            if let Err(e) = cli::run(command, &service_context().await).await {
+                eprintln!("{}", e.message);
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Serve) | None => start_service(service_context().await).await,
     }
     Ok(())
 }
 
+/// Generates GraphQL schema in `./schemas/invoice.graphql`.
+fn generate_schema() -> std::io::Result<()> {
+    let schema = Schema::build(Query, Mutation, Subscription).finish();
+    let mut file = File::create("./schemas/invoice.graphql")?;
+    let sdl_export_options = SDLExportOptions::new().federation();
+    let schema_sdl = schema.sdl_with_options(sdl_export_options);
+    file.write_all(schema_sdl.as_bytes())?;
+    info!("GraphQL schema: ./schemas/invoice.graphql was successfully generated!");
+    Ok(())
+}
+
+/// Connects to the database and reads the configuration shared by the service and the subcommands.
+async fn service_context() -> ServiceContext {
+    let client = db_connection().await;
+    ServiceContext::new(client.database("invoice-database"))
+}
+
 /// Describes the handler for GraphQL requests.
 ///
-/// Executes the GraphQL schema with the request.
+/// Executes the GraphQL schema with the request, passing the authenticated user of the `Authorized-User` header
+/// and the trace id of the `traceparent` header.
 async fn graphql_handler(
-    State(schema): State<Schema<Query, EmptyMutation, EmptySubscription>>,
+    State(schema): State<Schema<Query, Mutation, Subscription>>,
+    headers: HeaderMap,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
-    let req = req.into_inner();
+    let mut req = req.into_inner();
+    if let Some(authorized_user) = AuthorizedUser::from_headers(&headers) {
+        req = req.data(authorized_user);
+    }
+    if let Some(trace_id) = trace_id_from_headers(&headers) {
+        req = req.data(TraceId(trace_id));
+    }
     schema.execute(req).await.into()
 }
 
+/// Describes the handler for GraphQL subscriptions over graphql-ws.
+///
+/// Passes the authenticated user of the `Authorized-User` header and the trace id of the `traceparent` header
+/// of the upgrade request to all subscriptions of the connection.
+async fn graphql_ws_handler(
+    State(schema): State<Schema<Query, Mutation, Subscription>>,
+    headers: HeaderMap,
+    protocol: GraphQLProtocol,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let mut data = Data::default();
+    if let Some(authorized_user) = AuthorizedUser::from_headers(&headers) {
+        data.insert(authorized_user);
+    }
+    if let Some(trace_id) = trace_id_from_headers(&headers) {
+        data.insert(TraceId(trace_id));
+    }
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| {
+            GraphQLWebSocket::new(stream, schema, protocol)
+                .with_data(data)
+                .serve()
+        })
+}
+
 static RESOURCE: Lazy<Resource> = Lazy::new(|| {
     Resource::builder()
         .with_service_name("invoice")
@@ -172,25 +334,68 @@ fn init_otlp() -> HttpMetricsLayer {
 }
 
 /// Starts invoice service on port 8000.
-async fn start_service() {
-    let client = db_connection().await;
-    let db_client: Database = client.database("invoice-database");
+///
+/// * `context` - Configuration and connections shared with the subcommands.
+async fn start_service(context: ServiceContext) {
+    let db_client = context.db_client.clone();
+    let audit_log = context.audit_log.clone();
+    if let Err(e) = migration::run_pending_migrations(&db_client).await {
+        panic!("Applying migrations failed: {}", e.message);
+    }
+    if let Err(e) = indexes::ensure_indexes(&db_client).await {
+        panic!("Ensuring indexes failed: {}", e.message);
+    }
 
-    let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+    let schema = Schema::build(Query, Mutation, Subscription)
         .extension(Logger)
         .data(db_client.clone())
+        .data(audit_log.clone())
+        .data(context.clone())
         .enable_federation()
         .finish();
 
     let graphiql = Router::new()
         .route("/", get(graphiql).post(graphql_handler))
+        .route("/ws", get(graphql_ws_handler))
         .route("/health", get(StatusCode::OK))
         .with_state(schema);
-    let dapr_router = build_dapr_router(db_client).await;
+    tokio::spawn(job::collective::run(HttpEventServiceState::new(
+        context.clone(),
+    )));
+    tokio::spawn(job::recurring::run(HttpEventServiceState::new(
+        context.clone(),
+    )));
+    tokio::spawn(job::dunning::run(
+        db_client.collection::<Invoice>("invoices"),
+        audit_log.clone(),
+    ));
+    tokio::spawn(job::dispatch::run(
+        db_client.collection::<Invoice>("invoices"),
+        audit_log.clone(),
+    ));
+    tokio::spawn(job::publication::run(
+        db_client.collection::<Invoice>("invoices"),
+    ));
+    tokio::spawn(job::retention::run(
+        RetentionCollections {
+            invoices: db_client.collection::<Invoice>("invoices"),
+            users: db_client.collection::<User>("user"),
+            subscriptions: db_client.collection::<CustomerSubscription>("customer_subscriptions"),
+            order_invoicing: db_client.collection::<OrderInvoicing>("order_invoicing"),
+        },
+        context.storage_config.clone(),
+        audit_log,
+    ));
+
+    let document_router = build_document_router(context.clone()).await;
+    let export_router = build_export_router(context.clone()).await;
+    let dapr_router = build_dapr_router(context).await;
     let metrics = init_otlp();
 
     let app = Router::new()
         .merge(graphiql)
+        .merge(document_router)
+        .merge(export_router)
         .merge(dapr_router)
         .layer(metrics);
 
@@ -0,0 +1,180 @@
+use async_graphql::Result;
+use bson::{doc, DateTime, Document};
+use futures::{future::BoxFuture, FutureExt, TryStreamExt};
+use log::info;
+use mongodb::{
+    options::{FindOptions, UpdateOptions},
+    Database,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::indexes::{create_indexes, IndexDefinition};
+
+/// Record of an applied migration in the `_migrations` collection.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MigrationRecord {
+    /// Version of the migration.
+    pub _id: i64,
+    /// Name of the migration.
+    pub name: String,
+    /// Timestamp when the migration was applied.
+    pub applied_at: DateTime,
+}
+
+/// Migration step of the stored documents.
+///
+/// Steps must be idempotent, as replicas starting concurrently may apply the same step.
+struct Migration {
+    /// Version of the migration, migrations are applied in ascending order.
+    version: i64,
+    /// Name of the migration.
+    name: &'static str,
+    /// Applies the migration to the database.
+    apply: for<'a> fn(&'a Database) -> BoxFuture<'a, Result<()>>,
+}
+
+/// All migrations in order of their version. New migrations are appended with the next version.
+fn migrations() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            name: "create_indexes",
+            apply: |db_client| create_initial_indexes(db_client).boxed(),
+        },
+        Migration {
+            version: 2,
+            name: "move_user_addresses_to_addresses",
+            apply: |db_client| move_user_addresses_to_addresses(db_client).boxed(),
+        },
+        Migration {
+            version: 3,
+            name: "backfill_invoice_status",
+            apply: |db_client| backfill_invoice_status(db_client).boxed(),
+        },
+    ]
+}
+
+/// Applies all migrations which are not recorded in the `_migrations` collection, returns the applied migrations.
+///
+/// * `db_client` - Database of the invoice service.
+pub async fn run_pending_migrations(db_client: &Database) -> Result<Vec<MigrationRecord>> {
+    let collection = db_client.collection::<MigrationRecord>("_migrations");
+    let find_options = FindOptions::builder().sort(doc! {"_id": 1}).build();
+    let recorded: Vec<MigrationRecord> = collection
+        .find(doc! {}, find_options)
+        .await?
+        .try_collect()
+        .await?;
+    let mut applied = vec![];
+    for migration in migrations() {
+        if recorded.iter().any(|record| record._id == migration.version) {
+            continue;
+        }
+        info!(
+            "Applying migration {} `{}`.",
+            migration.version, migration.name
+        );
+        (migration.apply)(db_client).await?;
+        let record = MigrationRecord {
+            _id: migration.version,
+            name: migration.name.to_string(),
+            applied_at: DateTime::now(),
+        };
+        collection
+            .update_one(
+                doc! {"_id": record._id},
+                doc! {"$setOnInsert": bson::to_document(&record)?},
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        applied.push(record);
+    }
+    Ok(applied)
+}
+
+/// Creates the initial indexes of the invoice service.
+///
+/// The definitions are pinned to those of this migration, indexes defined later are created when indexes are ensured on startup.
+async fn create_initial_indexes(db_client: &Database) -> Result<()> {
+    create_indexes(db_client, initial_indexes()).await?;
+    Ok(())
+}
+
+/// Indexes created by the first migration.
+fn initial_indexes() -> Vec<IndexDefinition> {
+    vec![
+        ("invoices", "order_id", doc! {"order_id": 1}, None),
+        ("invoices", "user_id", doc! {"user_id": 1}, None),
+        ("invoices", "issued_at", doc! {"issued_at": 1}, None),
+        (
+            "invoices",
+            "sequence",
+            doc! {"sequence_id": 1, "sequence_number": 1},
+            Some(doc! {"sequence_number": {"$type": "long"}}),
+        ),
+        ("invoices", "status_due_at", doc! {"status": 1, "due_at": 1}, None),
+        (
+            "invoices",
+            "order_vendor_shipment_invoice",
+            doc! {"order_id": 1, "vendor_id": 1, "shipment_id": 1},
+            Some(doc! {"document_type": "Invoice", "order_id": {"$type": "binData"}}),
+        ),
+        (
+            "invoices",
+            "order_vendor_proforma",
+            doc! {"order_id": 1, "vendor_id": 1},
+            Some(doc! {"document_type": "Proforma", "order_id": {"$type": "binData"}}),
+        ),
+        ("user", "addresses_id", doc! {"addresses._id": 1}, None),
+        (
+            "customer_subscriptions",
+            "status_next_billing_at",
+            doc! {"status": 1, "next_billing_at": 1},
+            None,
+        ),
+        ("customer_subscriptions", "user_id", doc! {"user_id": 1}, None),
+        (
+            "order_invoicing",
+            "order_user_id",
+            doc! {"order.user_id": 1},
+            None,
+        ),
+        (
+            "invoice_audit",
+            "invoice_id_occurred_at",
+            doc! {"invoice_id": 1, "occurred_at": -1},
+            None,
+        ),
+        ("invoice_audit", "actor_id", doc! {"actor_id": 1}, None),
+        ("invoice_audit", "occurred_at", doc! {"occurred_at": -1}, None),
+    ]
+}
+
+/// Moves addresses which were stored under `user_addresses` to the `addresses` of the user projection.
+async fn move_user_addresses_to_addresses(db_client: &Database) -> Result<()> {
+    let pipeline = vec![
+        doc! {"$set": {"addresses": {"$concatArrays": [
+            {"$ifNull": ["$addresses", []]},
+            "$user_addresses",
+        ]}}},
+        doc! {"$unset": "user_addresses"},
+    ];
+    db_client
+        .collection::<Document>("user")
+        .update_many(doc! {"user_addresses": {"$type": "array"}}, pipeline, None)
+        .await?;
+    Ok(())
+}
+
+/// Sets the status of invoices stored before payment status was recorded to `Open`.
+async fn backfill_invoice_status(db_client: &Database) -> Result<()> {
+    db_client
+        .collection::<Document>("invoices")
+        .update_many(
+            doc! {"status": null},
+            doc! {"$set": {"status": "Open"}},
+            None,
+        )
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,321 @@
+use bson::{DateTime, Uuid};
+
+use crate::{
+    graphql::model::{
+        invoice::{Invoice, InvoiceDocumentType},
+        invoice_line_item::{InvoiceLineItem, LineItemDiscount, LineItemProduct},
+        invoice_party::InvoiceParty,
+    },
+    i18n::Locale,
+};
+
+/// Renders the markdown content of an invoice in a locale.
+///
+/// Only uses data stored on the invoice, so re-rendering an invoice yields the same document.
+pub fn render_invoice_content(invoice: &Invoice, locale: &Locale) -> String {
+    format!(
+        r#"
+# {}{}
+
+### {}:
+{}
+
+### {}:
+{}: {}
+{}
+
+### {}: {}, {}: {}, {}: {}
+
+{}: {}
+
+---
+
+{}:
+
+{}
+
+---
+
+{}: {}
+
+{}
+"#,
+        locale.message(invoice.document_type.title_key()),
+        render_reference(invoice, locale),
+        locale.message("invoice.company_information"),
+        render_party(&invoice.vendor, locale),
+        locale.message("invoice.customer_information"),
+        locale.message("invoice.customer_id"),
+        invoice.user_id,
+        render_party(&invoice.customer, locale),
+        locale.message("invoice.invoice_number"),
+        invoice.invoice_number,
+        locale.message("invoice.invoice_id"),
+        invoice._id,
+        locale.message("invoice.issued_at"),
+        locale.format_date_time(invoice.issued_at),
+        locale.message("invoice.terms_label"),
+        locale.message("invoice.terms"),
+        locale.message("invoice.items_overview"),
+        render_line_items(invoice, locale),
+        locale.message("invoice.total"),
+        locale.format_money(&invoice.total),
+        render_payment_terms(invoice, locale)
+    )
+}
+
+/// Renders the non-binding notice of proformas, the billing period of collective invoices,
+/// and the invoice a corrective invoice or credit note refers to and its reason.
+///
+/// Empty for regular invoices.
+fn render_reference(invoice: &Invoice, locale: &Locale) -> String {
+    let mut content = String::new();
+    if invoice.document_type == InvoiceDocumentType::Proforma {
+        content.push_str(&format!("\n\n**{}**", locale.message("invoice.proforma_notice")));
+    }
+    if let Some(period) = invoice.billing_period {
+        // The period ends exclusively, so its last day ends one millisecond earlier.
+        let last_day = DateTime::from_millis(period.end.timestamp_millis() - 1);
+        content.push_str("\n\n");
+        content.push_str(
+            &locale
+                .message("invoice.billing_period")
+                .replace("{start}", &locale.format_date(period.start))
+                .replace("{end}", &locale.format_date(last_day)),
+        );
+    }
+    if let Some(number) = &invoice.related_invoice_number {
+        content.push_str("\n\n");
+        content.push_str(&locale.message("invoice.refers_to").replace("{number}", number));
+    }
+    if let Some(reason) = &invoice.reason {
+        content.push_str(&format!("\n\n{}: {}", locale.message("invoice.reason"), reason));
+    }
+    content
+}
+
+/// Renders the payment terms, due date and early payment discount of an invoice.
+fn render_payment_terms(invoice: &Invoice, locale: &Locale) -> String {
+    let Some(due_at) = invoice.due_at else {
+        return String::new();
+    };
+    let mut content = format!(
+        "{}: {}",
+        locale.message("invoice.payment_terms"),
+        locale
+            .message("invoice.net_days")
+            .replace("{days}", &invoice.payment_terms.net_days.to_string())
+            .replace("{due_at}", &locale.format_date(due_at))
+    );
+    if let (Some(percentage), Some(amount), Some(deadline)) = (
+        invoice.payment_terms.early_payment_discount_percentage,
+        &invoice.early_payment_discount,
+        invoice.discount_deadline,
+    ) {
+        content.push(' ');
+        content.push_str(
+            &locale
+                .message("invoice.early_payment_discount")
+                .replace("{percentage}", &locale.format_percentage(percentage))
+                .replace("{amount}", &locale.format_money(amount))
+                .replace("{deadline}", &locale.format_date(deadline)),
+        );
+    }
+    content
+}
+
+/// Renders the line items of an invoice as a markdown table.
+///
+/// Collective invoices render a table per consolidated order, headed by the order.
+fn render_line_items(invoice: &Invoice, locale: &Locale) -> String {
+    if invoice.order_ids.is_empty() {
+        return render_line_item_table(invoice.line_items.iter(), locale);
+    }
+    invoice
+        .order_ids
+        .iter()
+        .map(|order_id| {
+            format!(
+                "**{}**\n\n{}",
+                locale.message("invoice.order").replace("{id}", &order_id.to_string()),
+                render_line_item_table(
+                    invoice
+                        .line_items
+                        .iter()
+                        .filter(|item| item.order_id == Some(*order_id)),
+                    locale
+                )
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Renders line items as a markdown table.
+///
+/// Product lines show the name and SKU of the ordered product variant version, followed by a row with its description and characteristics.
+/// Discounted lines show their undiscounted amount, followed by a row per discount with its reduction.
+/// Lines with their own tax treatment, like shipping, are followed by a row with their included VAT.
+fn render_line_item_table<'a>(
+    line_items: impl Iterator<Item = &'a InvoiceLineItem>,
+    locale: &Locale,
+) -> String {
+    let mut content = format!(
+        "| {} | {} | {} | {} |\n",
+        locale.message("invoice.item"),
+        locale.message("invoice.sku"),
+        locale.message("invoice.count"),
+        locale.message("invoice.amount")
+    );
+    content.push_str("| --- | --- | --- | --- |\n");
+    for item in line_items {
+        content.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            table_cell(&render_line_item_label(item, locale)),
+            match item.product.as_ref().and_then(|product| product.sku.as_deref()) {
+                Some(sku) => table_cell(sku),
+                None => optional_id(item.product_variant_id),
+            },
+            locale.format_number(item.count),
+            locale.format_money(item.undiscounted_amount.as_ref().unwrap_or(&item.amount))
+        ));
+        if let Some(details) = item.product.as_ref().and_then(render_product_details) {
+            content.push_str(&format!("| {} | - | - | - |\n", table_cell(&details)));
+        }
+        for discount in &item.discounts {
+            content.push_str(&format!(
+                "| {} | - | - | {} |\n",
+                table_cell(&render_discount(discount, locale)),
+                locale.format_money(&discount.amount)
+            ));
+        }
+        if let (Some(rate), Some(tax_amount)) = (item.tax_rate, &item.tax_amount) {
+            content.push_str(&format!(
+                "| {} | - | - | - |\n",
+                locale
+                    .message("invoice.included_vat")
+                    .replace("{rate}", &locale.format_percentage(rounded_percentage(rate)))
+                    .replace("{amount}", &locale.format_money(tax_amount))
+            ));
+        }
+    }
+    content
+}
+
+/// Renders the label of a line, e.g. the product name of order items or `Shipping: Express` for shipping lines.
+fn render_line_item_label(item: &InvoiceLineItem, locale: &Locale) -> String {
+    if let Some(product) = &item.product {
+        return product.name.clone();
+    }
+    match (&item.description, item.shipment_method_id) {
+        (Some(name), Some(_)) => locale.message("invoice.shipping").replace("{name}", name),
+        (Some(description), None) => description.clone(),
+        (None, _) => optional_id(item.order_item_id),
+    }
+}
+
+/// Renders the description and characteristics of a product, e.g. `Cotton shirt; Color: Red, Size: M`.
+///
+/// Returns `None` if the product has neither.
+fn render_product_details(product: &LineItemProduct) -> Option<String> {
+    let characteristics = product
+        .characteristics
+        .iter()
+        .map(|characteristic| format!("{}: {}", characteristic.name, characteristic.value))
+        .collect::<Vec<String>>()
+        .join(", ");
+    let details: Vec<&str> = [product.description.as_deref(), Some(characteristics.as_str())]
+        .into_iter()
+        .flatten()
+        .filter(|detail| !detail.trim().is_empty())
+        .collect();
+    match details.is_empty() {
+        true => None,
+        false => Some(details.join("; ")),
+    }
+}
+
+/// Renders text as a single table cell, replacing line breaks and the cell delimiter `|`.
+fn table_cell(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ").replace('|', "/")
+}
+
+/// Renders the label of a discount of a line, e.g. `Discount 10%: Summer sale`.
+fn render_discount(discount: &LineItemDiscount, locale: &Locale) -> String {
+    let mut label = format!(
+        "{} {}",
+        locale.message("invoice.discount"),
+        locale.format_percentage(rounded_percentage(discount.discount))
+    );
+    if let Some(description) = &discount.description {
+        label.push_str(&format!(": {}", description));
+    }
+    label
+}
+
+/// Converts a fraction to a percentage, rounded to two decimals as fractions like `0.1` are not exact in binary.
+fn rounded_percentage(fraction: f64) -> f64 {
+    (fraction * 10_000.0).round() / 100.0
+}
+
+/// Renders an optional UUID, `-` if not set.
+fn optional_id(id: Option<Uuid>) -> String {
+    id.map_or("-".to_string(), |id| id.to_string())
+}
+
+/// Renders the name, address and VAT number of a party.
+fn render_party(party: &InvoiceParty, locale: &Locale) -> String {
+    let mut content = format!("{}: {}\n", locale.message("invoice.name"), party.name);
+    if let Some(company) = party.company.as_ref().filter(|company| **company != party.name) {
+        content.push_str(&format!("{}: {}\n", locale.message("invoice.company"), company));
+    }
+    content.push_str(&format!("{}:\n", locale.message("invoice.address")));
+    for line in &party.address_lines {
+        content.push_str(&format!("{}\n", line));
+    }
+    content.push_str(&format!("{}\n", party.country_code));
+    content.push_str(&format!(
+        "\n{}: {}",
+        locale.message("invoice.vat_number"),
+        party.vat_id.as_deref().unwrap_or("-")
+    ));
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql::model::foreign_types::ProductCharacteristic;
+
+    fn product(description: Option<&str>, characteristics: &[(&str, &str)]) -> LineItemProduct {
+        LineItemProduct {
+            name: "Shirt".to_string(),
+            sku: Some("SHIRT-1".to_string()),
+            description: description.map(str::to_string),
+            characteristics: characteristics
+                .iter()
+                .map(|(name, value)| ProductCharacteristic {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn renders_description_and_characteristics_of_ordered_version() {
+        let shirt = product(Some("Cotton shirt"), &[("Color", "Red"), ("Size", "M")]);
+        assert_eq!(
+            render_product_details(&shirt).as_deref(),
+            Some("Cotton shirt; Color: Red, Size: M")
+        );
+        let shirt = product(None, &[("Color", "Red")]);
+        assert_eq!(render_product_details(&shirt).as_deref(), Some("Color: Red"));
+    }
+
+    #[test]
+    fn omits_details_of_products_without_description_and_characteristics() {
+        assert_eq!(render_product_details(&product(None, &[])), None);
+        assert_eq!(render_product_details(&product(Some("  "), &[])), None);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::graphql::model::invoice::Invoice;
+
+/// Renders an invoice as standalone HTML document, converting its markdown content.
+pub fn render_invoice_html(invoice: &Invoice) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(&invoice.locale),
+        escape(&invoice.invoice_number),
+        markdown_to_html(&invoice.content)
+    )
+}
+
+/// Converts the markdown subset used by invoice contents (headings, rules, tables and lines) to HTML.
+fn markdown_to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    let mut table_rows: Vec<Vec<String>> = Vec::new();
+    for line in markdown.lines().map(str::trim) {
+        if line.starts_with('|') {
+            let cells: Vec<String> = line
+                .trim_matches('|')
+                .split('|')
+                .map(|cell| cell.trim().to_string())
+                .collect();
+            if !cells.iter().all(|cell| cell.chars().all(|c| c == '-')) {
+                table_rows.push(cells);
+            }
+            continue;
+        }
+        if !table_rows.is_empty() {
+            html.push_str(&table_to_html(&table_rows));
+            table_rows.clear();
+        }
+        if line.is_empty() {
+            continue;
+        } else if line == "---" {
+            html.push_str("<hr>\n");
+        } else if let Some(heading) = line.strip_prefix("### ") {
+            html.push_str(&format!("<h3>{}</h3>\n", escape(heading)));
+        } else if let Some(heading) = line.strip_prefix("# ") {
+            html.push_str(&format!("<h1>{}</h1>\n", escape(heading)));
+        } else {
+            html.push_str(&format!("<p>{}</p>\n", escape(line)));
+        }
+    }
+    if !table_rows.is_empty() {
+        html.push_str(&table_to_html(&table_rows));
+    }
+    html
+}
+
+/// Converts table rows to an HTML table, using the first row as header.
+fn table_to_html(rows: &[Vec<String>]) -> String {
+    let mut html = String::from("<table>\n");
+    for (i, row) in rows.iter().enumerate() {
+        let tag = if i == 0 { "th" } else { "td" };
+        html.push_str("<tr>");
+        for cell in row {
+            html.push_str(&format!("<{}>{}</{}>", tag, escape(cell), tag));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// Escapes text for use in HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
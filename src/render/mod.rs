@@ -0,0 +1,4 @@
+pub mod content;
+pub mod html;
+pub mod pdf;
+pub mod xml;
@@ -0,0 +1,128 @@
+use crate::graphql::model::invoice::Invoice;
+
+/// Number of text lines per PDF page.
+const LINES_PER_PAGE: usize = 60;
+
+/// Renders an invoice as PDF document, printing its content as monospaced text on A4 pages.
+pub fn render_invoice_pdf(invoice: &Invoice) -> Vec<u8> {
+    let lines: Vec<&str> = invoice.content.lines().collect();
+    let pages: Vec<&[&str]> = lines.chunks(LINES_PER_PAGE).collect();
+    let page_count = pages.len().max(1);
+
+    // Objects: 1 catalog, 2 page tree, 3 font, then a page and a content stream object per page.
+    let mut objects: Vec<Vec<u8>> = Vec::new();
+    objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    let kids: Vec<String> = (0..page_count)
+        .map(|i| format!("{} 0 R", 4 + 2 * i))
+        .collect();
+    objects.push(
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            page_count
+        )
+        .into_bytes(),
+    );
+    objects.push(
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>".to_vec(),
+    );
+    for i in 0..page_count {
+        let page_lines = pages.get(i).copied().unwrap_or(&[]);
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                5 + 2 * i
+            )
+            .into_bytes(),
+        );
+        let stream = content_stream(page_lines);
+        let mut object = format!("<< /Length {} >>\nstream\n", stream.len()).into_bytes();
+        object.extend_from_slice(&stream);
+        object.extend_from_slice(b"\nendstream");
+        objects.push(object);
+    }
+    objects.push(document_info(invoice));
+    write_document(&objects)
+}
+
+/// Builds the document information dictionary, including the invoice hash and signature as custom entries.
+fn document_info(invoice: &Invoice) -> Vec<u8> {
+    let mut info = b"<< /Producer (MiSArch invoice) /Title (".to_vec();
+    info.extend_from_slice(&encode_text(&invoice.invoice_number));
+    info.extend_from_slice(format!(") /Subject ({})", invoice._id).as_bytes());
+    if let Some(hash) = &invoice.hash {
+        info.extend_from_slice(format!(" /InvoiceHash ({})", hash).as_bytes());
+    }
+    if let Some(signature) = &invoice.signature {
+        info.extend_from_slice(
+            format!(
+                " /SignatureAlgorithm ({:?}) /SignatureKeyId (",
+                signature.algorithm
+            )
+            .as_bytes(),
+        );
+        info.extend_from_slice(&encode_text(&signature.key_id));
+        info.extend_from_slice(format!(") /Signature ({})", signature.value).as_bytes());
+    }
+    info.extend_from_slice(b" >>");
+    info
+}
+
+/// Builds the content stream printing lines of text from the top of the page.
+fn content_stream(lines: &[&str]) -> Vec<u8> {
+    let mut stream = b"BT\n/F1 9 Tf\n11 TL\n40 800 Td\n".to_vec();
+    for line in lines {
+        stream.push(b'(');
+        stream.extend_from_slice(&encode_text(line));
+        stream.extend_from_slice(b") Tj T*\n");
+    }
+    stream.extend_from_slice(b"ET");
+    stream
+}
+
+/// Serializes PDF objects with cross-reference table and trailer, using the last object as document info.
+fn write_document(objects: &[Vec<u8>]) -> Vec<u8> {
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+    let xref_offset = pdf.len();
+    pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            objects.len(),
+            xref_offset
+        )
+        .as_bytes(),
+    );
+    pdf
+}
+
+/// Encodes text as escaped PDF string in WinAnsiEncoding, replacing unsupported characters by `?`.
+fn encode_text(text: &str) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        let byte = match c {
+            '€' => 0x80,
+            '\u{a0}' => b' ',
+            '(' | ')' | '\\' => {
+                encoded.push(b'\\');
+                c as u8
+            }
+            c if (c as u32) < 0x7f && !c.is_control() => c as u8,
+            c if (0xa1..=0xff).contains(&(c as u32)) => c as u32 as u8,
+            _ => b'?',
+        };
+        encoded.push(byte);
+    }
+    encoded
+}
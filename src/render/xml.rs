@@ -0,0 +1,162 @@
+use crate::graphql::model::{invoice::Invoice, invoice_party::InvoiceParty, money::Money};
+
+/// Renders an invoice as XML document for machine processing.
+pub fn render_invoice_xml(invoice: &Invoice) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<Invoice xmlns=\"urn:misarch:invoice:1\">\n");
+    xml.push_str(&element("ID", &invoice._id.to_string(), 1));
+    xml.push_str(&element("InvoiceNumber", &invoice.invoice_number, 1));
+    if let Some(order_id) = invoice.order_id {
+        xml.push_str(&element("OrderID", &order_id.to_string(), 1));
+    }
+    if let Some(shipment_id) = invoice.shipment_id {
+        xml.push_str(&element("ShipmentID", &shipment_id.to_string(), 1));
+    }
+    xml.push_str(&element(
+        "DocumentType",
+        &format!("{:?}", invoice.document_type),
+        1,
+    ));
+    if let Some(related_invoice_number) = &invoice.related_invoice_number {
+        xml.push_str(&element("RelatedInvoiceNumber", related_invoice_number, 1));
+    }
+    if let Some(reason) = &invoice.reason {
+        xml.push_str(&element("Reason", reason, 1));
+    }
+    xml.push_str(&element(
+        "IssueDate",
+        &invoice.issued_at.to_chrono().to_rfc3339(),
+        1,
+    ));
+    if let Some(due_at) = invoice.due_at {
+        xml.push_str(&element("DueDate", &due_at.to_chrono().to_rfc3339(), 1));
+    }
+    xml.push_str(&element("Locale", &invoice.locale, 1));
+    xml.push_str(&party("Supplier", &invoice.vendor));
+    xml.push_str(&party("Customer", &invoice.customer));
+    xml.push_str("  <Lines>\n");
+    for item in &invoice.line_items {
+        xml.push_str("    <Line>\n");
+        if let Some(order_item_id) = item.order_item_id {
+            xml.push_str(&element("OrderItemID", &order_item_id.to_string(), 3));
+        }
+        if let Some(product_variant_id) = item.product_variant_id {
+            xml.push_str(&element("ProductVariantID", &product_variant_id.to_string(), 3));
+        }
+        if let Some(product_variant_version_id) = item.product_variant_version_id {
+            xml.push_str(&element(
+                "ProductVariantVersionID",
+                &product_variant_version_id.to_string(),
+                3,
+            ));
+        }
+        if let Some(product) = &item.product {
+            xml.push_str("      <Product>\n");
+            xml.push_str(&element("Name", &product.name, 4));
+            if let Some(sku) = &product.sku {
+                xml.push_str(&element("SKU", sku, 4));
+            }
+            if let Some(description) = &product.description {
+                xml.push_str(&element("Description", description, 4));
+            }
+            for characteristic in &product.characteristics {
+                xml.push_str("        <Characteristic>\n");
+                xml.push_str(&element("Name", &characteristic.name, 5));
+                xml.push_str(&element("Value", &characteristic.value, 5));
+                xml.push_str("        </Characteristic>\n");
+            }
+            xml.push_str("      </Product>\n");
+        }
+        if let Some(description) = &item.description {
+            xml.push_str(&element("Description", description, 3));
+        }
+        if let Some(shipment_method_id) = item.shipment_method_id {
+            xml.push_str(&element("ShipmentMethodID", &shipment_method_id.to_string(), 3));
+        }
+        xml.push_str(&element("Quantity", &item.count.to_string(), 3));
+        xml.push_str(&money("Amount", &item.amount, 3));
+        if let Some(undiscounted_amount) = &item.undiscounted_amount {
+            xml.push_str(&money("UndiscountedAmount", undiscounted_amount, 3));
+        }
+        for discount in &item.discounts {
+            xml.push_str("      <Discount>\n");
+            xml.push_str(&element("DiscountID", &discount.discount_id.to_string(), 4));
+            if let Some(description) = &discount.description {
+                xml.push_str(&element("Description", description, 4));
+            }
+            xml.push_str(&element("Rate", &discount.discount.to_string(), 4));
+            xml.push_str(&money("Amount", &discount.amount, 4));
+            xml.push_str("      </Discount>\n");
+        }
+        if let Some(tax_rate) = item.tax_rate {
+            xml.push_str(&element("TaxRate", &tax_rate.to_string(), 3));
+        }
+        if let Some(tax_amount) = &item.tax_amount {
+            xml.push_str(&money("TaxAmount", tax_amount, 3));
+        }
+        xml.push_str("    </Line>\n");
+    }
+    xml.push_str("  </Lines>\n");
+    xml.push_str(&money("Total", &invoice.total, 1));
+    if let Some(hash) = &invoice.hash {
+        xml.push_str(&element("Hash", hash, 1));
+    }
+    if let Some(previous_hash) = &invoice.previous_hash {
+        xml.push_str(&element("PreviousHash", previous_hash, 1));
+    }
+    if let Some(signature) = &invoice.signature {
+        xml.push_str(&format!(
+            "  <Signature algorithm=\"{:?}\" keyId=\"{}\">{}</Signature>\n",
+            signature.algorithm,
+            escape(&signature.key_id),
+            escape(&signature.value)
+        ));
+    }
+    xml.push_str("</Invoice>\n");
+    xml
+}
+
+/// Renders a party as XML element.
+fn party(name: &str, party: &InvoiceParty) -> String {
+    let mut xml = format!("  <{}>\n", name);
+    xml.push_str(&element("Name", &party.name, 2));
+    if let Some(company) = &party.company {
+        xml.push_str(&element("Company", company, 2));
+    }
+    for line in &party.address_lines {
+        xml.push_str(&element("AddressLine", line, 2));
+    }
+    xml.push_str(&element("CountryCode", &party.country_code, 2));
+    if let Some(vat_id) = &party.vat_id {
+        xml.push_str(&element("VATID", vat_id, 2));
+    }
+    xml.push_str(&format!("  </{}>\n", name));
+    xml
+}
+
+/// Renders an amount in minor units as XML element with currency attributes.
+fn money(name: &str, money: &Money, depth: usize) -> String {
+    format!(
+        "{}<{} currency=\"{}\" minorUnits=\"{}\">{}</{}>\n",
+        "  ".repeat(depth),
+        name,
+        escape(&money.currency),
+        money.minor_unit_digits(),
+        money.amount,
+        name
+    )
+}
+
+/// Renders an indented XML element with escaped text content.
+fn element(name: &str, text: &str, depth: usize) -> String {
+    format!("{}<{}>{}</{}>\n", "  ".repeat(depth), name, escape(text), name)
+}
+
+/// Escapes text for use in XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
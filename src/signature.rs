@@ -0,0 +1,294 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use async_graphql::{Enum, SimpleObject};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bson::DateTime;
+use ed25519_dalek::pkcs8::{
+    spki::der::pem::LineEnding, DecodePrivateKey, DecodePublicKey, EncodePublicKey,
+};
+use log::info;
+use rsa::{
+    pkcs1v15,
+    signature::{Keypair, SignatureEncoding, Signer, Verifier},
+    RsaPrivateKey, RsaPublicKey,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::env_var;
+
+/// Algorithm of an invoice signature.
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureAlgorithm {
+    /// Ed25519 signature.
+    Ed25519,
+    /// RSASSA-PKCS1-v1_5 signature with SHA-256.
+    RsaPkcs1Sha256,
+}
+
+/// Signature of an issued invoice over its chain hash.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, Clone)]
+pub struct InvoiceSignature {
+    /// Algorithm of the signature.
+    pub algorithm: SignatureAlgorithm,
+    /// Identifier of the key the invoice was signed with.
+    pub key_id: String,
+    /// Base64 encoded signature.
+    pub value: String,
+    /// Timestamp of signing.
+    pub signed_at: DateTime,
+}
+
+/// Private key used to sign invoices.
+enum SigningKey {
+    Ed25519(ed25519_dalek::SigningKey),
+    Rsa(pkcs1v15::SigningKey<Sha256>),
+}
+
+/// Public key used to verify invoice signatures.
+enum VerifyingKey {
+    Ed25519(ed25519_dalek::VerifyingKey),
+    Rsa(pkcs1v15::VerifyingKey<Sha256>),
+}
+
+impl VerifyingKey {
+    /// Parses a PEM encoded Ed25519 or RSA public key, `None` if it is neither.
+    fn from_public_key_pem(pem: &str) -> Option<Self> {
+        match ed25519_dalek::VerifyingKey::from_public_key_pem(pem) {
+            Ok(key) => Some(VerifyingKey::Ed25519(key)),
+            Err(_) => RsaPublicKey::from_public_key_pem(pem)
+                .ok()
+                .map(|key| VerifyingKey::Rsa(pkcs1v15::VerifyingKey::new(key))),
+        }
+    }
+
+    /// Verifies a signature of an invoice hash.
+    fn verify(&self, hash: &str, signature: &[u8]) -> bool {
+        match self {
+            VerifyingKey::Ed25519(key) => ed25519_dalek::Signature::from_slice(signature)
+                .is_ok_and(|signature| key.verify(hash.as_bytes(), &signature).is_ok()),
+            VerifyingKey::Rsa(key) => pkcs1v15::Signature::try_from(signature)
+                .is_ok_and(|signature| key.verify(hash.as_bytes(), &signature).is_ok()),
+        }
+    }
+
+    /// PEM encoded public key.
+    fn public_key_pem(&self) -> String {
+        let pem = match self {
+            VerifyingKey::Ed25519(key) => key.to_public_key_pem(LineEnding::LF),
+            VerifyingKey::Rsa(key) => key.to_public_key_pem(LineEnding::LF),
+        };
+        pem.expect("Public key is always PEM encodable.")
+    }
+}
+
+/// Signs issued invoices with the key configured by `$INVOICE_SIGNING_KEY_PATH` and verifies their signatures.
+///
+/// Signatures are verified with the public key of the key id they were made with, so invoices signed before a key rotation stay verifiable.
+pub struct InvoiceSigner {
+    key_id: String,
+    key: SigningKey,
+    /// Public keys of the current and all previous signing keys by key id.
+    verifying_keys: HashMap<String, VerifyingKey>,
+}
+
+impl InvoiceSigner {
+    /// Loads the PKCS#8 PEM encoded Ed25519 or RSA private key from `$INVOICE_SIGNING_KEY_PATH`.
+    ///
+    /// The key id is `$INVOICE_SIGNING_KEY_ID`, or derived from the public key if not set.
+    /// Public keys of previous signing keys are loaded from the files `<key id>.pem` in `$INVOICE_VERIFICATION_KEYS_DIR`.
+    /// Returns `None` if no key is configured, panics if a key cannot be read.
+    pub fn from_env() -> Option<Self> {
+        let path: String = env_var("INVOICE_SIGNING_KEY_PATH")?;
+        let pem = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Signing key `{}` could not be read: {}", path, e));
+        let key = match ed25519_dalek::SigningKey::from_pkcs8_pem(&pem) {
+            Ok(key) => SigningKey::Ed25519(key),
+            Err(_) => match RsaPrivateKey::from_pkcs8_pem(&pem) {
+                Ok(key) => SigningKey::Rsa(pkcs1v15::SigningKey::new(key)),
+                Err(_) => panic!(
+                    "Signing key `{}` is neither a PKCS#8 Ed25519 nor RSA private key.",
+                    path
+                ),
+            },
+        };
+        let mut signer = Self {
+            key_id: String::new(),
+            key,
+            verifying_keys: HashMap::new(),
+        };
+        signer.key_id = env_var("INVOICE_SIGNING_KEY_ID").unwrap_or(signer.fingerprint());
+        if let Some(dir) = env_var::<String>("INVOICE_VERIFICATION_KEYS_DIR") {
+            signer.verifying_keys = load_verifying_keys(Path::new(&dir));
+        }
+        let own_key = VerifyingKey::from_public_key_pem(&signer.public_key_pem())
+            .expect("Public key of the signing key is always parsable.");
+        signer.verifying_keys.insert(signer.key_id.clone(), own_key);
+        Some(signer)
+    }
+
+    /// Identifier of the signing key.
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Algorithm of the signing key.
+    pub fn algorithm(&self) -> SignatureAlgorithm {
+        match self.key {
+            SigningKey::Ed25519(_) => SignatureAlgorithm::Ed25519,
+            SigningKey::Rsa(_) => SignatureAlgorithm::RsaPkcs1Sha256,
+        }
+    }
+
+    /// Signs an invoice hash.
+    pub fn sign(&self, hash: &str) -> InvoiceSignature {
+        let signature = match &self.key {
+            SigningKey::Ed25519(key) => key.sign(hash.as_bytes()).to_vec(),
+            SigningKey::Rsa(key) => key.sign(hash.as_bytes()).to_vec(),
+        };
+        InvoiceSignature {
+            algorithm: self.algorithm(),
+            key_id: self.key_id.clone(),
+            value: STANDARD.encode(signature),
+            signed_at: DateTime::now(),
+        }
+    }
+
+    /// Verifies a base64 encoded signature of an invoice hash made with the key of a key id, `false` for unknown key ids.
+    pub fn verify(&self, key_id: &str, hash: &str, signature: &str) -> bool {
+        let Ok(signature) = STANDARD.decode(signature) else {
+            return false;
+        };
+        self.verifying_keys
+            .get(key_id)
+            .is_some_and(|key| key.verify(hash, &signature))
+    }
+
+    /// Whether signatures of a key id can be verified.
+    pub fn knows_key(&self, key_id: &str) -> bool {
+        self.verifying_keys.contains_key(key_id)
+    }
+
+    /// PEM encoded public key of a key id, which partners use to verify signatures themselves, `None` for unknown key ids.
+    pub fn verifying_key_pem(&self, key_id: &str) -> Option<String> {
+        self.verifying_keys
+            .get(key_id)
+            .map(VerifyingKey::public_key_pem)
+    }
+
+    /// PEM encoded public key of the current signing key.
+    fn public_key_pem(&self) -> String {
+        let pem = match &self.key {
+            SigningKey::Ed25519(key) => key.verifying_key().to_public_key_pem(LineEnding::LF),
+            SigningKey::Rsa(key) => key.verifying_key().to_public_key_pem(LineEnding::LF),
+        };
+        pem.expect("Public key is always PEM encodable.")
+    }
+
+    /// Derives a key id from the first 16 hex digits of the SHA-256 hash of the public key.
+    fn fingerprint(&self) -> String {
+        Sha256::digest(self.public_key_pem().as_bytes())
+            .iter()
+            .take(8)
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Loads the PEM encoded public keys of a directory, named `<key id>.pem`, by key id.
+///
+/// Panics if a key cannot be read or parsed, as signatures of its key id would silently fail to verify.
+fn load_verifying_keys(dir: &Path) -> HashMap<String, VerifyingKey> {
+    let unreadable = |e: std::io::Error| -> ! {
+        panic!(
+            "Verification keys `{}` could not be read: {}",
+            dir.display(),
+            e
+        )
+    };
+    let entries = fs::read_dir(dir).unwrap_or_else(|e| unreadable(e));
+    let mut keys = HashMap::new();
+    for entry in entries {
+        let path = entry.unwrap_or_else(|e| unreadable(e)).path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some("pem") {
+            continue;
+        }
+        let Some(key_id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let pem = fs::read_to_string(&path).unwrap_or_else(|e| unreadable(e));
+        let key = VerifyingKey::from_public_key_pem(&pem).unwrap_or_else(|| {
+            panic!(
+                "Verification key `{}` is neither an Ed25519 nor RSA public key.",
+                path.display()
+            )
+        });
+        info!("Loaded verification key `{}`.", key_id);
+        keys.insert(key_id.to_string(), key);
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ed25519 signer with a key derived from a seed, knowing the public keys of previous signers.
+    fn signer(seed: u8, key_id: &str, previous: &[&InvoiceSigner]) -> InvoiceSigner {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[seed; 32]);
+        let mut verifying_keys = HashMap::new();
+        for signer in previous {
+            let key = VerifyingKey::from_public_key_pem(&signer.public_key_pem()).unwrap();
+            verifying_keys.insert(signer.key_id.clone(), key);
+        }
+        verifying_keys.insert(
+            key_id.to_string(),
+            VerifyingKey::Ed25519(key.verifying_key()),
+        );
+        InvoiceSigner {
+            key_id: key_id.to_string(),
+            key: SigningKey::Ed25519(key),
+            verifying_keys,
+        }
+    }
+
+    #[test]
+    fn verifies_signatures_of_previous_keys_after_rotation() {
+        let previous = signer(1, "2025", &[]);
+        let signature = previous.sign("hash");
+        let current = signer(2, "2026", &[&previous]);
+        assert_eq!(signature.key_id, "2025");
+        assert!(current.knows_key("2025"));
+        assert!(current.verify(&signature.key_id, "hash", &signature.value));
+        let signature = current.sign("hash");
+        assert_eq!(signature.key_id, "2026");
+        assert!(current.verify(&signature.key_id, "hash", &signature.value));
+    }
+
+    #[test]
+    fn rejects_signatures_verified_with_another_key() {
+        let previous = signer(1, "2025", &[]);
+        let current = signer(2, "2026", &[&previous]);
+        let signature = previous.sign("hash");
+        assert!(!current.verify("2026", "hash", &signature.value));
+        assert!(!current.verify("2025", "other hash", &signature.value));
+        assert!(!current.verify("2024", "hash", &signature.value));
+        assert!(!current.verify("2025", "hash", "not base64"));
+        assert!(current.verifying_key_pem("2024").is_none());
+    }
+
+    #[test]
+    fn loads_verifying_keys_by_key_id() {
+        let previous = signer(1, "2025", &[]);
+        let dir = std::env::temp_dir().join(format!("invoice-keys-{}", uuid::Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("2025.pem"), previous.public_key_pem()).unwrap();
+        fs::write(dir.join("README.txt"), "not a key").unwrap();
+        let keys = load_verifying_keys(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(keys.len(), 1);
+        let signature = previous.sign("hash");
+        let signature = STANDARD.decode(signature.value).unwrap();
+        assert!(keys["2025"].verify("hash", &signature));
+    }
+}